@@ -1,12 +1,411 @@
-#[derive(Debug, Copy, Clone)]
+use std::str::FromStr;
+
+use crate::errors::{ChipsError, Result};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct Color(u8, u8, u8);
 
 impl Color {
-    pub fn new(r: u8, g: u8, b: u8) -> Self {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
         Self(r, g, b)
     }
 
-    pub fn as_serial(&self) -> u16 {
-        ((self.0 as i32) << 8 & 63488 | (self.1 as i32) << 3 & 2016 | (self.2 as i32) >> 3) as u16
+    pub fn r(&self) -> u8 {
+        self.0
+    }
+
+    pub fn g(&self) -> u8 {
+        self.1
+    }
+
+    pub fn b(&self) -> u8 {
+        self.2
+    }
+
+    /// Unpacks an RGB565 value, scaling each channel back up to the full 8-bit range.
+    pub fn from_rgb565(value: u16) -> Self {
+        let r = (value >> 11) as u8;
+        let g = ((value >> 5) & 63) as u8;
+        let b = (value & 31) as u8;
+        Self(r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2)
+    }
+
+    /// Packs the color into the device's RGB565 pixel format.
+    pub fn to_rgb565(self) -> u16 {
+        pack_rgb565(self.0, self.1, self.2)
+    }
+
+    /// Parses `#rgb` or `#rrggbb`, with or without the leading `#`.
+    pub fn from_hex(hex: &str) -> Result<Self> {
+        let digits = hex.strip_prefix('#').unwrap_or(hex);
+        let invalid = || ChipsError::InvalidColor(hex.to_string());
+        // from_str_radix would also take a sign, like the `+f` in `#+f+f+f`
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+
+        let channel = |s: &str| u8::from_str_radix(s, 16).map_err(|_| invalid());
+        match digits.len() {
+            3 => {
+                let r = channel(&digits[0..1])?;
+                let g = channel(&digits[1..2])?;
+                let b = channel(&digits[2..3])?;
+                Ok(Self(r * 17, g * 17, b * 17))
+            }
+            6 => Ok(Self(
+                channel(&digits[0..2])?,
+                channel(&digits[2..4])?,
+                channel(&digits[4..6])?,
+            )),
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Looks up a CSS named color, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        CSS_COLORS
+            .iter()
+            .find(|(css_name, _)| *css_name == name)
+            .map(|(_, rgb)| Self((rgb >> 16) as u8, (rgb >> 8) as u8, *rgb as u8))
+    }
+
+    /// Builds a color from hue in degrees and saturation/value in `0.0..=1.0`.
+    pub fn from_hsv(h: f32, s: f32, v: f32) -> Self {
+        let h = h.rem_euclid(360.0) / 60.0;
+        let s = s.clamp(0.0, 1.0);
+        let v = v.clamp(0.0, 1.0);
+
+        let c = v * s;
+        let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+        let (r, g, b) = match h as u32 {
+            0 => (c, x, 0.0),
+            1 => (x, c, 0.0),
+            2 => (0.0, c, x),
+            3 => (0.0, x, c),
+            4 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+
+        let m = v - c;
+        let to_u8 = |channel: f32| ((channel + m) * 255.0).round() as u8;
+        Self(to_u8(r), to_u8(g), to_u8(b))
+    }
+
+    /// Linearly interpolates towards `other`, where `t = 0.0` is `self` and `t = 1.0` is `other`.
+    pub fn lerp(&self, other: Color, t: f32) -> Self {
+        let t = t.clamp(0.0, 1.0);
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+        Self(
+            mix(self.0, other.0),
+            mix(self.1, other.1),
+            mix(self.2, other.2),
+        )
+    }
+
+    /// Composites this color over `background` with the given coverage (255 is opaque).
+    pub fn blend(&self, background: Color, alpha: u8) -> Self {
+        let alpha = alpha as u16;
+        let mix =
+            |fg: u8, bg: u8| ((fg as u16 * alpha + bg as u16 * (255 - alpha) + 127) / 255) as u8;
+        Self(
+            mix(self.0, background.0),
+            mix(self.1, background.1),
+            mix(self.2, background.2),
+        )
+    }
+}
+
+impl FromStr for Color {
+    type Err = ChipsError;
+
+    /// Accepts hex notation (`#rrggbb`, `#rgb`), `hsv(h, s, v)` with the hue in degrees and
+    /// saturation and value from 0 to 1, or a CSS color name.
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s.starts_with('#') {
+            return Self::from_hex(s);
+        }
+
+        if let Some(args) = s.strip_prefix("hsv(").and_then(|s| s.strip_suffix(')')) {
+            let invalid = || ChipsError::InvalidColor(s.to_string());
+            let args = args
+                .split(',')
+                .map(|arg| arg.trim().parse::<f32>().map_err(|_| invalid()))
+                .collect::<Result<Vec<f32>>>()?;
+            return match args[..] {
+                [h, s, v]
+                    if h.is_finite() && (0.0..=1.0).contains(&s) && (0.0..=1.0).contains(&v) =>
+                {
+                    Ok(Self::from_hsv(h, s, v))
+                }
+                _ => Err(invalid()),
+            };
+        }
+
+        match Self::from_name(s) {
+            Some(color) => Ok(color),
+            None => Self::from_hex(s),
+        }
+    }
+}
+
+/// Packs 8-bit channels into RGB565 by keeping the high bits of each. Every
+/// conversion to the device's pixel format should go through this.
+pub fn pack_rgb565(r: u8, g: u8, b: u8) -> u16 {
+    ((r as u16 >> 3) << 11) | ((g as u16 >> 2) << 5) | (b as u16 >> 3)
+}
+
+const CSS_COLORS: [(&str, u32); 148] = [
+    ("aliceblue", 0xF0F8FF),
+    ("antiquewhite", 0xFAEBD7),
+    ("aqua", 0x00FFFF),
+    ("aquamarine", 0x7FFFD4),
+    ("azure", 0xF0FFFF),
+    ("beige", 0xF5F5DC),
+    ("bisque", 0xFFE4C4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xFFEBCD),
+    ("blue", 0x0000FF),
+    ("blueviolet", 0x8A2BE2),
+    ("brown", 0xA52A2A),
+    ("burlywood", 0xDEB887),
+    ("cadetblue", 0x5F9EA0),
+    ("chartreuse", 0x7FFF00),
+    ("chocolate", 0xD2691E),
+    ("coral", 0xFF7F50),
+    ("cornflowerblue", 0x6495ED),
+    ("cornsilk", 0xFFF8DC),
+    ("crimson", 0xDC143C),
+    ("cyan", 0x00FFFF),
+    ("darkblue", 0x00008B),
+    ("darkcyan", 0x008B8B),
+    ("darkgoldenrod", 0xB8860B),
+    ("darkgray", 0xA9A9A9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xA9A9A9),
+    ("darkkhaki", 0xBDB76B),
+    ("darkmagenta", 0x8B008B),
+    ("darkolivegreen", 0x556B2F),
+    ("darkorange", 0xFF8C00),
+    ("darkorchid", 0x9932CC),
+    ("darkred", 0x8B0000),
+    ("darksalmon", 0xE9967A),
+    ("darkseagreen", 0x8FBC8F),
+    ("darkslateblue", 0x483D8B),
+    ("darkslategray", 0x2F4F4F),
+    ("darkslategrey", 0x2F4F4F),
+    ("darkturquoise", 0x00CED1),
+    ("darkviolet", 0x9400D3),
+    ("deeppink", 0xFF1493),
+    ("deepskyblue", 0x00BFFF),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1E90FF),
+    ("firebrick", 0xB22222),
+    ("floralwhite", 0xFFFAF0),
+    ("forestgreen", 0x228B22),
+    ("fuchsia", 0xFF00FF),
+    ("gainsboro", 0xDCDCDC),
+    ("ghostwhite", 0xF8F8FF),
+    ("gold", 0xFFD700),
+    ("goldenrod", 0xDAA520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xADFF2F),
+    ("grey", 0x808080),
+    ("honeydew", 0xF0FFF0),
+    ("hotpink", 0xFF69B4),
+    ("indianred", 0xCD5C5C),
+    ("indigo", 0x4B0082),
+    ("ivory", 0xFFFFF0),
+    ("khaki", 0xF0E68C),
+    ("lavender", 0xE6E6FA),
+    ("lavenderblush", 0xFFF0F5),
+    ("lawngreen", 0x7CFC00),
+    ("lemonchiffon", 0xFFFACD),
+    ("lightblue", 0xADD8E6),
+    ("lightcoral", 0xF08080),
+    ("lightcyan", 0xE0FFFF),
+    ("lightgoldenrodyellow", 0xFAFAD2),
+    ("lightgray", 0xD3D3D3),
+    ("lightgreen", 0x90EE90),
+    ("lightgrey", 0xD3D3D3),
+    ("lightpink", 0xFFB6C1),
+    ("lightsalmon", 0xFFA07A),
+    ("lightseagreen", 0x20B2AA),
+    ("lightskyblue", 0x87CEFA),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xB0C4DE),
+    ("lightyellow", 0xFFFFE0),
+    ("lime", 0x00FF00),
+    ("limegreen", 0x32CD32),
+    ("linen", 0xFAF0E6),
+    ("magenta", 0xFF00FF),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66CDAA),
+    ("mediumblue", 0x0000CD),
+    ("mediumorchid", 0xBA55D3),
+    ("mediumpurple", 0x9370DB),
+    ("mediumseagreen", 0x3CB371),
+    ("mediumslateblue", 0x7B68EE),
+    ("mediumspringgreen", 0x00FA9A),
+    ("mediumturquoise", 0x48D1CC),
+    ("mediumvioletred", 0xC71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xF5FFFA),
+    ("mistyrose", 0xFFE4E1),
+    ("moccasin", 0xFFE4B5),
+    ("navajowhite", 0xFFDEAD),
+    ("navy", 0x000080),
+    ("oldlace", 0xFDF5E6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6B8E23),
+    ("orange", 0xFFA500),
+    ("orangered", 0xFF4500),
+    ("orchid", 0xDA70D6),
+    ("palegoldenrod", 0xEEE8AA),
+    ("palegreen", 0x98FB98),
+    ("paleturquoise", 0xAFEEEE),
+    ("palevioletred", 0xDB7093),
+    ("papayawhip", 0xFFEFD5),
+    ("peachpuff", 0xFFDAB9),
+    ("peru", 0xCD853F),
+    ("pink", 0xFFC0CB),
+    ("plum", 0xDDA0DD),
+    ("powderblue", 0xB0E0E6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xFF0000),
+    ("rosybrown", 0xBC8F8F),
+    ("royalblue", 0x4169E1),
+    ("saddlebrown", 0x8B4513),
+    ("salmon", 0xFA8072),
+    ("sandybrown", 0xF4A460),
+    ("seagreen", 0x2E8B57),
+    ("seashell", 0xFFF5EE),
+    ("sienna", 0xA0522D),
+    ("silver", 0xC0C0C0),
+    ("skyblue", 0x87CEEB),
+    ("slateblue", 0x6A5ACD),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xFFFAFA),
+    ("springgreen", 0x00FF7F),
+    ("steelblue", 0x4682B4),
+    ("tan", 0xD2B48C),
+    ("teal", 0x008080),
+    ("thistle", 0xD8BFD8),
+    ("tomato", 0xFF6347),
+    ("turquoise", 0x40E0D0),
+    ("violet", 0xEE82EE),
+    ("wheat", 0xF5DEB3),
+    ("white", 0xFFFFFF),
+    ("whitesmoke", 0xF5F5F5),
+    ("yellow", 0xFFFF00),
+    ("yellowgreen", 0x9ACD32),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rgb565_round_trips() {
+        for value in 0..=u16::MAX {
+            assert_eq!(Color::from_rgb565(value).to_rgb565(), value);
+        }
+    }
+
+    #[test]
+    fn rgb565_matches_legacy_packing() {
+        // The formula `as_serial` used before packing was shared
+        let legacy = |r: u8, g: u8, b: u8| {
+            ((r as i32) << 8 & 63488 | (g as i32) << 3 & 2016 | (b as i32) >> 3) as u16
+        };
+        for r in (0..=255).step_by(7) {
+            for g in (0..=255).step_by(5) {
+                for b in (0..=255).step_by(3) {
+                    assert_eq!(Color::new(r, g, b).to_rgb565(), legacy(r, g, b));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rgb565_extremes_expand_to_full_range() {
+        assert_eq!(Color::from_rgb565(0xFFFF), Color::new(255, 255, 255));
+        assert_eq!(Color::from_rgb565(0x0000), Color::new(0, 0, 0));
+        assert_eq!(Color::from_rgb565(0xF800), Color::new(255, 0, 0));
+    }
+
+    #[test]
+    fn parses_hex() {
+        assert_eq!(Color::from_hex("#3f4351").unwrap(), Color::new(63, 67, 81));
+        assert_eq!(
+            Color::from_hex("E4CF9A").unwrap(),
+            Color::new(228, 207, 154)
+        );
+        assert_eq!(Color::from_hex("#f80").unwrap(), Color::new(255, 136, 0));
+        assert!(Color::from_hex("#12345").is_err());
+        assert!(Color::from_hex("#gggggg").is_err());
+        assert!(Color::from_hex("#ééé").is_err());
+        assert!(Color::from_hex("#+f+f+f").is_err());
+        assert!(Color::from_hex("+ff").is_err());
+        assert!(Color::from_hex("# ff").is_err());
+    }
+
+    #[test]
+    fn parses_names_and_hex_from_str() {
+        assert_eq!("Orange".parse::<Color>().unwrap(), Color::new(255, 165, 0));
+        assert_eq!(
+            " rebeccapurple ".parse::<Color>().unwrap(),
+            Color::new(102, 51, 153)
+        );
+        assert_eq!("#000".parse::<Color>().unwrap(), Color::new(0, 0, 0));
+        assert_eq!("fff".parse::<Color>().unwrap(), Color::new(255, 255, 255));
+        assert!("notacolor".parse::<Color>().is_err());
+    }
+
+    #[test]
+    fn converts_from_hsv() {
+        assert_eq!(Color::from_hsv(0.0, 1.0, 1.0), Color::new(255, 0, 0));
+        assert_eq!(Color::from_hsv(120.0, 1.0, 1.0), Color::new(0, 255, 0));
+        assert_eq!(Color::from_hsv(-120.0, 1.0, 1.0), Color::new(0, 0, 255));
+        assert_eq!(Color::from_hsv(39.0, 1.0, 1.0), Color::new(255, 166, 0));
+        assert_eq!(Color::from_hsv(200.0, 0.0, 0.5), Color::new(128, 128, 128));
+
+        assert_eq!(
+            "hsv(120, 1, 0.5)".parse::<Color>().unwrap(),
+            Color::new(0, 128, 0)
+        );
+        for invalid in [
+            "hsv(120, 1)",
+            "hsv(120, 1.5, 1)",
+            "hsv(a, 1, 1)",
+            "hsv(0, 1, 1",
+        ] {
+            assert!(invalid.parse::<Color>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn lerp_hits_endpoints_and_midpoint() {
+        let a = Color::new(0, 100, 200);
+        let b = Color::new(200, 100, 0);
+        assert_eq!(a.lerp(b, 0.0), a);
+        assert_eq!(a.lerp(b, 1.0), b);
+        assert_eq!(a.lerp(b, 0.5), Color::new(100, 100, 100));
+        assert_eq!(a.lerp(b, 2.0), b);
+    }
+
+    #[test]
+    fn blend_respects_alpha() {
+        let fg = Color::new(255, 255, 255);
+        let bg = Color::new(0, 0, 0);
+        assert_eq!(fg.blend(bg, 255), fg);
+        assert_eq!(fg.blend(bg, 0), bg);
+        assert_eq!(fg.blend(bg, 128), Color::new(128, 128, 128));
     }
 }
@@ -0,0 +1,260 @@
+use std::time::{Duration, Instant};
+
+use crate::color::Color;
+use crate::metrics::MetricSnapshot;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Levels trigger as the value rises to their threshold, e.g. for temperatures.
+    Above,
+    /// Levels trigger as the value drops to their threshold, e.g. for free disk space.
+    Below,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ColorLevel {
+    threshold: f64,
+    color: Color,
+    /// Alternates between the level's color and the normal color, switching every interval.
+    blink: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ActiveLevel {
+    index: usize,
+    since: Instant,
+}
+
+/// Picks a widget color from the value of a metric, e.g. turning a GPU temperature red above
+/// 80°C. A level stays active until the value moves back past its threshold by more than the
+/// hysteresis, so values hovering around a threshold don't make the color flicker.
+#[derive(Debug, Clone)]
+pub struct ColorRule {
+    metric: String,
+    direction: Direction,
+    normal: Color,
+    levels: Vec<ColorLevel>,
+    hysteresis: f64,
+    active: Option<ActiveLevel>,
+}
+
+impl ColorRule {
+    pub fn new(metric: impl Into<String>, direction: Direction, normal: Color) -> Self {
+        Self {
+            metric: metric.into(),
+            direction,
+            normal,
+            levels: vec![],
+            hysteresis: 0.0,
+            active: None,
+        }
+    }
+
+    /// Adds a level that applies `color` once the value reaches `threshold`. Levels must be
+    /// added in the order they trigger, e.g. warning before critical.
+    pub fn level(mut self, threshold: f64, color: Color) -> Self {
+        self.levels.push(ColorLevel {
+            threshold,
+            color,
+            blink: None,
+        });
+        self
+    }
+
+    /// Makes the most recently added level blink, toggling every `interval`.
+    pub fn blinking(mut self, interval: Duration) -> Self {
+        if let Some(level) = self.levels.last_mut() {
+            level.blink = Some(interval);
+        }
+        self
+    }
+
+    pub fn hysteresis(mut self, hysteresis: f64) -> Self {
+        self.hysteresis = hysteresis.abs();
+        self
+    }
+
+    /// Updates the active level from the snapshot and returns the color to show at `now`. A
+    /// missing or non-numeric metric keeps the current level, since a provider skipping a sample
+    /// shouldn't make an alert flicker off.
+    pub fn evaluate(&mut self, snapshot: &MetricSnapshot, now: Instant) -> Color {
+        if let Some(value) = snapshot
+            .get(&self.metric)
+            .and_then(|value| value.as_number())
+        {
+            self.active = self.next_level(value, now);
+        }
+
+        let Some(active) = self.active else {
+            return self.normal;
+        };
+
+        let level = self.levels[active.index];
+        match level.blink {
+            Some(interval) if !interval.is_zero() => {
+                let phase = now.duration_since(active.since).as_nanos() / interval.as_nanos();
+                if phase.is_multiple_of(2) {
+                    level.color
+                } else {
+                    self.normal
+                }
+            }
+            _ => level.color,
+        }
+    }
+
+    /// When the color next switches on its own after `now`, which only happens while a blinking
+    /// level is active.
+    pub fn next_toggle(&self, now: Instant) -> Option<Instant> {
+        let active = self.active?;
+        let interval = self.levels[active.index]
+            .blink
+            .filter(|interval| !interval.is_zero())?
+            .as_nanos();
+        let elapsed = now.saturating_duration_since(active.since).as_nanos();
+        let next = (elapsed / interval + 1) * interval;
+        active
+            .since
+            .checked_add(Duration::from_nanos(next.try_into().ok()?))
+    }
+
+    fn next_level(&self, value: f64, now: Instant) -> Option<ActiveLevel> {
+        // Work in terms of "above" so both directions share the same comparisons
+        let sign = match self.direction {
+            Direction::Above => 1.0,
+            Direction::Below => -1.0,
+        };
+        let reached =
+            |level: &ColorLevel, margin: f64| value * sign >= level.threshold * sign - margin;
+
+        let triggered = self.levels.iter().rposition(|level| reached(level, 0.0));
+        let current = self.active.map(|active| active.index);
+
+        // Levels at or below the current one only release once the value passes the hysteresis
+        let held = current.and_then(|current| {
+            self.levels[..=current]
+                .iter()
+                .rposition(|level| reached(level, self.hysteresis))
+        });
+
+        let index = match (triggered, held) {
+            (Some(triggered), Some(held)) => triggered.max(held),
+            (triggered, held) => triggered.or(held)?,
+        };
+
+        match self.active {
+            Some(active) if active.index == index => Some(active),
+            _ => Some(ActiveLevel { index, since: now }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NORMAL: Color = Color::new(228, 207, 154);
+    const WARNING: Color = Color::new(230, 150, 60);
+    const CRITICAL: Color = Color::new(220, 70, 60);
+
+    fn snapshot(value: f64) -> MetricSnapshot {
+        let mut snapshot = MetricSnapshot::new();
+        snapshot.set("gpu.temperature", value);
+        snapshot
+    }
+
+    fn temperature_rule() -> ColorRule {
+        ColorRule::new("gpu.temperature", Direction::Above, NORMAL)
+            .level(80.0, WARNING)
+            .level(90.0, CRITICAL)
+            .hysteresis(5.0)
+    }
+
+    #[test]
+    fn holds_levels_within_hysteresis() {
+        let mut rule = temperature_rule();
+        let now = Instant::now();
+        let color = |rule: &mut ColorRule, value: f64| rule.evaluate(&snapshot(value), now);
+
+        assert_eq!(color(&mut rule, 79.0), NORMAL);
+        assert_eq!(color(&mut rule, 80.0), WARNING);
+        assert_eq!(color(&mut rule, 92.0), CRITICAL);
+
+        // Dropping below a threshold by less than the hysteresis keeps the level
+        assert_eq!(color(&mut rule, 86.0), CRITICAL);
+        assert_eq!(color(&mut rule, 84.9), WARNING);
+        assert_eq!(color(&mut rule, 76.0), WARNING);
+        assert_eq!(color(&mut rule, 74.9), NORMAL);
+
+        // Rising again needs the full threshold
+        assert_eq!(color(&mut rule, 79.9), NORMAL);
+    }
+
+    #[test]
+    fn triggers_below_thresholds() {
+        let gib = 1024.0 * 1024.0 * 1024.0;
+        let mut rule = ColorRule::new("mem.available", Direction::Below, NORMAL)
+            .level(2.0 * gib, WARNING)
+            .hysteresis(0.25 * gib);
+        let now = Instant::now();
+        let mut color = |value: f64| {
+            let mut snapshot = MetricSnapshot::new();
+            snapshot.set("mem.available", value);
+            rule.evaluate(&snapshot, now)
+        };
+
+        assert_eq!(color(3.0 * gib), NORMAL);
+        assert_eq!(color(2.0 * gib), WARNING);
+        assert_eq!(color(2.2 * gib), WARNING);
+        assert_eq!(color(2.3 * gib), NORMAL);
+    }
+
+    #[test]
+    fn keeps_level_while_metric_is_missing() {
+        let mut rule = temperature_rule();
+        let now = Instant::now();
+        assert_eq!(rule.evaluate(&snapshot(95.0), now), CRITICAL);
+        assert_eq!(rule.evaluate(&MetricSnapshot::new(), now), CRITICAL);
+
+        let mut text = MetricSnapshot::new();
+        text.set("gpu.temperature", "n/a");
+        assert_eq!(rule.evaluate(&text, now), CRITICAL);
+        assert_eq!(rule.evaluate(&snapshot(50.0), now), NORMAL);
+    }
+
+    #[test]
+    fn blinks_from_when_level_started() {
+        let second = Duration::from_secs(1);
+        let mut rule = temperature_rule().blinking(second);
+        let start = Instant::now();
+
+        assert_eq!(rule.evaluate(&snapshot(95.0), start), CRITICAL);
+        assert_eq!(rule.evaluate(&snapshot(95.0), start + second / 2), CRITICAL);
+        assert_eq!(rule.evaluate(&snapshot(95.0), start + second), NORMAL);
+        assert_eq!(rule.evaluate(&snapshot(95.0), start + second * 2), CRITICAL);
+
+        // Only the last level added blinks, and coming back to it restarts the phase
+        let later = start + second * 3;
+        assert_eq!(rule.evaluate(&snapshot(84.0), later), WARNING);
+        assert_eq!(rule.evaluate(&snapshot(84.0), later + second), WARNING);
+        assert_eq!(rule.evaluate(&snapshot(95.0), later + second), CRITICAL);
+    }
+
+    #[test]
+    fn reports_next_toggle() {
+        let second = Duration::from_secs(1);
+        let mut rule = temperature_rule().blinking(second);
+        let start = Instant::now();
+        assert_eq!(rule.evaluate(&snapshot(50.0), start), NORMAL);
+        assert_eq!(rule.next_toggle(start), None);
+
+        rule.evaluate(&snapshot(95.0), start);
+        assert_eq!(rule.next_toggle(start), Some(start + second));
+        assert_eq!(rule.next_toggle(start + second / 2), Some(start + second));
+        assert_eq!(rule.next_toggle(start + second), Some(start + second * 2));
+
+        // Levels that don't blink only change with the metric
+        rule.evaluate(&snapshot(84.0), start + second);
+        assert_eq!(rule.next_toggle(start + second), None);
+    }
+}
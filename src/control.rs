@@ -0,0 +1,499 @@
+use std::io::Cursor;
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crossbeam::channel::Sender;
+use image::{DynamicImage, ImageReader, Limits};
+
+use crate::device::Rect;
+use crate::errors::{error_message, ChipsError, Result};
+use crate::http::{read_request, write_response, DeadlineReader, Request};
+use crate::image_fit::FitMode;
+use crate::metrics::MetricValue;
+use crate::sources::parse_value;
+
+/// Where the control API listens unless `CHIPS_CONTROL_ADDR` says otherwise. Only local tools
+/// can reach it.
+pub const DEFAULT_CONTROL_ADDR: &str = "127.0.0.1:7878";
+
+/// Clients that take longer than this to send a whole request are dropped. Each client has its
+/// own thread, so a stuck one doesn't hold up the others in the meantime.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Decoders can allocate more than the image itself, but nothing near this for a screen-sized one.
+const MAX_IMAGE_ALLOC: u64 = 64 * 1024 * 1024;
+
+const DEFAULT_NOTIFICATION_DURATION: Duration = Duration::from_secs(5);
+
+/// Notifications longer than this are turned away, since they'd never go away in practice and
+/// far larger ones can't be added to the current time.
+const MAX_NOTIFICATION_DURATION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Which widgets the dashboard shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// Every widget.
+    Dashboard,
+    /// Only the clocks and uptime.
+    Clock,
+    /// Nothing but the wallpaper, which leaves the screen to images sent through the API.
+    Wallpaper,
+}
+
+impl FromStr for Layout {
+    type Err = ChipsError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "dashboard" => Ok(Layout::Dashboard),
+            "clock" => Ok(Layout::Clock),
+            "wallpaper" => Ok(Layout::Wallpaper),
+            _ => Err(ChipsError::InvalidCommand(format!(
+                "unknown layout {:?}",
+                s
+            ))),
+        }
+    }
+}
+
+/// Something another program asked the screen to do, to be carried out by the device worker.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlCommand {
+    /// From 0 to 100.
+    SetBrightness(i32),
+    SwitchLayout(Layout),
+    /// Sets a metric that none of the providers have, for templates and widgets to show.
+    PushMetric {
+        name: String,
+        value: MetricValue,
+    },
+    /// Draws an image fitted into an area, on top of whatever is there. Widgets still redraw
+    /// over it when they change, so it's best paired with [`Layout::Wallpaper`].
+    DrawImage {
+        image: DynamicImage,
+        rect: Rect,
+        fit: FitMode,
+    },
+    /// Shows text in place of the widgets for a while.
+    Notify {
+        text: String,
+        duration: Duration,
+    },
+}
+
+impl ControlCommand {
+    /// Reads a command from a `POST` request to the control API:
+    ///
+    /// | Path              | Body                                   | Query                        |
+    /// |-------------------|----------------------------------------|------------------------------|
+    /// | `/brightness`     | 0 to 100                               |                              |
+    /// | `/layout`         | `dashboard`, `clock` or `wallpaper`    |                              |
+    /// | `/metrics/<name>` | A number or text                       |                              |
+    /// | `/image`          | An image file, like a PNG              | `x`, `y`, `width`, `height`, |
+    /// |                   |                                        | `fit`, `contain` by default  |
+    /// | `/notify`         | The text to show                       | `seconds`, 5 by default, up  |
+    /// |                   |                                        | to a day                     |
+    ///
+    /// Images fill the whole screen unless given an area, and can't be bigger than the screen.
+    /// They're fitted into the area with any [`FitMode`], like `cover` or `tile`.
+    pub fn from_request(request: &Request) -> Result<Self> {
+        let invalid = |message: String| ChipsError::InvalidCommand(message);
+        if request.method != "POST" {
+            return Err(invalid(format!("expected POST, not {}", request.method)));
+        }
+
+        let text = || {
+            std::str::from_utf8(&request.body)
+                .map(str::trim)
+                .map_err(|_| invalid("body isn't UTF-8 text".to_string()))
+        };
+        let number = |name: &str, default: f64| -> Result<f64> {
+            match request.query(name) {
+                Some(value) => value
+                    .parse()
+                    .map_err(|_| invalid(format!("invalid {} {:?}", name, value))),
+                None => Ok(default),
+            }
+        };
+
+        match request.path.as_str() {
+            "/brightness" => {
+                let brightness = text()?;
+                brightness
+                    .parse()
+                    .ok()
+                    .filter(|brightness| (0..=100).contains(brightness))
+                    .map(ControlCommand::SetBrightness)
+                    .ok_or_else(|| invalid(format!("invalid brightness {:?}", brightness)))
+            }
+            "/layout" => Ok(ControlCommand::SwitchLayout(text()?.parse()?)),
+            "/notify" => {
+                let seconds = number("seconds", DEFAULT_NOTIFICATION_DURATION.as_secs_f64())?;
+                let duration = Duration::try_from_secs_f64(seconds)
+                    .ok()
+                    .filter(|duration| *duration <= MAX_NOTIFICATION_DURATION)
+                    .ok_or_else(|| invalid(format!("invalid seconds {}", seconds)))?;
+                Ok(ControlCommand::Notify {
+                    text: text()?.to_string(),
+                    duration,
+                })
+            }
+            "/image" => {
+                let screen = Rect::screen();
+                let rect = Rect::new(
+                    number("x", 0.0)? as i32,
+                    number("y", 0.0)? as i32,
+                    number("width", screen.width as f64)? as i32,
+                    number("height", screen.height as f64)? as i32,
+                );
+                if rect.is_empty() || screen.intersect(&rect) != Some(rect) {
+                    return Err(invalid(format!("{:?} isn't on the screen", rect)));
+                }
+
+                // Images are only ever shown fitted into part of the screen, so anything bigger is
+                // turned away before it's decoded
+                let mut limits = Limits::default();
+                limits.max_image_width = Some(screen.width as u32);
+                limits.max_image_height = Some(screen.height as u32);
+                limits.max_alloc = Some(MAX_IMAGE_ALLOC);
+                let mut reader =
+                    ImageReader::new(Cursor::new(&request.body)).with_guessed_format()?;
+                reader.limits(limits);
+                let fit = match request.query("fit") {
+                    Some(fit) => fit.parse()?,
+                    None => FitMode::Contain,
+                };
+                let image = reader.decode()?;
+                Ok(ControlCommand::DrawImage { image, rect, fit })
+            }
+            path => match path.strip_prefix("/metrics/") {
+                Some(name) if !name.is_empty() => Ok(ControlCommand::PushMetric {
+                    name: name.to_string(),
+                    value: parse_value(text()?),
+                }),
+                _ => Err(invalid(format!("unknown command {:?}", path))),
+            },
+        }
+    }
+}
+
+/// A small HTTP server on the local machine that lets other programs drive the screen, with
+/// commands read by [`ControlCommand::from_request`].
+///
+/// Web pages can send simple requests to local servers too, so requests that look like they
+/// came from a browser are turned away, as described in [`authorize`].
+pub struct ControlServer {
+    listener: TcpListener,
+    token: Option<String>,
+}
+
+impl ControlServer {
+    pub fn bind(addr: impl ToSocketAddrs) -> Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+            token: None,
+        })
+    }
+
+    /// Requires requests to carry an `Authorization: Bearer <token>` header.
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Answers requests on background threads, forwarding the commands to `commands`. Requests
+    /// are answered once the command is queued, without waiting for it to be carried out. The
+    /// server stops after the receiving end is dropped.
+    pub fn spawn(self, commands: Sender<ControlCommand>) {
+        thread::spawn(move || {
+            let stopped = Arc::new(AtomicBool::new(false));
+            for stream in self.listener.incoming().flatten() {
+                if stopped.load(Ordering::Relaxed) {
+                    break;
+                }
+
+                let commands = commands.clone();
+                let token = self.token.clone();
+                let stopped = stopped.clone();
+                thread::spawn(move || {
+                    match handle_connection(stream, token.as_deref(), &commands) {
+                        Ok(true) => {}
+                        Ok(false) => stopped.store(true, Ordering::Relaxed),
+                        Err(err) => println!("{:?}", err),
+                    }
+                });
+            }
+        });
+    }
+}
+
+/// Answers one request, returning whether the worker is still taking commands.
+fn handle_connection(
+    stream: TcpStream,
+    token: Option<&str>,
+    commands: &Sender<ControlCommand>,
+) -> Result<bool> {
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    let command = read_request(DeadlineReader::new(&stream, REQUEST_TIMEOUT)).and_then(|request| {
+        authorize(&request, token)?;
+        ControlCommand::from_request(&request)
+    });
+    match command {
+        Ok(command) => {
+            if commands.send(command).is_err() {
+                write_response(&stream, 503, "Service Unavailable", "shutting down\n")?;
+                return Ok(false);
+            }
+            write_response(&stream, 202, "Accepted", "")?;
+        }
+        Err(err) => {
+            let (status, reason) = match err {
+                ChipsError::Forbidden(_) => (403, "Forbidden"),
+                _ => (400, "Bad Request"),
+            };
+            let message = format!("{}\n", error_message(&err));
+            write_response(&stream, status, reason, &message)?;
+        }
+    }
+    Ok(true)
+}
+
+/// Checks that a request came from a local tool rather than a web page. Browsers add an `Origin`
+/// header to requests that pages send to other sites, and a page that rebinds its own domain to
+/// this machine still sends that domain as the `Host`. When the server has a token, the request
+/// must also carry it.
+fn authorize(request: &Request, token: Option<&str>) -> Result<()> {
+    let forbidden = |message: String| Err(ChipsError::Forbidden(message));
+    if let Some(origin) = request.header("Origin") {
+        return forbidden(format!("requests from {:?} aren't allowed", origin));
+    }
+    if let Some(host) = request.header("Host") {
+        if !is_loopback_host(host) {
+            return forbidden(format!("host {:?} isn't this machine", host));
+        }
+    }
+    if let Some(token) = token {
+        let given = request
+            .header("Authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        if !same_token(given.trim(), token) {
+            return forbidden("missing or wrong token".to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Whether a `Host` header, with or without a port, names this machine.
+fn is_loopback_host(host: &str) -> bool {
+    let name = match host.strip_prefix('[') {
+        Some(bracketed) => bracketed.split(']').next().unwrap_or_default(),
+        None => host.split(':').next().unwrap_or_default(),
+    };
+    name.eq_ignore_ascii_case("localhost")
+        || name.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Compares tokens in constant time, so that how long a check takes doesn't give away how much
+/// of a guess was right.
+fn same_token(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::time::Instant;
+
+    use crossbeam::channel::unbounded;
+    use image::ImageFormat;
+
+    use super::*;
+
+    fn post(path: &str, body: &[u8]) -> Request {
+        let (path, query) = path.split_once('?').unwrap_or((path, ""));
+        Request {
+            method: "POST".to_string(),
+            path: path.to_string(),
+            query: query
+                .split('&')
+                .filter(|pair| !pair.is_empty())
+                .map(|pair| {
+                    let (name, value) = pair.split_once('=').unwrap();
+                    (name.to_string(), value.to_string())
+                })
+                .collect(),
+            headers: vec![("Host".to_string(), "127.0.0.1:7878".to_string())],
+            body: body.to_vec(),
+        }
+    }
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut png = Cursor::new(vec![]);
+        DynamicImage::new_rgb8(width, height)
+            .write_to(&mut png, ImageFormat::Png)
+            .unwrap();
+        png.into_inner()
+    }
+
+    #[test]
+    fn reads_commands() {
+        let command = |path: &str, body: &[u8]| ControlCommand::from_request(&post(path, body));
+        assert_eq!(
+            command("/brightness", b"40\n").unwrap(),
+            ControlCommand::SetBrightness(40)
+        );
+        assert_eq!(
+            command("/layout", b"clock").unwrap(),
+            ControlCommand::SwitchLayout(Layout::Clock)
+        );
+        assert_eq!(
+            command("/metrics/room.temperature", b"21.5").unwrap(),
+            ControlCommand::PushMetric {
+                name: "room.temperature".to_string(),
+                value: MetricValue::Number(21.5),
+            }
+        );
+        assert_eq!(
+            command("/notify?seconds=2.5", b"Build finished").unwrap(),
+            ControlCommand::Notify {
+                text: "Build finished".to_string(),
+                duration: Duration::from_millis(2500),
+            }
+        );
+        match command("/image?x=100&y=50&width=200&height=100", &png(4, 2)).unwrap() {
+            ControlCommand::DrawImage { image, rect, fit } => {
+                assert_eq!((image.width(), image.height()), (4, 2));
+                assert_eq!(rect, Rect::new(100, 50, 200, 100));
+                assert_eq!(fit, FitMode::Contain);
+            }
+            command => panic!("unexpected {:?}", command),
+        }
+        match command("/image?fit=tile", &png(4, 2)).unwrap() {
+            ControlCommand::DrawImage { rect, fit, .. } => {
+                assert_eq!(rect, Rect::screen());
+                assert_eq!(fit, FitMode::Tile);
+            }
+            command => panic!("unexpected {:?}", command),
+        }
+
+        for (path, body) in [
+            ("/brightness", b"101".as_slice()),
+            ("/layout", b"graphs"),
+            ("/metrics/", b"1"),
+            ("/notify?seconds=-1", b"hi"),
+            ("/notify?seconds=86401", b"hi"),
+            ("/notify?seconds=1e19", b"hi"),
+            ("/image?x=700", &png(1, 1)),
+            ("/image", b"not an image"),
+            ("/image", &png(801, 1)),
+            ("/image?fit=fill", &png(1, 1)),
+            ("/reboot", b""),
+        ] {
+            assert!(command(path, body).is_err(), "{}", path);
+        }
+        let mut get = post("/brightness", b"40");
+        get.method = "GET".to_string();
+        assert!(ControlCommand::from_request(&get).is_err());
+    }
+
+    #[test]
+    fn authorizes_requests() {
+        let with_header = |name: &str, value: &str| {
+            let mut request = post("/brightness", b"40");
+            request.headers.push((name.to_string(), value.to_string()));
+            request
+        };
+        assert!(authorize(&post("/brightness", b"40"), None).is_ok());
+        for host in [
+            "localhost",
+            "LOCALHOST:7878",
+            "127.0.0.2:7878",
+            "[::1]:7878",
+            "[::1]",
+        ] {
+            let mut request = post("/brightness", b"40");
+            request.headers = vec![("Host".to_string(), host.to_string())];
+            assert!(authorize(&request, None).is_ok(), "{}", host);
+        }
+
+        for host in ["example.com", "192.168.1.2:7878", "localhost.example.com"] {
+            let mut request = post("/brightness", b"40");
+            request.headers = vec![("Host".to_string(), host.to_string())];
+            assert!(matches!(
+                authorize(&request, None),
+                Err(ChipsError::Forbidden(_))
+            ));
+        }
+        assert!(matches!(
+            authorize(&with_header("Origin", "https://example.com"), None),
+            Err(ChipsError::Forbidden(_))
+        ));
+        assert!(matches!(
+            authorize(&with_header("origin", "null"), None),
+            Err(ChipsError::Forbidden(_))
+        ));
+
+        let authorized = with_header("Authorization", "Bearer s3cret");
+        assert!(authorize(&authorized, Some("s3cret")).is_ok());
+        assert!(authorize(&authorized, Some("s3cre")).is_err());
+        assert!(authorize(&with_header("Authorization", "s3cret"), Some("s3cret")).is_err());
+        assert!(authorize(&post("/brightness", b"40"), Some("s3cret")).is_err());
+    }
+
+    #[test]
+    fn forwards_commands() {
+        let server = ControlServer::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let (sender, commands) = unbounded();
+        server.spawn(sender);
+
+        let request = |request: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let response = request("POST /layout HTTP/1.1\r\nContent-Length: 9\r\n\r\nwallpaper");
+        assert!(response.starts_with("HTTP/1.0 202"), "{}", response);
+        assert_eq!(
+            commands.recv_timeout(Duration::from_secs(5)).unwrap(),
+            ControlCommand::SwitchLayout(Layout::Wallpaper)
+        );
+
+        let response = request("POST /layout HTTP/1.1\r\nContent-Length: 6\r\n\r\ngraphs");
+        assert!(response.starts_with("HTTP/1.0 400"), "{}", response);
+        assert!(response.contains("unknown layout"), "{}", response);
+
+        let response = request(
+            "POST /layout HTTP/1.1\r\nOrigin: https://example.com\r\nContent-Length: 5\r\n\r\nclock",
+        );
+        assert!(response.starts_with("HTTP/1.0 403"), "{}", response);
+        assert!(commands.is_empty());
+
+        // A client that never finishes its request doesn't hold up the next one
+        let mut stalled = TcpStream::connect(addr).unwrap();
+        stalled
+            .write_all(b"POST /layout HTTP/1.1\r\nContent-Length: 100\r\n\r\n")
+            .unwrap();
+        let started = Instant::now();
+        let response = request("POST /layout HTTP/1.1\r\nContent-Length: 5\r\n\r\nclock");
+        assert!(response.starts_with("HTTP/1.0 202"), "{}", response);
+        assert!(started.elapsed() < REQUEST_TIMEOUT);
+    }
+}
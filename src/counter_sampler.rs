@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use crate::cpu_sampler::{Sampled, MIN_SAMPLE_INTERVAL};
+
+/// A pair of cumulative counters for one device, like the bytes a network interface has
+/// received and sent.
+pub trait Counters: Clone {
+    /// Identifies the device from one reading to the next.
+    fn key(&self) -> &str;
+
+    fn values(&self) -> [u64; 2];
+}
+
+/// Each device with the rates of its two counters, per second.
+pub type Rates<T> = Vec<(T, [f64; 2])>;
+
+/// Computes rates from the change in each device's counters between calls, in the same way as
+/// [`crate::cpu_sampler::CpuSampler`].
+///
+/// Devices that appear between calls have nothing to compare against, so they're left out of
+/// the first reading after they appear. The same goes for devices whose counters went
+/// backwards, such as after a network adapter was reset.
+pub struct CounterSampler<T> {
+    at: Instant,
+    counters: HashMap<String, T>,
+}
+
+impl<T: Counters> CounterSampler<T> {
+    pub fn new(counters: Vec<T>, now: Instant) -> Self {
+        Self {
+            at: now,
+            counters: by_key(counters),
+        }
+    }
+
+    /// Returns the rates in the order the devices were read. Returns `None` if called again
+    /// within [`MIN_SAMPLE_INTERVAL`], without moving the baseline.
+    pub fn sample(&mut self, counters: Vec<T>, now: Instant) -> Option<Sampled<Rates<T>>> {
+        let interval = now.checked_duration_since(self.at)?;
+        if interval < MIN_SAMPLE_INTERVAL {
+            return None;
+        }
+
+        let seconds = interval.as_secs_f64();
+        let rates = counters
+            .iter()
+            .filter_map(|current| {
+                let last = self.counters.get(current.key())?.values();
+                let [first, second] = current.values();
+                let first = first.checked_sub(last[0])?;
+                let second = second.checked_sub(last[1])?;
+                Some((
+                    current.clone(),
+                    [first as f64 / seconds, second as f64 / seconds],
+                ))
+            })
+            .collect();
+
+        self.at = now;
+        self.counters = by_key(counters);
+        Some(Sampled {
+            value: rates,
+            interval,
+        })
+    }
+}
+
+fn by_key<T: Counters>(counters: Vec<T>) -> HashMap<String, T> {
+    counters
+        .into_iter()
+        .map(|counters| (counters.key().to_string(), counters))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Device(&'static str, u64, u64);
+
+    impl Counters for Device {
+        fn key(&self) -> &str {
+            self.0
+        }
+
+        fn values(&self) -> [u64; 2] {
+            [self.1, self.2]
+        }
+    }
+
+    #[test]
+    fn rejects_readings_too_close_together() {
+        let start = Instant::now();
+        let mut sampler = CounterSampler::new(vec![Device("eth0", 0, 0)], start);
+        assert_eq!(sampler.sample(vec![Device("eth0", 10, 10)], start), None);
+
+        let sample = sampler
+            .sample(vec![Device("eth0", 10, 20)], start + Duration::from_secs(2))
+            .unwrap();
+        assert_eq!(sample.interval, Duration::from_secs(2));
+        assert_eq!(sample.value, vec![(Device("eth0", 10, 20), [5.0, 10.0])]);
+    }
+
+    #[test]
+    fn skips_new_devices_and_reset_counters() {
+        let start = Instant::now();
+        let second = Duration::from_secs(1);
+        let mut sampler = CounterSampler::new(vec![Device("eth0", 5000, 5000)], start);
+        let sample = sampler
+            .sample(
+                vec![Device("eth0", 10, 10), Device("wlan0", 100, 100)],
+                start + second,
+            )
+            .unwrap();
+        assert_eq!(sample.value, vec![]);
+
+        let sample = sampler
+            .sample(
+                vec![Device("eth0", 20, 30), Device("wlan0", 150, 100)],
+                start + second * 2,
+            )
+            .unwrap();
+        assert_eq!(
+            sample.value,
+            vec![
+                (Device("eth0", 20, 30), [10.0, 20.0]),
+                (Device("wlan0", 150, 100), [50.0, 0.0]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,290 @@
+use std::time::{Duration, Instant};
+
+use crate::errors::Result;
+
+/// Samples closer together than this are rejected, since the system only updates its CPU time
+/// counters every few milliseconds and the ratio would mostly be noise.
+pub const MIN_SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Cumulative CPU time since boot, in arbitrary but consistent units.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CpuTimes {
+    /// All time, including idle time.
+    pub total: u64,
+    /// Time spent doing anything other than idling.
+    pub busy: u64,
+}
+
+/// Where CPU time counters and timestamps come from, so that sampling can be tested without
+/// waiting on a real clock.
+pub trait TimeSource {
+    fn now(&self) -> Instant;
+
+    fn cpu_times(&mut self) -> Result<CpuTimes>;
+
+    /// Per logical processor, in a stable order.
+    fn core_times(&mut self) -> Result<Vec<CpuTimes>>;
+}
+
+/// A value computed from the difference between two samples.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sampled<T> {
+    pub value: T,
+    /// The wall-clock time between the two samples.
+    pub interval: Duration,
+}
+
+#[derive(Debug, Clone)]
+struct Baseline<T> {
+    at: Instant,
+    times: T,
+}
+
+/// Computes CPU usage from the change in CPU times between calls. A baseline is taken on
+/// creation, so the first reading covers the time since then rather than since boot.
+///
+/// When a reading isn't possible, such as when called again within [`MIN_SAMPLE_INTERVAL`] or
+/// after the counters went backwards, `None` is returned. Nothing is reset in that case, except
+/// after a counter reset, so the next reading covers the combined interval.
+pub struct CpuSampler<S: TimeSource> {
+    source: S,
+    total: Baseline<CpuTimes>,
+    cores: Baseline<Vec<CpuTimes>>,
+}
+
+impl<S: TimeSource> CpuSampler<S> {
+    pub fn new(mut source: S) -> Result<Self> {
+        let total = Baseline {
+            at: source.now(),
+            times: source.cpu_times()?,
+        };
+        let cores = Baseline {
+            at: source.now(),
+            times: source.core_times()?,
+        };
+
+        Ok(Self {
+            source,
+            total,
+            cores,
+        })
+    }
+
+    /// Gets the ratio of busy time across all processors.
+    pub fn cpu_usage(&mut self) -> Result<Option<Sampled<f64>>> {
+        let now = self.source.now();
+        let times = self.source.cpu_times()?;
+
+        let Some(interval) = valid_interval(self.total.at, now) else {
+            return Ok(None);
+        };
+        let usage = usage_between(self.total.times, times);
+        if usage.is_some() || is_reset(self.total.times, times) {
+            self.total = Baseline { at: now, times };
+        }
+
+        Ok(usage.map(|value| Sampled { value, interval }))
+    }
+
+    /// Gets the ratio of busy time of each logical processor.
+    pub fn core_usage(&mut self) -> Result<Option<Sampled<Vec<f64>>>> {
+        let now = self.source.now();
+        let times = self.source.core_times()?;
+
+        let Some(interval) = valid_interval(self.cores.at, now) else {
+            return Ok(None);
+        };
+
+        // A different set of processors can't be compared against, so it becomes the baseline
+        let usage = if times.len() == self.cores.times.len() {
+            self.cores
+                .times
+                .iter()
+                .zip(&times)
+                .map(|(last, current)| usage_between(*last, *current))
+                .collect::<Option<Vec<f64>>>()
+        } else {
+            None
+        };
+
+        let reset = times.len() != self.cores.times.len()
+            || self
+                .cores
+                .times
+                .iter()
+                .zip(&times)
+                .any(|(last, current)| is_reset(*last, *current));
+        if usage.is_some() || reset {
+            self.cores = Baseline { at: now, times };
+        }
+
+        Ok(usage.map(|value| Sampled { value, interval }))
+    }
+}
+
+fn valid_interval(from: Instant, to: Instant) -> Option<Duration> {
+    let interval = to.checked_duration_since(from)?;
+    (interval >= MIN_SAMPLE_INTERVAL).then_some(interval)
+}
+
+fn is_reset(last: CpuTimes, current: CpuTimes) -> bool {
+    current.total < last.total || current.busy < last.busy
+}
+
+fn usage_between(last: CpuTimes, current: CpuTimes) -> Option<f64> {
+    let total = current.total.checked_sub(last.total)?;
+    let busy = current.busy.checked_sub(last.busy)?;
+    if total == 0 {
+        return None;
+    }
+
+    Some((busy as f64 / total as f64).clamp(0.0, 1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    /// Replays scripted CPU times, with a clock that only moves when told to.
+    #[derive(Clone)]
+    struct FakeTimeSource {
+        now: Rc<Cell<Instant>>,
+        times: Rc<Cell<CpuTimes>>,
+        cores: Rc<Cell<[CpuTimes; 2]>>,
+    }
+
+    impl FakeTimeSource {
+        fn new() -> Self {
+            Self {
+                now: Rc::new(Cell::new(Instant::now())),
+                times: Rc::new(Cell::new(CpuTimes::default())),
+                cores: Rc::new(Cell::new([CpuTimes::default(); 2])),
+            }
+        }
+
+        /// Moves the clock forward, with the given share of the elapsed time spent busy.
+        fn advance(&self, elapsed: Duration, busy: f64) {
+            self.now.set(self.now.get() + elapsed);
+
+            let ticks = elapsed.as_millis() as u64;
+            let busy_ticks = (ticks as f64 * busy) as u64;
+            let step = |times: CpuTimes| CpuTimes {
+                total: times.total + ticks,
+                busy: times.busy + busy_ticks,
+            };
+            self.times.set(step(self.times.get()));
+            self.cores.set(self.cores.get().map(step));
+        }
+    }
+
+    impl TimeSource for FakeTimeSource {
+        fn now(&self) -> Instant {
+            self.now.get()
+        }
+
+        fn cpu_times(&mut self) -> Result<CpuTimes> {
+            Ok(self.times.get())
+        }
+
+        fn core_times(&mut self) -> Result<Vec<CpuTimes>> {
+            Ok(self.cores.get().to_vec())
+        }
+    }
+
+    fn booted_source() -> FakeTimeSource {
+        // Lots of idle time since boot, which the first reading must not include
+        let source = FakeTimeSource::new();
+        source.advance(Duration::from_secs(3600), 0.0);
+        source
+    }
+
+    #[test]
+    fn first_reading_is_relative_to_creation() {
+        let source = booted_source();
+        let mut sampler = CpuSampler::new(source.clone()).unwrap();
+
+        source.advance(Duration::from_secs(1), 0.5);
+        let sample = sampler.cpu_usage().unwrap().unwrap();
+        assert_eq!(sample.value, 0.5);
+        assert_eq!(sample.interval, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn readings_cover_time_since_previous_reading() {
+        let source = booted_source();
+        let mut sampler = CpuSampler::new(source.clone()).unwrap();
+
+        source.advance(Duration::from_secs(1), 1.0);
+        assert_eq!(sampler.cpu_usage().unwrap().unwrap().value, 1.0);
+
+        source.advance(Duration::from_secs(2), 0.25);
+        let sample = sampler.cpu_usage().unwrap().unwrap();
+        assert_eq!(sample.value, 0.25);
+        assert_eq!(sample.interval, Duration::from_secs(2));
+    }
+
+    #[test]
+    fn readings_too_close_together_are_rejected() {
+        let source = booted_source();
+        let mut sampler = CpuSampler::new(source.clone()).unwrap();
+
+        // No time at all, which used to divide by zero
+        assert_eq!(sampler.cpu_usage().unwrap(), None);
+        assert_eq!(sampler.core_usage().unwrap(), None);
+
+        source.advance(MIN_SAMPLE_INTERVAL / 2, 1.0);
+        assert_eq!(sampler.cpu_usage().unwrap(), None);
+
+        // The rejected readings don't move the baseline
+        source.advance(MIN_SAMPLE_INTERVAL / 2, 0.0);
+        let sample = sampler.cpu_usage().unwrap().unwrap();
+        assert_eq!(sample.value, 0.5);
+        assert_eq!(sample.interval, MIN_SAMPLE_INTERVAL);
+    }
+
+    #[test]
+    fn stalled_counters_are_rejected() {
+        let source = booted_source();
+        let mut sampler = CpuSampler::new(source.clone()).unwrap();
+
+        // The clock moves but the counters don't
+        source.now.set(source.now.get() + Duration::from_secs(1));
+        assert_eq!(sampler.cpu_usage().unwrap(), None);
+    }
+
+    #[test]
+    fn counter_reset_becomes_new_baseline() {
+        let source = booted_source();
+        let mut sampler = CpuSampler::new(source.clone()).unwrap();
+
+        source.times.set(CpuTimes::default());
+        source.advance(Duration::from_secs(1), 0.5);
+        assert_eq!(sampler.cpu_usage().unwrap(), None);
+
+        source.advance(Duration::from_secs(1), 0.75);
+        assert_eq!(sampler.cpu_usage().unwrap().unwrap().value, 0.75);
+    }
+
+    #[test]
+    fn core_readings_are_per_core() {
+        let source = booted_source();
+        let mut sampler = CpuSampler::new(source.clone()).unwrap();
+
+        source.advance(Duration::from_secs(1), 0.0);
+        let [first, second] = source.cores.get();
+        source.cores.set([
+            first,
+            CpuTimes {
+                total: second.total,
+                busy: second.busy + 500,
+            },
+        ]);
+
+        let sample = sampler.core_usage().unwrap().unwrap();
+        assert_eq!(sample.value, vec![0.0, 0.5]);
+        assert_eq!(sample.interval, Duration::from_secs(1));
+    }
+}
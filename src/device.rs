@@ -1,14 +1,18 @@
+use std::borrow::Cow;
+use std::io::Write;
 use std::thread;
 use std::time::Duration;
 
 use crate::{
     color::Color,
+    dither::{image_to_rgb565, DitherMode},
     errors::{ChipsError, Result},
 };
-use image::{DynamicImage, RgbImage};
+use image::DynamicImage;
 use serialport::{
     DataBits, FlowControl, Parity, SerialPort, SerialPortInfo, SerialPortType, StopBits,
 };
+#[cfg(windows)]
 use windows::Devices::Enumeration::DeviceInformation;
 
 // 3.5-inch model: 480x320
@@ -18,27 +22,124 @@ pub const SCREEN_WIDTH: i32 = 800;
 pub const SCREEN_HEIGHT: i32 = 480;
 pub const PIXEL_DEPTH: u32 = 2;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Point(i32, i32);
 
 impl Point {
     pub fn new(x: i32, y: i32) -> Self {
         Self(x, y)
     }
+
+    pub fn x(&self) -> i32 {
+        self.0
+    }
+
+    pub fn y(&self) -> i32 {
+        self.1
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Rect {
+    pub const fn new(x: i32, y: i32, width: i32, height: i32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn screen() -> Self {
+        Self::new(0, 0, SCREEN_WIDTH, SCREEN_HEIGHT)
+    }
+
+    /// The first column past the right edge.
+    pub fn right(&self) -> i32 {
+        self.x + self.width
+    }
+
+    /// The first row past the bottom edge.
+    pub fn bottom(&self) -> i32 {
+        self.y + self.height
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width <= 0 || self.height <= 0
+    }
+
+    /// Returns the overlapping area of both rectangles, if there is any.
+    pub fn intersect(&self, other: &Rect) -> Option<Rect> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let rect = Rect::new(
+            x,
+            y,
+            self.right().min(other.right()) - x,
+            self.bottom().min(other.bottom()) - y,
+        );
+
+        if rect.is_empty() {
+            None
+        } else {
+            Some(rect)
+        }
+    }
+}
+
+/// Splits image uploads into separate command 197 writes of at most `tile_width` by
+/// `tile_height` pixels, retrying a failed write up to `retries` times per tile.
+///
+/// Once the device has read a tile's header, it takes the next `width * height * 2` bytes as
+/// pixels no matter how long they take to arrive. A retry therefore resumes from the first byte
+/// the port didn't accept, rather than resending the header, which would be drawn as pixels.
+#[derive(Debug, Clone, Copy)]
+pub struct ImageTiling {
+    tile_width: i32,
+    tile_height: i32,
+    retries: u32,
+}
+
+impl ImageTiling {
+    pub fn new(tile_width: i32, tile_height: i32, retries: u32) -> Result<Self> {
+        if tile_width <= 0 || tile_height <= 0 {
+            return Err(ChipsError::InvalidImageTiling {
+                width: tile_width,
+                height: tile_height,
+            });
+        }
+
+        Ok(Self {
+            tile_width,
+            tile_height,
+            retries,
+        })
+    }
 }
 
 #[derive(Debug)]
 pub struct ChipsDevice {
     serial_port_info: SerialPortInfo,
     serial_port: Option<Box<dyn SerialPort>>,
+    dither_mode: DitherMode,
+    image_tiling: Option<ImageTiling>,
 }
 
 impl ChipsDevice {
     pub fn new(serial_port_info: SerialPortInfo) -> Self {
-        return Self {
+        Self {
             serial_port_info,
             serial_port: None,
-        };
+            dither_mode: DitherMode::None,
+            image_tiling: None,
+        }
     }
 
     pub fn connect(&mut self) -> Result<()> {
@@ -54,18 +155,25 @@ impl ChipsDevice {
         self.adjust_screen(false, true, true)
     }
 
+    #[allow(dead_code)]
     pub fn shutdown(&mut self) -> Result<()> {
         // We don't implement Drop with this since that makes it easy to cause accidental shutdowns
         self.send_command_code(108)?;
         Ok(())
     }
 
+    #[allow(dead_code)]
     pub fn restart(&mut self) -> Result<()> {
         self.send_command_code(101)?;
         thread::sleep(Duration::from_secs(1));
         Ok(())
     }
 
+    /// Sets the dithering applied when converting images to the device's RGB565 format.
+    pub fn set_dither_mode(&mut self, mode: DitherMode) {
+        self.dither_mode = mode;
+    }
+
     pub fn set_brightness(&mut self, value: i32) -> Result<()> {
         self.send_command_simple(110, value, 0, 0, 0)
     }
@@ -95,49 +203,111 @@ impl ChipsDevice {
         self.send_command_121(landscape_invert, SCREEN_WIDTH, SCREEN_HEIGHT)
     }
 
+    /// Sets how image uploads are split into separate command 197 writes, or `None` to send
+    /// each image in a single write.
+    pub fn set_image_tiling(&mut self, tiling: Option<ImageTiling>) {
+        self.image_tiling = tiling;
+    }
+
+    /// Draws an image with its top-left corner at the given position. Anything outside of the
+    /// screen is clipped, so the image may be partially or entirely off-screen.
     pub fn draw_image(&mut self, image: &DynamicImage, x: i32, y: i32) -> Result<()> {
-        let width = image.width() as i32;
-        let height = image.height() as i32;
-        if width + x > SCREEN_WIDTH || height + y > SCREEN_HEIGHT {
-            return Err(ChipsError::ImageTooLarge);
-        }
+        let bounds = Rect::new(x, y, image.width() as i32, image.height() as i32);
+        let visible = match bounds.intersect(&Rect::screen()) {
+            Some(visible) => visible,
+            None => return Ok(()),
+        };
 
         // Convert to RGB so we have a known pixel format to convert from
-        let image = image.to_rgb8();
+        let image = image
+            .crop_imm(
+                (visible.x - x) as u32,
+                (visible.y - y) as u32,
+                visible.width as u32,
+                visible.height as u32,
+            )
+            .to_rgb8();
+
+        // Dither the whole visible area at once so error diffusion carries across tile edges
+        let pixels = image_to_rgb565(&image, self.dither_mode);
+        self.write_image_pixels(visible, &pixels)
+    }
 
-        let mut buf = ChipsDevice::image_to_buffer(&image);
-        self.send_command_simple(197, x, y, x + width - 1, y + height - 1)?;
-        self.write_to_serial_port(&mut buf)?;
-        thread::sleep(Duration::from_millis(10));
+    /// Draws pixels that are already in RGB565 format, in row-major order filling `rect`.
+    /// Like [`ChipsDevice::draw_image`], anything outside of the screen is clipped.
+    pub fn draw_rgb565(&mut self, rect: Rect, pixels: &[u16]) -> Result<()> {
+        match clip_to_screen(rect, pixels)? {
+            Some((visible, pixels)) => self.write_image_pixels(visible, &pixels),
+            None => Ok(()),
+        }
+    }
+
+    fn write_image_pixels(&mut self, rect: Rect, pixels: &[u16]) -> Result<()> {
+        let (tile_width, tile_height, retries) = match self.image_tiling {
+            Some(tiling) => (tiling.tile_width, tiling.tile_height, tiling.retries),
+            None => (rect.width, rect.height, 0),
+        };
+
+        for tile in tiles(rect, tile_width, tile_height) {
+            let buf = ChipsDevice::pixels_to_buffer(pixels, rect.width, tile);
+            let tile = Rect::new(rect.x + tile.x, rect.y + tile.y, tile.width, tile.height);
+            self.write_image_tile(tile, &buf, retries)?;
+        }
 
         Ok(())
     }
 
-    fn image_to_buffer(image: &RgbImage) -> Vec<u8> {
-        let buf_size = (PIXEL_DEPTH * image.width() * image.height()) as usize;
-        let mut buf: Vec<u8> = vec![0; buf_size];
+    /// Sends one command 197 with its pixels, resuming after a failed write as described on
+    /// [`ImageTiling`].
+    fn write_image_tile(&mut self, tile: Rect, buf: &[u8], retries: u32) -> Result<()> {
+        let mut message = vec![0; 6];
+        encode_command(
+            197,
+            tile.x,
+            tile.y,
+            tile.right() - 1,
+            tile.bottom() - 1,
+            &mut message,
+        );
+        message.extend_from_slice(buf);
+
+        let Some(serial_port) = &mut self.serial_port else {
+            return Ok(());
+        };
+
+        let mut written = 0;
+        let mut attempt = 0;
+        while written < message.len() {
+            match serial_port.write(&message[written..]) {
+                Ok(0) => return Err(std::io::Error::from(std::io::ErrorKind::WriteZero).into()),
+                Ok(count) => written += count,
+                Err(_) if attempt < retries => attempt += 1,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        thread::sleep(Duration::from_millis(10));
+        Ok(())
+    }
 
-        for y in 0..image.height() {
-            for x in 0..image.width() {
-                // Pixel format is 16bpp, RGB565
-                let pixel = image.get_pixel(x, y);
-                let pixel_r = (pixel.0[0] >> 3) as u16; // 5 high bits
-                let pixel_g = (pixel.0[1] >> 2) as u16; // 6 high bits
-                let pixel_b = (pixel.0[2] >> 3) as u16; // 5 high bits
-                let pixel_16 = (pixel_r << 11) | (pixel_g << 5) | pixel_b;
-                let idx = (x * PIXEL_DEPTH + (PIXEL_DEPTH * image.width()) * y) as usize;
+    /// Serializes the `region` of a row-major pixel buffer with the given row width.
+    fn pixels_to_buffer(pixels: &[u16], width: i32, region: Rect) -> Vec<u8> {
+        let buf_size = (PIXEL_DEPTH as i32 * region.width * region.height) as usize;
+        let mut buf: Vec<u8> = Vec::with_capacity(buf_size);
 
+        for y in region.y..region.bottom() {
+            let row_start = (y * width + region.x) as usize;
+            for pixel_16 in &pixels[row_start..row_start + region.width as usize] {
                 // Write to the buffer, flipping endianness (device is BE)
-                buf[idx] = (pixel_16 & 255) as u8;
-                buf[idx + 1] = (pixel_16 >> 8) as u8;
+                buf.extend_from_slice(&pixel_16.to_le_bytes());
             }
         }
 
-        return buf;
+        buf
     }
 
     pub fn draw_pixels(&mut self, color: Color, points: &[Point]) -> Result<()> {
-        if points.len() == 0 {
+        if points.is_empty() {
             return Ok(());
         }
 
@@ -156,7 +326,7 @@ impl ChipsDevice {
 
         self.draw_pixels_raw(0, 0, color, &list_1)?;
 
-        if source.len() == 0 {
+        if source.is_empty() {
             return Ok(());
         }
 
@@ -191,7 +361,7 @@ impl ChipsDevice {
         let chunk_reserved = 8;
         let chunk_offset = chunk_size - chunk_reserved;
 
-        let color_16 = color.as_serial();
+        let color_16 = color.to_rgb565();
         let mut source_index = 0;
         let mut buf = vec![0; chunk_size];
         buf[6] = (color_16 >> 8) as u8;
@@ -224,10 +394,10 @@ impl ChipsDevice {
         color_fg: Color,
         data: &[u8],
     ) -> Result<()> {
-        let color_bg_16 = color_bg.as_serial();
-        let color_fg_16 = color_fg.as_serial();
-        let ecc = ((((color_fg_16 as i32) >> 2) + 2 & 15) | (((color_bg_16 as i32) >> 3) + 3 & 240))
-            as u8;
+        let color_bg_16 = color_bg.to_rgb565();
+        let color_fg_16 = color_fg.to_rgb565();
+        let ecc = (((((color_fg_16 as i32) >> 2) + 2) & 15)
+            | ((((color_bg_16 as i32) >> 3) + 3) & 240)) as u8;
 
         let chunk_size = 64;
         let chunk_reserved = 12;
@@ -287,10 +457,10 @@ impl ChipsDevice {
         color_fg: Color,
         data: &[u8],
     ) -> Result<()> {
-        let color_bg_16 = color_bg.as_serial();
-        let color_fg_16 = color_fg.as_serial();
-        let ecc = ((((color_fg_16 as i32) >> 2) + 2 & 15) | (((color_bg_16 as i32) >> 3) + 3 & 240))
-            as u8;
+        let color_bg_16 = color_bg.to_rgb565();
+        let color_fg_16 = color_fg.to_rgb565();
+        let ecc = (((((color_fg_16 as i32) >> 2) + 2) & 15)
+            | ((((color_bg_16 as i32) >> 3) + 3) & 240)) as u8;
 
         let chunk_size = 64;
         let chunk_reserved = 12;
@@ -336,11 +506,12 @@ impl ChipsDevice {
         bottom: i32,
         color: Color,
     ) -> Result<()> {
-        let color_16 = color.as_serial();
-        let ecc = ((((color_16 as i32) >> 2) + 2 & 15) | ((bottom >> 3) + 3 & 240)) as u8;
+        let color_16 = color.to_rgb565();
+        let ecc = (((((color_16 as i32) >> 2) + 2) & 15) | (((bottom >> 3) + 3) & 240)) as u8;
         self.kd_draw(136, left, top, right, bottom, color_16 as i32, ecc)
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn kd_draw(
         &mut self,
         command_code: u8,
@@ -364,6 +535,7 @@ impl ChipsDevice {
         )
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn kd_draw_buf(
         &mut self,
         command_code: u8,
@@ -440,6 +612,7 @@ impl ChipsDevice {
         self.send_command_delayed(command_code, left, top, right, bottom, data, 5)
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn send_command_delayed(
         &mut self,
         command_code: u8,
@@ -457,12 +630,7 @@ impl ChipsDevice {
             });
         }
 
-        data[0] = (left >> 2) as u8;
-        data[1] = (((left & 3) << 6) + (top >> 4)) as u8;
-        data[2] = (((top & 15) << 4) + (right >> 6)) as u8;
-        data[3] = (((right & 63) << 2) + (bottom >> 8)) as u8;
-        data[4] = (bottom & 255) as u8;
-        data[5] = command_code;
+        encode_command(command_code, left, top, right, bottom, data);
         self.write_to_serial_port(data)?;
         thread::sleep(Duration::from_millis(delay));
         Ok(())
@@ -494,6 +662,63 @@ impl ChipsDevice {
     }
 }
 
+/// Writes the 6 byte header shared by most commands to the start of `data`.
+fn encode_command(command_code: u8, left: i32, top: i32, right: i32, bottom: i32, data: &mut [u8]) {
+    data[0] = (left >> 2) as u8;
+    data[1] = (((left & 3) << 6) + (top >> 4)) as u8;
+    data[2] = (((top & 15) << 4) + (right >> 6)) as u8;
+    data[3] = (((right & 63) << 2) + (bottom >> 8)) as u8;
+    data[4] = (bottom & 255) as u8;
+    data[5] = command_code;
+}
+
+/// Splits `rect` into tiles of at most `tile_width` by `tile_height`, relative to its top-left
+/// corner and in row-major order.
+fn tiles(rect: Rect, tile_width: i32, tile_height: i32) -> impl Iterator<Item = Rect> {
+    (0..rect.height)
+        .step_by(tile_height as usize)
+        .flat_map(move |tile_y| {
+            (0..rect.width)
+                .step_by(tile_width as usize)
+                .map(move |tile_x| {
+                    Rect::new(
+                        tile_x,
+                        tile_y,
+                        tile_width.min(rect.width - tile_x),
+                        tile_height.min(rect.height - tile_y),
+                    )
+                })
+        })
+}
+
+/// The part of a row-major pixel buffer filling `rect` that is on screen, if any.
+fn clip_to_screen(rect: Rect, pixels: &[u16]) -> Result<Option<(Rect, Cow<'_, [u16]>)>> {
+    let expected = rect.width.max(0) as usize * rect.height.max(0) as usize;
+    if pixels.len() < expected {
+        return Err(ChipsError::InvalidLength {
+            received: pixels.len(),
+            expected,
+        });
+    }
+
+    let visible = match rect.intersect(&Rect::screen()) {
+        Some(visible) => visible,
+        None => return Ok(None),
+    };
+
+    if visible == rect {
+        return Ok(Some((rect, Cow::Borrowed(pixels))));
+    }
+
+    let mut clipped = Vec::with_capacity((visible.width * visible.height) as usize);
+    for y in visible.y..visible.bottom() {
+        let row_start = ((y - rect.y) * rect.width + (visible.x - rect.x)) as usize;
+        clipped.extend_from_slice(&pixels[row_start..row_start + visible.width as usize]);
+    }
+
+    Ok(Some((visible, Cow::Owned(clipped))))
+}
+
 pub fn get_chips_serial_port_info(chips_device_id: &str) -> Option<SerialPortInfo> {
     serialport::available_ports()
         .expect("Failed to enumerate available ports")
@@ -502,21 +727,174 @@ pub fn get_chips_serial_port_info(chips_device_id: &str) -> Option<SerialPortInf
             SerialPortType::UsbPort(usb_port) => usb_port
                 .serial_number
                 .clone()
-                .and_then(|serial_number| Some(chips_device_id.contains(&serial_number)))
+                .map(|serial_number| chips_device_id.contains(&serial_number))
                 .unwrap_or(false),
             _ => false,
         })
 }
 
+/// Part of the device ID, which is also the USB serial number.
+const CHIPS_SERIAL_NUMBER: &str = "USB35INCHIPSV2";
+
+#[cfg(windows)]
 pub fn get_chips_id() -> Result<Option<String>> {
     let device_info_collection = DeviceInformation::FindAllAsync()?.get()?;
     for device_info in device_info_collection {
         let device_enabled = device_info.IsEnabled()?;
         let device_id = device_info.Id()?;
-        if device_enabled && device_id.to_string().contains("USB35INCHIPSV2") {
+        if device_enabled && device_id.to_string().contains(CHIPS_SERIAL_NUMBER) {
             return Ok(Some(device_id.to_string()));
         }
     }
 
     Ok(None)
 }
+
+/// Finds the device by the serial number of its USB serial port, which is all the ID is used for.
+#[cfg(not(windows))]
+pub fn get_chips_id() -> Result<Option<String>> {
+    Ok(serialport::available_ports()?
+        .into_iter()
+        .find_map(|port| match port.port_type {
+            SerialPortType::UsbPort(usb_port) => usb_port
+                .serial_number
+                .filter(|serial_number| serial_number.contains(CHIPS_SERIAL_NUMBER)),
+            _ => None,
+        }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn image_buffer_matches_command_color_packing() {
+        let colors = [
+            Color::new(0, 0, 0),
+            Color::new(255, 255, 255),
+            Color::new(63, 67, 81),
+            Color::new(228, 207, 154),
+            Color::new(1, 2, 3),
+            Color::new(254, 7, 129),
+        ];
+
+        let image = RgbImage::from_fn(colors.len() as u32, 1, |x, _| {
+            let color = colors[x as usize];
+            Rgb([color.r(), color.g(), color.b()])
+        });
+        let pixels = image_to_rgb565(&image, DitherMode::None);
+        let region = Rect::new(0, 0, image.width() as i32, image.height() as i32);
+        let buf = ChipsDevice::pixels_to_buffer(&pixels, region.width, region);
+
+        for (i, color) in colors.iter().enumerate() {
+            // Command paths send the high byte first, image data sends the low byte first
+            let [high, low] = color.to_rgb565().to_be_bytes();
+            assert_eq!(buf[i * 2..i * 2 + 2], [low, high]);
+        }
+    }
+
+    #[test]
+    fn intersects_rects() {
+        let rect = Rect::new(10, 20, 30, 40);
+        assert_eq!(rect.intersect(&rect), Some(rect));
+        assert_eq!(
+            rect.intersect(&Rect::new(0, 0, 20, 30)),
+            Some(Rect::new(10, 20, 10, 10))
+        );
+        assert_eq!(
+            rect.intersect(&Rect::new(15, 25, 5, 5)),
+            Some(Rect::new(15, 25, 5, 5))
+        );
+
+        // Touching edges don't overlap
+        assert_eq!(rect.intersect(&Rect::new(40, 20, 10, 10)), None);
+        assert_eq!(rect.intersect(&Rect::new(10, 0, 10, 20)), None);
+        assert_eq!(rect.intersect(&Rect::new(100, 100, 10, 10)), None);
+        assert_eq!(rect.intersect(&Rect::new(10, 20, -5, 10)), None);
+    }
+
+    #[test]
+    fn clips_pixels_to_screen() {
+        let pixels: Vec<u16> = (0..200).collect();
+
+        // Off the top-left corner, leaving the bottom-right quarter
+        let (visible, clipped) = clip_to_screen(Rect::new(-10, -5, 20, 10), &pixels)
+            .unwrap()
+            .unwrap();
+        assert_eq!(visible, Rect::new(0, 0, 10, 5));
+        let expected: Vec<u16> = (5..10)
+            .flat_map(|y| (10..20).map(move |x| y * 20 + x))
+            .collect();
+        assert_eq!(clipped.as_ref(), expected.as_slice());
+
+        // Entirely visible buffers are passed through
+        let (visible, clipped) = clip_to_screen(Rect::new(5, 5, 20, 10), &pixels)
+            .unwrap()
+            .unwrap();
+        assert_eq!(visible, Rect::new(5, 5, 20, 10));
+        assert!(matches!(clipped, Cow::Borrowed(_)));
+
+        assert!(clip_to_screen(Rect::new(SCREEN_WIDTH, 0, 20, 10), &pixels)
+            .unwrap()
+            .is_none());
+        assert!(clip_to_screen(Rect::new(0, 0, -20, 10), &pixels)
+            .unwrap()
+            .is_none());
+        assert!(matches!(
+            clip_to_screen(Rect::new(0, 0, 20, 11), &pixels),
+            Err(ChipsError::InvalidLength {
+                received: 200,
+                expected: 220
+            })
+        ));
+    }
+
+    #[test]
+    fn clips_oversized_pixels_to_screen() {
+        let rect = Rect::new(-1, -1, SCREEN_WIDTH + 2, SCREEN_HEIGHT + 2);
+        let pixels: Vec<u16> = (0..rect.width * rect.height).map(|i| i as u16).collect();
+
+        let (visible, clipped) = clip_to_screen(rect, &pixels).unwrap().unwrap();
+        assert_eq!(visible, Rect::screen());
+        assert_eq!(clipped.len(), (SCREEN_WIDTH * SCREEN_HEIGHT) as usize);
+        assert_eq!(clipped[0], pixels[rect.width as usize + 1]);
+        assert_eq!(
+            clipped[SCREEN_WIDTH as usize],
+            pixels[2 * rect.width as usize + 1]
+        );
+    }
+
+    #[test]
+    fn draws_off_screen_images() {
+        let mut device = ChipsDevice::new(SerialPortInfo {
+            port_name: String::from("test"),
+            port_type: SerialPortType::Unknown,
+        });
+        let image = DynamicImage::new_rgb8(SCREEN_WIDTH as u32 * 2, 10);
+
+        device.draw_image(&image, -SCREEN_WIDTH, -5).unwrap();
+        device.draw_image(&image, SCREEN_WIDTH, 0).unwrap();
+        device.draw_image(&image, 0, -10).unwrap();
+    }
+
+    #[test]
+    fn splits_into_tiles() {
+        assert!(ImageTiling::new(0, 120, 2).is_err());
+        assert!(ImageTiling::new(200, -1, 2).is_err());
+        assert!(ImageTiling::new(200, 120, 0).is_ok());
+
+        let tiles: Vec<Rect> = tiles(Rect::new(50, 50, 450, 130), 200, 120).collect();
+        assert_eq!(
+            tiles,
+            vec![
+                Rect::new(0, 0, 200, 120),
+                Rect::new(200, 0, 200, 120),
+                Rect::new(400, 0, 50, 120),
+                Rect::new(0, 120, 200, 10),
+                Rect::new(200, 120, 200, 10),
+                Rect::new(400, 120, 50, 10),
+            ]
+        );
+    }
+}
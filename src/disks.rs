@@ -0,0 +1,445 @@
+#[cfg(windows)]
+use windows::{
+    core::PCWSTR,
+    Win32::{
+        Foundation::CloseHandle,
+        Storage::FileSystem::{
+            CreateFileW, GetDiskFreeSpaceExW, GetDriveTypeW, GetLogicalDriveStringsW,
+            FILE_FLAGS_AND_ATTRIBUTES, FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING,
+        },
+        System::IO::DeviceIoControl,
+        System::{
+            Ioctl::{DISK_PERFORMANCE, IOCTL_DISK_PERFORMANCE},
+            WindowsProgramming::DRIVE_FIXED,
+        },
+    },
+};
+
+use crate::counter_sampler::{Counters, Rates};
+#[cfg(target_os = "linux")]
+use crate::errors::ChipsError;
+use crate::errors::Result;
+use crate::metrics::{metric_name_segment, MetricSnapshot};
+
+/// Linux always counts disk I/O in 512-byte sectors, whatever the device's real sector size.
+#[cfg(target_os = "linux")]
+const DISKSTATS_SECTOR_SIZE: u64 = 512;
+
+/// Space on one mounted filesystem, in bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiskSpace {
+    /// Where the filesystem is mounted, like `/home` or `C:\`.
+    pub mount: String,
+    pub total: u64,
+    pub used: u64,
+    /// Space that can be written to. This can be less than `total - used`, since some
+    /// filesystems reserve space for the system.
+    pub available: u64,
+}
+
+impl DiskSpace {
+    /// The ratio of used to total space.
+    pub fn usage(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+
+        self.used as f64 / self.total as f64
+    }
+}
+
+/// Cumulative I/O on one disk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiskCounters {
+    pub device: String,
+    pub read_bytes: u64,
+    pub written_bytes: u64,
+}
+
+/// Transfer rates, in bytes per second.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DiskRate {
+    pub read: f64,
+    pub write: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DiskThroughput {
+    /// Summed over all disks.
+    pub total: DiskRate,
+    pub devices: Vec<(String, DiskRate)>,
+}
+
+impl DiskThroughput {
+    /// Collects the rates from a [`crate::counter_sampler::CounterSampler`], totalling all disks.
+    pub fn from_rates(rates: Rates<DiskCounters>) -> Self {
+        let mut throughput = Self::default();
+        for (disk, [read, write]) in rates {
+            throughput.total.read += read;
+            throughput.total.write += write;
+            throughput
+                .devices
+                .push((disk.device, DiskRate { read, write }));
+        }
+        throughput
+    }
+
+    /// Sets `disk.read` and `disk.write` to the total, and `disk.<device>.read` and
+    /// `disk.<device>.write` for each disk, in bytes per second. Devices are named like mounts
+    /// are in [`write_space_metrics`], so `nvme0n1` stays as it is and `C:` becomes `c`.
+    pub fn write_metrics(&self, snapshot: &mut MetricSnapshot) {
+        snapshot.set("disk.read", self.total.read);
+        snapshot.set("disk.write", self.total.write);
+        for (device, rate) in &self.devices {
+            let name = metric_name_segment(device);
+            snapshot.set(format!("disk.{}.read", name), rate.read);
+            snapshot.set(format!("disk.{}.write", name), rate.write);
+        }
+    }
+}
+
+/// Sets `disk.<mount>.used`, `.total` and `.available` in bytes, and `.usage` as a percentage,
+/// for each filesystem. Mounts are named by their path as by [`metric_name_segment`], so `C:\`
+/// becomes `disk.c.usage` and `/home/data` becomes `disk.home_data.usage`. Those names never
+/// start with `_`, so `/` becomes `disk._root.usage` without clashing with a `/root` mount.
+pub fn write_space_metrics(disks: &[DiskSpace], snapshot: &mut MetricSnapshot) {
+    for disk in disks {
+        let name = metric_name_segment(&disk.mount);
+        let name = if name.is_empty() { "_root" } else { &name };
+        snapshot.set(format!("disk.{}.used", name), disk.used as f64);
+        snapshot.set(format!("disk.{}.total", name), disk.total as f64);
+        snapshot.set(format!("disk.{}.available", name), disk.available as f64);
+        snapshot.set(format!("disk.{}.usage", name), disk.usage() * 100.0);
+    }
+}
+
+impl Counters for DiskCounters {
+    fn key(&self) -> &str {
+        &self.device
+    }
+
+    fn values(&self) -> [u64; 2] {
+        [self.read_bytes, self.written_bytes]
+    }
+}
+
+/// Lists the fixed drives, like `C:\`, skipping removable and network drives.
+#[cfg(windows)]
+fn fixed_drives() -> Vec<Vec<u16>> {
+    let mut buffer = vec![0u16; 512];
+    let len = unsafe { GetLogicalDriveStringsW(Some(&mut buffer)) } as usize;
+    buffer.truncate(len.min(buffer.len()));
+
+    // The drives are separated by nulls, and each one is passed on null-terminated
+    buffer
+        .split(|c| *c == 0)
+        .filter(|drive| !drive.is_empty())
+        .map(|drive| drive.iter().copied().chain([0]).collect::<Vec<u16>>())
+        .filter(|drive| unsafe { GetDriveTypeW(PCWSTR(drive.as_ptr())) } == DRIVE_FIXED)
+        .collect()
+}
+
+/// Reads the space on each fixed drive, skipping any that can't be queried, like a BitLocker
+/// volume that's still locked.
+#[cfg(windows)]
+pub fn read_disk_space() -> Result<Vec<DiskSpace>> {
+    Ok(fixed_drives()
+        .into_iter()
+        .filter_map(|drive| {
+            let mut available = 0u64;
+            let mut total = 0u64;
+            let mut free = 0u64;
+            unsafe {
+                GetDiskFreeSpaceExW(
+                    PCWSTR(drive.as_ptr()),
+                    Some(&mut available),
+                    Some(&mut total),
+                    Some(&mut free),
+                )
+            }
+            .ok()?;
+
+            Some(DiskSpace {
+                mount: String::from_utf16_lossy(&drive[..drive.len() - 1]),
+                total,
+                used: total - free,
+                available,
+            })
+        })
+        .collect())
+}
+
+/// Reads the I/O counters of each fixed drive, like `C:`. Windows only keeps these for volumes
+/// that have had performance counters enabled, which is the default, so drives without them are
+/// left out.
+#[cfg(windows)]
+pub fn read_disk_counters() -> Result<Vec<DiskCounters>> {
+    let mut counters = vec![];
+    for drive in fixed_drives() {
+        // Volumes are opened like \\.\C:, without the trailing backslash
+        let device = String::from_utf16_lossy(&drive[..drive.len() - 1])
+            .trim_end_matches('\\')
+            .to_string();
+        let path: Vec<u16> = format!(r"\\.\{}", device)
+            .encode_utf16()
+            .chain([0])
+            .collect();
+
+        // No access rights are needed to query the counters
+        let Ok(handle) = (unsafe {
+            CreateFileW(
+                PCWSTR(path.as_ptr()),
+                0,
+                FILE_SHARE_READ | FILE_SHARE_WRITE,
+                None,
+                OPEN_EXISTING,
+                FILE_FLAGS_AND_ATTRIBUTES(0),
+                None,
+            )
+        }) else {
+            continue;
+        };
+
+        let mut performance = DISK_PERFORMANCE::default();
+        let result = unsafe {
+            DeviceIoControl(
+                handle,
+                IOCTL_DISK_PERFORMANCE,
+                None,
+                0,
+                Some(&mut performance as *mut _ as *mut _),
+                std::mem::size_of::<DISK_PERFORMANCE>() as u32,
+                None,
+                None,
+            )
+        };
+        unsafe { CloseHandle(handle) }?;
+
+        if result.is_ok() {
+            counters.push(DiskCounters {
+                device,
+                read_bytes: performance.BytesRead as u64,
+                written_bytes: performance.BytesWritten as u64,
+            });
+        }
+    }
+
+    Ok(counters)
+}
+
+/// Reads the space on each filesystem backed by a block device, skipping pseudo filesystems
+/// like `proc` and `tmpfs`, loop devices and repeated mounts of the same device.
+#[cfg(target_os = "linux")]
+pub fn read_disk_space() -> Result<Vec<DiskSpace>> {
+    let mounts = parse_mounts(&std::fs::read_to_string("/proc/mounts")?);
+
+    let mut seen_devices = std::collections::HashSet::new();
+    let mut disks = vec![];
+    for (device, mount) in mounts {
+        if !device.starts_with("/dev/")
+            || device.starts_with("/dev/loop")
+            || !seen_devices.insert(device)
+        {
+            continue;
+        }
+
+        let Ok(path) = std::ffi::CString::new(mount.as_str()) else {
+            continue;
+        };
+        let mut stats = unsafe { std::mem::zeroed::<libc::statvfs>() };
+        if unsafe { libc::statvfs(path.as_ptr(), &mut stats) } != 0 {
+            continue;
+        }
+
+        let block_size = stats.f_frsize as u64;
+        let total = stats.f_blocks as u64 * block_size;
+        disks.push(DiskSpace {
+            mount,
+            total,
+            used: total - stats.f_bfree as u64 * block_size,
+            available: stats.f_bavail as u64 * block_size,
+        });
+    }
+
+    Ok(disks)
+}
+
+/// Parses `/proc/mounts` into devices and mount points. Spaces and other special characters in
+/// mount points are escaped as octal, like `\040`.
+#[cfg(target_os = "linux")]
+fn parse_mounts(mounts: &str) -> Vec<(String, String)> {
+    let unescape = |field: &str| {
+        let mut unescaped = String::new();
+        let mut rest = field;
+        while let Some(idx) = rest.find('\\') {
+            unescaped.push_str(&rest[..idx]);
+            let code = rest
+                .get(idx + 1..idx + 4)
+                .and_then(|octal| u8::from_str_radix(octal, 8).ok());
+            match code {
+                Some(code) => {
+                    unescaped.push(code as char);
+                    rest = &rest[idx + 4..];
+                }
+                None => {
+                    unescaped.push('\\');
+                    rest = &rest[idx + 1..];
+                }
+            }
+        }
+        unescaped.push_str(rest);
+        unescaped
+    };
+
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            Some((unescape(fields.next()?), unescape(fields.next()?)))
+        })
+        .collect()
+}
+
+/// Reads the I/O counters of each physical disk, leaving out partitions, loop devices and
+/// device-mapper volumes, whose I/O is already counted on the disk beneath them.
+#[cfg(target_os = "linux")]
+pub fn read_disk_counters() -> Result<Vec<DiskCounters>> {
+    let counters = parse_diskstats(&std::fs::read_to_string("/proc/diskstats")?)?;
+    Ok(counters
+        .into_iter()
+        .filter(|disk| {
+            std::path::Path::new("/sys/block")
+                .join(&disk.device)
+                .join("device")
+                .exists()
+        })
+        .collect())
+}
+
+/// Parses `/proc/diskstats`, where each line has the device numbers and name followed by its
+/// counters. The third and seventh counters are the sectors read and written.
+#[cfg(target_os = "linux")]
+fn parse_diskstats(diskstats: &str) -> Result<Vec<DiskCounters>> {
+    diskstats
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let invalid = || ChipsError::InvalidSystemInfo(format!("diskstats line {:?}", line));
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 10 {
+                return Err(invalid());
+            }
+            let sectors = |idx: usize| fields[idx].parse::<u64>().map_err(|_| invalid());
+
+            Ok(DiskCounters {
+                device: fields[2].to_string(),
+                read_bytes: sectors(5)? * DISKSTATS_SECTOR_SIZE,
+                written_bytes: sectors(9)? * DISKSTATS_SECTOR_SIZE,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_metrics_after_mounts() {
+        let disks = [
+            DiskSpace {
+                mount: "/".to_string(),
+                total: 1000,
+                used: 250,
+                available: 700,
+            },
+            DiskSpace {
+                mount: "/root".to_string(),
+                total: 100,
+                used: 50,
+                available: 50,
+            },
+            DiskSpace {
+                mount: r"C:\".to_string(),
+                total: 0,
+                used: 0,
+                available: 0,
+            },
+            DiskSpace {
+                mount: "/mnt/Game Data".to_string(),
+                total: 10,
+                used: 10,
+                available: 0,
+            },
+        ];
+        let mut snapshot = MetricSnapshot::new();
+        write_space_metrics(&disks, &mut snapshot);
+
+        let number = |name: &str| snapshot.get(name).and_then(|value| value.as_number());
+        assert_eq!(number("disk._root.usage"), Some(25.0));
+        assert_eq!(number("disk._root.available"), Some(700.0));
+        assert_eq!(number("disk.root.usage"), Some(50.0));
+        assert_eq!(number("disk.c.usage"), Some(0.0));
+        assert_eq!(number("disk.mnt_game_data.usage"), Some(100.0));
+    }
+
+    #[test]
+    fn totals_all_disks() {
+        let counters = |device: &str| DiskCounters {
+            device: device.to_string(),
+            read_bytes: 0,
+            written_bytes: 0,
+        };
+        let throughput = DiskThroughput::from_rates(vec![
+            (counters("nvme0n1"), [2000.0, 500.0]),
+            (counters("C:"), [1000.0, 0.0]),
+        ]);
+        assert_eq!(
+            throughput.total,
+            DiskRate {
+                read: 3000.0,
+                write: 500.0
+            }
+        );
+
+        let mut snapshot = MetricSnapshot::new();
+        throughput.write_metrics(&mut snapshot);
+        let number = |name: &str| snapshot.get(name).and_then(|value| value.as_number());
+        assert_eq!(number("disk.nvme0n1.read"), Some(2000.0));
+        assert_eq!(number("disk.c.write"), Some(0.0));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn parses_proc_files() {
+        let mounts = "\
+/dev/nvme0n1p2 / ext4 rw,relatime 0 0
+proc /proc proc rw,nosuid,nodev,noexec,relatime 0 0
+/dev/sda1 /mnt/Game\\040Data ntfs3 rw,relatime 0 0
+";
+        assert_eq!(
+            parse_mounts(mounts),
+            vec![
+                ("/dev/nvme0n1p2".to_string(), "/".to_string()),
+                ("proc".to_string(), "/proc".to_string()),
+                ("/dev/sda1".to_string(), "/mnt/Game Data".to_string()),
+            ]
+        );
+
+        let diskstats = "\
+ 259       0 nvme0n1 129431 31203 10378262 24877 233213 142352 16601936 261389 0 116596 301548 0 0 0 0 13244 15280
+ 259       1 nvme0n1p1 313 1002 12362 51 2 0 2 0 0 60 51 0 0 0 0 0 0
+";
+        let counters = parse_diskstats(diskstats).unwrap();
+        assert_eq!(
+            counters[0],
+            DiskCounters {
+                device: "nvme0n1".to_string(),
+                read_bytes: 10378262 * 512,
+                written_bytes: 16601936 * 512,
+            }
+        );
+        assert_eq!(counters[1].device, "nvme0n1p1");
+        assert!(parse_diskstats("8 0 sda 1 2").is_err());
+    }
+}
@@ -0,0 +1,246 @@
+use std::str::FromStr;
+
+use image::RgbImage;
+
+use crate::color::{pack_rgb565, Color};
+use crate::device::Rect;
+use crate::errors::{ChipsError, Result};
+
+// Thresholds for a 4x4 ordered dither, in sixteenths of a quantization step
+const BAYER_4X4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+// Bits dropped from each channel when going from RGB888 to RGB565
+const CHANNEL_SHIFTS: [u32; 3] = [3, 2, 3];
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum DitherMode {
+    /// Truncate each channel to its high bits.
+    #[default]
+    None,
+    /// 4x4 Bayer ordered dithering. Cheap and stable between frames, which
+    /// makes it the better choice for content that changes often.
+    Bayer,
+    /// Floyd-Steinberg error diffusion. Smoothest gradients, but a change to
+    /// one pixel can shift the pattern of everything after it.
+    FloydSteinberg,
+}
+
+impl FromStr for DitherMode {
+    type Err = ChipsError;
+
+    /// Accepts `none`, `bayer` or `floyd-steinberg`.
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "none" => Ok(DitherMode::None),
+            "bayer" => Ok(DitherMode::Bayer),
+            "floyd-steinberg" => Ok(DitherMode::FloydSteinberg),
+            _ => Err(ChipsError::InvalidDitherMode(s.to_string())),
+        }
+    }
+}
+
+/// Converts an RGB888 image to RGB565 pixels in row-major order.
+pub fn image_to_rgb565(image: &RgbImage, mode: DitherMode) -> Vec<u16> {
+    match mode {
+        DitherMode::None => truncate(image),
+        DitherMode::Bayer => bayer(image),
+        DitherMode::FloydSteinberg => floyd_steinberg(image),
+    }
+}
+
+/// An image that's already been converted to RGB565, so that the same pixels can be drawn again.
+/// Converting part of an image a second time wouldn't give the same pixels with error diffusion,
+/// which depends on everything before each pixel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rgb565Image {
+    width: i32,
+    height: i32,
+    pixels: Vec<u16>,
+}
+
+impl Rgb565Image {
+    pub fn new(image: &RgbImage, mode: DitherMode) -> Self {
+        Self {
+            width: image.width() as i32,
+            height: image.height() as i32,
+            pixels: image_to_rgb565(image, mode),
+        }
+    }
+
+    /// The whole image, with its top-left corner at the origin.
+    pub fn rect(&self) -> Rect {
+        Rect::new(0, 0, self.width, self.height)
+    }
+
+    /// In row-major order.
+    pub fn pixels(&self) -> &[u16] {
+        &self.pixels
+    }
+
+    /// Copies out the pixels of an area, along with the area itself after clipping it to the
+    /// image. Returns `None` if none of the area is on the image.
+    pub fn crop(&self, rect: Rect) -> Option<(Rect, Vec<u16>)> {
+        let visible = rect.intersect(&self.rect())?;
+        let mut pixels = Vec::with_capacity((visible.width * visible.height) as usize);
+        for y in visible.y..visible.bottom() {
+            let row_start = (y * self.width + visible.x) as usize;
+            pixels.extend_from_slice(&self.pixels[row_start..row_start + visible.width as usize]);
+        }
+        Some((visible, pixels))
+    }
+}
+
+fn truncate(image: &RgbImage) -> Vec<u16> {
+    image
+        .pixels()
+        .map(|pixel| pack_rgb565(pixel.0[0], pixel.0[1], pixel.0[2]))
+        .collect()
+}
+
+fn bayer(image: &RgbImage) -> Vec<u16> {
+    let mut buf = Vec::with_capacity((image.width() * image.height()) as usize);
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let threshold = BAYER_4X4[(y & 3) as usize][(x & 3) as usize] as u32;
+
+        // Offsetting by a fraction of the step before truncating rounds up
+        // on a fixed share of pixels, which averages out to the true value.
+        let mut channels = [0u8; 3];
+        for (i, channel) in channels.iter_mut().enumerate() {
+            let step = 1u32 << CHANNEL_SHIFTS[i];
+            let value = pixel.0[i] as u32 + threshold * step / 16;
+            *channel = value.min(255) as u8;
+        }
+
+        buf.push(pack_rgb565(channels[0], channels[1], channels[2]));
+    }
+
+    buf
+}
+
+fn floyd_steinberg(image: &RgbImage) -> Vec<u16> {
+    let width = image.width() as usize;
+    let mut buf = Vec::with_capacity(width * image.height() as usize);
+
+    // Accumulated error for the current and next row, padded by one pixel on
+    // each side so the kernel never needs bounds checks.
+    let mut current = vec![[0i32; 3]; width + 2];
+    let mut next = vec![[0i32; 3]; width + 2];
+
+    for y in 0..image.height() {
+        for x in 0..width {
+            let pixel = image.get_pixel(x as u32, y);
+
+            // Errors are carried in sixteenths to keep the arithmetic integral
+            let mut wanted = [0i32; 3];
+            for (i, channel) in wanted.iter_mut().enumerate() {
+                *channel = (pixel.0[i] as i32 + current[x + 1][i] / 16).clamp(0, 255);
+            }
+
+            let pixel_16 = pack_rgb565(wanted[0] as u8, wanted[1] as u8, wanted[2] as u8);
+            let shown = Color::from_rgb565(pixel_16);
+            let errors = [
+                wanted[0] - shown.r() as i32,
+                wanted[1] - shown.g() as i32,
+                wanted[2] - shown.b() as i32,
+            ];
+
+            for i in 0..3 {
+                current[x + 2][i] += errors[i] * 7;
+                next[x][i] += errors[i] * 3;
+                next[x + 1][i] += errors[i] * 5;
+                next[x + 2][i] += errors[i];
+            }
+
+            buf.push(pixel_16);
+        }
+
+        std::mem::swap(&mut current, &mut next);
+        next.iter_mut().for_each(|error| *error = [0; 3]);
+    }
+
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgb;
+
+    use super::*;
+
+    /// Where every pixel is the same shade of red, between the first two RGB565 levels.
+    fn dark_red(width: u32, height: u32) -> RgbImage {
+        RgbImage::from_pixel(width, height, Rgb([4, 0, 0]))
+    }
+
+    const RED_1: u16 = 1 << 11;
+
+    #[test]
+    fn truncates_without_dithering() {
+        assert_eq!(
+            image_to_rgb565(&dark_red(4, 1), DitherMode::None),
+            vec![0; 4]
+        );
+    }
+
+    #[test]
+    fn bayer_rounds_up_on_a_fixed_pattern() {
+        // Half a step rounds up wherever the threshold is at least half, in the same places on
+        // every 4x4 block
+        assert_eq!(
+            image_to_rgb565(&dark_red(4, 2), DitherMode::Bayer),
+            vec![0, RED_1, 0, RED_1, RED_1, 0, RED_1, 0]
+        );
+        assert_eq!(
+            image_to_rgb565(&dark_red(8, 1), DitherMode::Bayer),
+            vec![0, RED_1, 0, RED_1, 0, RED_1, 0, RED_1]
+        );
+    }
+
+    #[test]
+    fn floyd_steinberg_carries_error_forward() {
+        // The error from the first three pixels adds up to light the last one
+        assert_eq!(
+            image_to_rgb565(&dark_red(2, 2), DitherMode::FloydSteinberg),
+            vec![0, 0, 0, RED_1]
+        );
+
+        // Colors that RGB565 can show exactly have no error to carry
+        let exact = RgbImage::from_pixel(3, 3, Rgb([8, 4, 8]));
+        assert_eq!(
+            image_to_rgb565(&exact, DitherMode::FloydSteinberg),
+            vec![pack_rgb565(8, 4, 8); 9]
+        );
+    }
+
+    #[test]
+    fn parses_modes() {
+        assert_eq!("bayer".parse::<DitherMode>().unwrap(), DitherMode::Bayer);
+        assert_eq!(
+            "floyd-steinberg".parse::<DitherMode>().unwrap(),
+            DitherMode::FloydSteinberg
+        );
+        assert!("ordered".parse::<DitherMode>().is_err());
+    }
+
+    #[test]
+    fn crops_within_image() {
+        let image = RgbImage::from_fn(4, 3, |x, y| Rgb([(x * 8) as u8, (y * 4) as u8, 0]));
+        let rgb565 = Rgb565Image::new(&image, DitherMode::None);
+        let pixel = |x: u32, y: u32| pack_rgb565((x * 8) as u8, (y * 4) as u8, 0);
+
+        assert_eq!(
+            rgb565.crop(Rect::new(1, 1, 2, 2)),
+            Some((
+                Rect::new(1, 1, 2, 2),
+                vec![pixel(1, 1), pixel(2, 1), pixel(1, 2), pixel(2, 2)]
+            ))
+        );
+        // Clipped to the image rather than wrapping around or panicking
+        assert_eq!(
+            rgb565.crop(Rect::new(-2, 2, 4, 5)),
+            Some((Rect::new(0, 2, 2, 1), vec![pixel(0, 2), pixel(1, 2)]))
+        );
+        assert_eq!(rgb565.crop(Rect::new(4, 0, 2, 2)), None);
+        assert_eq!(rgb565.crop(Rect::new(-5, -5, 3, 3)), None);
+    }
+}
@@ -0,0 +1,595 @@
+use std::collections::BTreeMap;
+
+use crate::color::Color;
+use crate::device::{ChipsDevice, Point, Rect};
+use crate::errors::Result;
+
+// Microseconds per byte on the wire, at 115200 baud with 10 bits per byte
+const BYTE_MICROS: i32 = 87;
+
+// A rectangle command is 12 bytes regardless of size
+const RECTANGLE_MICROS: i32 = 12 * BYTE_MICROS;
+
+// A pixel list command fits 28 points into 64 bytes, but is followed by a 5ms pause
+const PIXEL_MICROS: i32 = (64 * BYTE_MICROS + 5000) / 28;
+
+// Runs at least this long are quicker to send as rectangles
+const MIN_RECTANGLE_RUN: i32 = RECTANGLE_MICROS / PIXEL_MICROS + 1;
+
+// Pixel list coordinates are single bytes relative to an offset
+const PIXEL_WINDOW_SHIFT: i32 = 8;
+
+/// A horizontal run of pixels on row `y`, from `x0` to `x1` inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Span {
+    y: i32,
+    x0: i32,
+    x1: i32,
+}
+
+/// The pixels covered by a primitive, collected as spans so that overlapping parts of a shape
+/// are only sent once.
+#[derive(Debug, Default)]
+struct Shape {
+    spans: Vec<Span>,
+}
+
+impl Shape {
+    fn pixel(&mut self, x: i32, y: i32) {
+        self.span(y, x, x);
+    }
+
+    fn span(&mut self, y: i32, x0: i32, x1: i32) {
+        if x0 <= x1 {
+            self.spans.push(Span { y, x0, x1 });
+        }
+    }
+
+    /// Sorts, clips and merges the spans so that no two overlap or touch.
+    fn normalize(mut self, clip: Rect) -> Vec<Span> {
+        self.spans.sort();
+
+        let mut spans: Vec<Span> = Vec::with_capacity(self.spans.len());
+        for span in self.spans {
+            if span.y < clip.y || span.y >= clip.bottom() {
+                continue;
+            }
+
+            let span = Span {
+                y: span.y,
+                x0: span.x0.max(clip.x),
+                x1: span.x1.min(clip.right() - 1),
+            };
+            if span.x0 > span.x1 {
+                continue;
+            }
+
+            match spans.last_mut() {
+                Some(last) if last.y == span.y && span.x0 <= last.x1 + 1 => {
+                    last.x1 = last.x1.max(span.x1);
+                }
+                _ => spans.push(span),
+            }
+        }
+
+        spans
+    }
+}
+
+/// Splits normalized spans into the rectangles and loose pixels that are cheapest to send.
+fn encode(spans: &[Span]) -> (Vec<Rect>, Vec<Point>) {
+    let mut rects: Vec<Rect> = vec![];
+    let mut pixels: Vec<Point> = vec![];
+
+    // Long horizontal runs become rectangles, stacking identical runs on consecutive rows into
+    // a single taller rectangle. Open rectangles are keyed by their horizontal extent.
+    let mut open: BTreeMap<(i32, i32), Rect> = BTreeMap::new();
+    let mut current_row = i32::MIN;
+    for span in spans {
+        if span.y != current_row {
+            // Anything that didn't continue onto this row is finished
+            let continues = |rect: &Rect| rect.bottom() == span.y;
+            let (still_open, finished): (BTreeMap<_, _>, BTreeMap<_, _>) =
+                open.into_iter().partition(|(_, rect)| continues(rect));
+            rects.extend(finished.into_values());
+            open = still_open;
+            current_row = span.y;
+        }
+
+        let width = span.x1 - span.x0 + 1;
+        if width < MIN_RECTANGLE_RUN {
+            pixels.extend((span.x0..=span.x1).map(|x| Point::new(x, span.y)));
+            continue;
+        }
+
+        open.entry((span.x0, span.x1))
+            .and_modify(|rect| rect.height += 1)
+            .or_insert_with(|| Rect::new(span.x0, span.y, width, 1));
+    }
+    rects.extend(open.into_values());
+
+    // Of what's left, long vertical runs (the sides of outlines, mostly) also become rectangles
+    pixels.sort_by_key(|point| (point.x(), point.y()));
+    let mut loose: Vec<Point> = vec![];
+    let mut run_start = 0;
+    for i in 1..=pixels.len() {
+        let continues = i < pixels.len()
+            && pixels[i].x() == pixels[i - 1].x()
+            && pixels[i].y() == pixels[i - 1].y() + 1;
+        if continues {
+            continue;
+        }
+
+        let run = &pixels[run_start..i];
+        if run.len() as i32 >= MIN_RECTANGLE_RUN {
+            rects.push(Rect::new(run[0].x(), run[0].y(), 1, run.len() as i32));
+        } else {
+            loose.extend_from_slice(run);
+        }
+        run_start = i;
+    }
+
+    (rects, loose)
+}
+
+/// Draws primitives using the device's filled rectangle (136) and pixel list (195) commands,
+/// picking whichever is cheaper for each part of a shape. Angles are in degrees, clockwise from
+/// 12 o'clock.
+pub struct Canvas<'a> {
+    device: &'a mut ChipsDevice,
+}
+
+impl<'a> Canvas<'a> {
+    pub fn new(device: &'a mut ChipsDevice) -> Self {
+        Self { device }
+    }
+
+    /// Draws arbitrary pixels, such as rasterized text, merging runs into rectangles where
+    /// that's cheaper.
+    pub fn pixels(&mut self, points: &[Point], color: Color) -> Result<()> {
+        let mut shape = Shape::default();
+        for point in points {
+            shape.pixel(point.x(), point.y());
+        }
+
+        self.fill(shape, color)
+    }
+
+    pub fn line(&mut self, from: Point, to: Point, color: Color) -> Result<()> {
+        let mut shape = Shape::default();
+        bresenham(from, to, |x, y| shape.pixel(x, y));
+        self.fill(shape, color)
+    }
+
+    pub fn thick_line(
+        &mut self,
+        from: Point,
+        to: Point,
+        thickness: i32,
+        color: Color,
+    ) -> Result<()> {
+        if thickness <= 1 {
+            return self.line(from, to, color);
+        }
+
+        let dx = (to.x() - from.x()) as f32;
+        let dy = (to.y() - from.y()) as f32;
+        let length = (dx * dx + dy * dy).sqrt();
+        if length == 0.0 {
+            return self.fill_circle(from, thickness / 2, color);
+        }
+
+        // Offset both ends perpendicular to the line by half the thickness
+        let half = thickness as f32 / 2.0;
+        let nx = -dy / length * half;
+        let ny = dx / length * half;
+        let corner = |point: Point, sign: f32| {
+            Point::new(
+                (point.x() as f32 + nx * sign).round() as i32,
+                (point.y() as f32 + ny * sign).round() as i32,
+            )
+        };
+
+        self.fill_polygon(
+            &[
+                corner(from, 1.0),
+                corner(to, 1.0),
+                corner(to, -1.0),
+                corner(from, -1.0),
+            ],
+            color,
+        )
+    }
+
+    pub fn circle(&mut self, center: Point, radius: i32, color: Color) -> Result<()> {
+        let mut shape = Shape::default();
+
+        // Midpoint circle, mirrored into all eight octants
+        let (cx, cy) = (center.x(), center.y());
+        let (mut x, mut y) = (radius, 0);
+        let mut error = 1 - radius;
+        while x >= y {
+            for (px, py) in [
+                (x, y),
+                (y, x),
+                (-y, x),
+                (-x, y),
+                (-x, -y),
+                (-y, -x),
+                (y, -x),
+                (x, -y),
+            ] {
+                shape.pixel(cx + px, cy + py);
+            }
+
+            y += 1;
+            if error < 0 {
+                error += 2 * y + 1;
+            } else {
+                x -= 1;
+                error += 2 * (y - x) + 1;
+            }
+        }
+
+        self.fill(shape, color)
+    }
+
+    pub fn fill_circle(&mut self, center: Point, radius: i32, color: Color) -> Result<()> {
+        let mut shape = Shape::default();
+        for dy in -radius..=radius {
+            let dx = circle_extent(radius, dy);
+            shape.span(center.y() + dy, center.x() - dx, center.x() + dx);
+        }
+
+        self.fill(shape, color)
+    }
+
+    /// Draws a ring segment `thickness` pixels wide, with `radius` as its outer edge. The segment
+    /// runs clockwise from `start_angle` to `end_angle`, wrapping through 12 o'clock when the end
+    /// is the smaller angle.
+    pub fn arc(
+        &mut self,
+        center: Point,
+        radius: i32,
+        thickness: i32,
+        start_angle: f32,
+        end_angle: f32,
+        color: Color,
+    ) -> Result<()> {
+        let mut shape = Shape::default();
+        // Both edges are half a pixel out, matching fill_circle
+        let outer_sq = radius * radius + radius;
+        let inner = (radius - thickness).max(0);
+        let inner_sq = inner * inner + inner;
+        let sweep = arc_sweep(start_angle, end_angle);
+
+        for dy in -radius..=radius {
+            let mut run_start: Option<i32> = None;
+            for dx in -radius..=radius + 1 {
+                let distance_sq = dx * dx + dy * dy;
+                let inside = dx <= radius
+                    && distance_sq <= outer_sq
+                    && (inner == 0 || distance_sq > inner_sq)
+                    && angle_in_sweep(dx, dy, start_angle, sweep);
+
+                match (inside, run_start) {
+                    (true, None) => run_start = Some(dx),
+                    (false, Some(start)) => {
+                        shape.span(center.y() + dy, center.x() + start, center.x() + dx - 1);
+                        run_start = None;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        self.fill(shape, color)
+    }
+
+    pub fn fill_rectangle(&mut self, rect: Rect, color: Color) -> Result<()> {
+        let mut shape = Shape::default();
+        for y in rect.y..rect.bottom() {
+            shape.span(y, rect.x, rect.right() - 1);
+        }
+
+        self.fill(shape, color)
+    }
+
+    pub fn fill_rounded_rectangle(&mut self, rect: Rect, radius: i32, color: Color) -> Result<()> {
+        let mut shape = Shape::default();
+        for y in rect.y..rect.bottom() {
+            let (x0, x1) = rounded_row(rect, radius, y);
+            shape.span(y, x0, x1);
+        }
+
+        self.fill(shape, color)
+    }
+
+    /// Draws the border of a rounded rectangle, `thickness` pixels wide on the inside of `rect`.
+    /// A `radius` of 0 gives a plain outlined rectangle.
+    pub fn rounded_rectangle(
+        &mut self,
+        rect: Rect,
+        radius: i32,
+        thickness: i32,
+        color: Color,
+    ) -> Result<()> {
+        let inner = Rect::new(
+            rect.x + thickness,
+            rect.y + thickness,
+            rect.width - 2 * thickness,
+            rect.height - 2 * thickness,
+        );
+        let inner_radius = (radius - thickness).max(0);
+
+        let mut shape = Shape::default();
+        for y in rect.y..rect.bottom() {
+            let (x0, x1) = rounded_row(rect, radius, y);
+            if inner.is_empty() || y < inner.y || y >= inner.bottom() {
+                shape.span(y, x0, x1);
+                continue;
+            }
+
+            // The border is whatever the outer row covers that the inner row doesn't
+            let (inner_x0, inner_x1) = rounded_row(inner, inner_radius, y);
+            shape.span(y, x0, inner_x0 - 1);
+            shape.span(y, inner_x1 + 1, x1);
+        }
+
+        self.fill(shape, color)
+    }
+
+    /// Draws the outline of a closed polygon.
+    pub fn polygon(&mut self, points: &[Point], color: Color) -> Result<()> {
+        let mut shape = Shape::default();
+        for (i, from) in points.iter().enumerate() {
+            let to = points[(i + 1) % points.len()];
+            bresenham(*from, to, |x, y| shape.pixel(x, y));
+        }
+
+        self.fill(shape, color)
+    }
+
+    /// Fills a polygon using the even-odd rule, so self-intersecting polygons have holes.
+    pub fn fill_polygon(&mut self, points: &[Point], color: Color) -> Result<()> {
+        if points.len() < 3 {
+            return self.polygon(points, color);
+        }
+
+        let mut shape = Shape::default();
+        let top = points
+            .iter()
+            .map(|point| point.y())
+            .min()
+            .unwrap_or_default();
+        let bottom = points
+            .iter()
+            .map(|point| point.y())
+            .max()
+            .unwrap_or_default();
+
+        for y in top..=bottom {
+            // Sample through the middle of the row
+            let scan_y = y as f32 + 0.5;
+            let mut crossings: Vec<f32> = vec![];
+            for (i, a) in points.iter().enumerate() {
+                let b = points[(i + 1) % points.len()];
+                let (ay, by) = (a.y() as f32, b.y() as f32);
+                if (ay <= scan_y) != (by <= scan_y) {
+                    let t = (scan_y - ay) / (by - ay);
+                    crossings.push(a.x() as f32 + t * (b.x() - a.x()) as f32);
+                }
+            }
+
+            crossings.sort_by(|a, b| a.total_cmp(b));
+            for pair in crossings.chunks_exact(2) {
+                shape.span(y, pair[0].round() as i32, pair[1].round() as i32 - 1);
+            }
+        }
+
+        // Include the edges themselves so thin polygons don't disappear
+        for (i, from) in points.iter().enumerate() {
+            let to = points[(i + 1) % points.len()];
+            bresenham(*from, to, |x, y| shape.pixel(x, y));
+        }
+
+        self.fill(shape, color)
+    }
+
+    fn fill(&mut self, shape: Shape, color: Color) -> Result<()> {
+        let spans = shape.normalize(Rect::screen());
+        let (rects, pixels) = encode(&spans);
+
+        for rect in rects {
+            self.device.draw_rectangle(
+                rect.x,
+                rect.y,
+                rect.right() - 1,
+                rect.bottom() - 1,
+                color,
+            )?;
+        }
+
+        // Pixel lists can only address a 256x256 window at a time
+        let mut windows: BTreeMap<(i32, i32), Vec<Point>> = BTreeMap::new();
+        for pixel in pixels {
+            windows
+                .entry((
+                    pixel.x() >> PIXEL_WINDOW_SHIFT,
+                    pixel.y() >> PIXEL_WINDOW_SHIFT,
+                ))
+                .or_default()
+                .push(pixel);
+        }
+
+        for window in windows.values() {
+            self.device.draw_pixels(color, window)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn bresenham(from: Point, to: Point, mut plot: impl FnMut(i32, i32)) {
+    let (mut x, mut y) = (from.x(), from.y());
+    let dx = (to.x() - x).abs();
+    let dy = -(to.y() - y).abs();
+    let step_x = if x < to.x() { 1 } else { -1 };
+    let step_y = if y < to.y() { 1 } else { -1 };
+    let mut error = dx + dy;
+
+    loop {
+        plot(x, y);
+        if x == to.x() && y == to.y() {
+            break;
+        }
+
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+}
+
+/// Half the width of a filled circle's row at vertical offset `dy` from its center. The edge is
+/// placed half a pixel out so that the top and bottom rows aren't a single pixel wide.
+fn circle_extent(radius: i32, dy: i32) -> i32 {
+    ((radius * radius + radius - dy * dy) as f32)
+        .max(0.0)
+        .sqrt() as i32
+}
+
+/// The first and last column of a rounded rectangle's row.
+fn rounded_row(rect: Rect, radius: i32, y: i32) -> (i32, i32) {
+    let radius = radius.min(rect.width / 2).min(rect.height / 2).max(0);
+
+    // Distance into the corner's circle, if this row passes through one
+    let corner_dy = if y < rect.y + radius {
+        Some(rect.y + radius - y)
+    } else if y >= rect.bottom() - radius {
+        Some(y - (rect.bottom() - radius - 1))
+    } else {
+        None
+    };
+
+    let inset = match corner_dy {
+        Some(dy) => radius - circle_extent(radius, dy),
+        None => 0,
+    };
+
+    (rect.x + inset, rect.right() - 1 - inset)
+}
+
+/// The clockwise angle from `start_angle` to `end_angle`, where a difference of a whole turn or
+/// more is a full circle rather than nothing.
+fn arc_sweep(start_angle: f32, end_angle: f32) -> f32 {
+    let difference = end_angle - start_angle;
+    if difference.abs() >= 360.0 {
+        return 360.0;
+    }
+
+    difference.rem_euclid(360.0)
+}
+
+fn angle_in_sweep(dx: i32, dy: i32, start_angle: f32, sweep: f32) -> bool {
+    if sweep >= 360.0 {
+        return true;
+    }
+
+    // Clockwise from 12 o'clock, with y growing downwards
+    let angle = (dx as f32).atan2(-dy as f32).to_degrees();
+    (angle - start_angle).rem_euclid(360.0) <= sweep
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(y: i32, x0: i32, x1: i32) -> Span {
+        Span { y, x0, x1 }
+    }
+
+    #[test]
+    fn normalizes_spans() {
+        let mut shape = Shape::default();
+        shape.span(1, 5, 8);
+        shape.span(0, 0, 3);
+        // Overlapping and touching spans on the same row merge
+        shape.span(1, 0, 2);
+        shape.span(1, 3, 4);
+        shape.span(1, 7, 10);
+        // Clipped to the left and right edges, or dropped when outside
+        shape.span(2, -5, 20);
+        shape.span(3, 30, 40);
+        shape.span(-1, 0, 3);
+        shape.span(10, 0, 3);
+        // Empty spans are ignored
+        shape.span(0, 6, 5);
+
+        assert_eq!(
+            shape.normalize(Rect::new(0, 0, 16, 10)),
+            vec![span(0, 0, 3), span(1, 0, 10), span(2, 0, 15)]
+        );
+    }
+
+    #[test]
+    fn rectangle_runs_pay_for_the_pixel_pause() {
+        // Pausing after each pixel list makes even short runs quicker as rectangles, at about
+        // 1ms per rectangle against 0.38ms per pixel
+        assert_eq!((RECTANGLE_MICROS, PIXEL_MICROS), (1044, 377));
+        assert_eq!(MIN_RECTANGLE_RUN, 3);
+    }
+
+    #[test]
+    fn encodes_runs_as_rectangles() {
+        let long = MIN_RECTANGLE_RUN;
+        let spans = vec![
+            span(0, 0, long - 1),
+            span(0, 20, 20 + long - 2),
+            span(1, 0, long - 1),
+            span(2, 0, long),
+        ];
+
+        let (rects, pixels) = encode(&spans);
+        // Identical runs on consecutive rows stack into one rectangle
+        assert_eq!(
+            rects,
+            vec![Rect::new(0, 0, long, 2), Rect::new(0, 2, long + 1, 1)]
+        );
+        let short: Vec<Point> = (20..20 + long - 1).map(|x| Point::new(x, 0)).collect();
+        assert_eq!(pixels, short);
+    }
+
+    #[test]
+    fn encodes_vertical_runs_as_rectangles() {
+        let long = MIN_RECTANGLE_RUN;
+        let mut spans: Vec<Span> = (0..long).map(|y| span(y, 4, 4)).collect();
+        spans.push(span(long + 5, 4, 4));
+
+        let (rects, pixels) = encode(&spans);
+        assert_eq!(rects, vec![Rect::new(4, 0, 1, long)]);
+        assert_eq!(pixels, vec![Point::new(4, long + 5)]);
+    }
+
+    #[test]
+    fn normalizes_arc_sweeps() {
+        assert_eq!(arc_sweep(0.0, 90.0), 90.0);
+        // Reversed ends wrap clockwise through 12 o'clock
+        assert_eq!(arc_sweep(300.0, 60.0), 120.0);
+        assert_eq!(arc_sweep(-45.0, 45.0), 90.0);
+        assert_eq!(arc_sweep(90.0, 0.0), 270.0);
+        assert_eq!(arc_sweep(0.0, 0.0), 0.0);
+        assert_eq!(arc_sweep(0.0, 360.0), 360.0);
+        assert_eq!(arc_sweep(90.0, -270.0), 360.0);
+
+        // Straight up is in a sweep wrapping through it, and straight down isn't
+        let sweep = arc_sweep(300.0, 60.0);
+        assert!(angle_in_sweep(0, -5, 300.0, sweep));
+        assert!(!angle_in_sweep(0, 5, 300.0, sweep));
+    }
+}
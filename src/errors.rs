@@ -12,8 +12,48 @@ pub enum ChipsError {
     InvalidLength { received: usize, expected: usize },
     #[error("invalid image")]
     InvalidImage(#[from] image::ImageError),
-    #[error("image too large for screen")]
-    ImageTooLarge,
+    #[error("invalid color {0:?}")]
+    InvalidColor(String),
+    #[error("invalid template: {0}")]
+    InvalidTemplate(String),
+    #[error("invalid dither mode {0:?}")]
+    InvalidDitherMode(String),
+    #[error("invalid fit mode {0:?}")]
+    InvalidFitMode(String),
+    #[error("invalid time format {0:?}")]
+    InvalidTimeFormat(String),
+    #[error("invalid GPU selector {0:?}")]
+    InvalidGpuSelector(String),
+    #[error("invalid sensor mapping {0:?}")]
+    InvalidSensorMapping(String),
+    #[error("metric {0:?} is registered more than once")]
+    DuplicateMetric(String),
+    #[error("invalid source config: {0}")]
+    InvalidSourceConfig(String),
+    #[error("invalid URL {0:?}")]
+    InvalidUrl(String),
+    #[error("http error: {0}")]
+    Http(String),
+    #[error("command failed: {0}")]
+    CommandFailed(String),
+    #[error("timed out")]
+    TimedOut,
+    #[error("invalid json")]
+    Json(#[from] serde_json::Error),
+    #[error("no value at {0:?}")]
+    MissingValue(String),
+    #[error("invalid Prometheus exposition line {0:?}")]
+    InvalidExposition(String),
+    #[error("mqtt error: {0}")]
+    Mqtt(String),
+    #[error("invalid command: {0}")]
+    InvalidCommand(String),
+    #[error("forbidden: {0}")]
+    Forbidden(String),
+    #[error("unexpected system info: {0}")]
+    InvalidSystemInfo(String),
+    #[error("invalid image tiling {width}x{height}")]
+    InvalidImageTiling { width: i32, height: i32 },
     #[error("coordinate bounds too large for screen")]
     BoundsTooLarge,
     #[error("nvml error")]
@@ -23,3 +63,15 @@ pub enum ChipsError {
 }
 
 pub type Result<T, E = ChipsError> = std::result::Result<T, E>;
+
+/// Describes an error along with its causes, which are often more telling than the error itself,
+/// like "io error: No such file or directory (os error 2)".
+pub fn error_message(err: &ChipsError) -> String {
+    let mut message = err.to_string();
+    let mut source = std::error::Error::source(err);
+    while let Some(err) = source {
+        message = format!("{}: {}", message, err);
+        source = err.source();
+    }
+    message
+}
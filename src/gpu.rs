@@ -0,0 +1,619 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use nvml_wrapper::enum_wrappers::device::{Clock, TemperatureSensor};
+use nvml_wrapper::Nvml;
+use once_cell::sync::Lazy;
+
+use crate::errors::{ChipsError, Result};
+use crate::metrics::MetricSnapshot;
+
+static NVML: Lazy<Option<Nvml>> = Lazy::new(|| match Nvml::init() {
+    Err(err) => {
+        println!("{}", err);
+        None
+    }
+    Ok(nvml) => Some(nvml),
+});
+
+const PCI_VENDOR_AMD: &str = "0x1002";
+const PCI_VENDOR_INTEL: &str = "0x8086";
+const PCI_VENDOR_NVIDIA: &str = "0x10de";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GpuVendor {
+    Nvidia,
+    Amd,
+    Intel,
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GpuInfo {
+    /// The position of the GPU across all providers, in the order they were added.
+    pub index: usize,
+    pub name: String,
+    pub uuid: Option<String>,
+    pub vendor: GpuVendor,
+    /// Identifies the GPU within its provider, such as an NVML index or a sysfs path.
+    pub device_id: String,
+}
+
+/// A reading of a GPU's metrics. Anything the driver doesn't report is left as `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GpuSample {
+    /// Ratio of time the GPU was busy.
+    pub usage: Option<f64>,
+    pub temperature_celsius: Option<f64>,
+    pub memory_used: Option<u64>,
+    pub memory_total: Option<u64>,
+    /// Ratio of time the memory was being read or written.
+    pub memory_utilization: Option<f64>,
+    pub power_watts: Option<f64>,
+    /// The power limit the driver is currently enforcing.
+    pub power_limit_watts: Option<f64>,
+    pub core_clock_mhz: Option<u32>,
+    pub memory_clock_mhz: Option<u32>,
+    /// Ratio of the first fan's maximum speed.
+    pub fan_speed: Option<f64>,
+    /// Ratio of time the video encoder was busy.
+    pub encoder_usage: Option<f64>,
+    /// Ratio of time the video decoder was busy.
+    pub decoder_usage: Option<f64>,
+}
+
+impl GpuSample {
+    /// Sets each reported metric in the snapshot, named like `gpu.temperature`. Ratios are set as
+    /// percentages, like the other usage metrics.
+    ///
+    /// | Metric | Unit |
+    /// | --- | --- |
+    /// | `gpu.usage` | % |
+    /// | `gpu.temperature` | °C |
+    /// | `gpu.memory.used`, `gpu.memory.total` | bytes |
+    /// | `gpu.memory.usage` | % of total memory used |
+    /// | `gpu.memory.utilization` | % of time busy |
+    /// | `gpu.power`, `gpu.power.limit` | W |
+    /// | `gpu.power.usage` | % of the power limit |
+    /// | `gpu.clock.core`, `gpu.clock.memory` | MHz |
+    /// | `gpu.fan` | % |
+    /// | `gpu.encoder`, `gpu.decoder` | % |
+    pub fn write_metrics(&self, snapshot: &mut MetricSnapshot) {
+        let percent = |ratio: Option<f64>| ratio.map(|ratio| ratio * 100.0);
+        let ratio = |used: Option<f64>, total: Option<f64>| match (used, total) {
+            (Some(used), Some(total)) if total > 0.0 => Some(used / total),
+            _ => None,
+        };
+        let memory_used = self.memory_used.map(|bytes| bytes as f64);
+        let memory_total = self.memory_total.map(|bytes| bytes as f64);
+
+        let metrics = [
+            ("gpu.usage", percent(self.usage)),
+            ("gpu.temperature", self.temperature_celsius),
+            ("gpu.memory.used", memory_used),
+            ("gpu.memory.total", memory_total),
+            (
+                "gpu.memory.usage",
+                percent(ratio(memory_used, memory_total)),
+            ),
+            ("gpu.memory.utilization", percent(self.memory_utilization)),
+            ("gpu.power", self.power_watts),
+            ("gpu.power.limit", self.power_limit_watts),
+            (
+                "gpu.power.usage",
+                percent(ratio(self.power_watts, self.power_limit_watts)),
+            ),
+            ("gpu.clock.core", self.core_clock_mhz.map(f64::from)),
+            ("gpu.clock.memory", self.memory_clock_mhz.map(f64::from)),
+            ("gpu.fan", percent(self.fan_speed)),
+            ("gpu.encoder", percent(self.encoder_usage)),
+            ("gpu.decoder", percent(self.decoder_usage)),
+        ];
+        for (name, value) in metrics {
+            if let Some(value) = value {
+                snapshot.set(name, value);
+            }
+        }
+    }
+}
+
+/// A source of GPUs and their metrics, such as a vendor library or the kernel's sysfs.
+pub trait GpuProvider {
+    /// Lists the GPUs this provider can read. The `index` of each is assigned by [`Gpus`].
+    fn gpus(&self) -> Result<Vec<GpuInfo>>;
+
+    fn sample(&self, gpu: &GpuInfo) -> Result<GpuSample>;
+}
+
+/// Picks one GPU out of all the detected ones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GpuSelector {
+    Index(usize),
+    /// Matches a case-insensitive substring of the name, like "rtx 4090" or "radeon".
+    Name(String),
+    Uuid(String),
+}
+
+impl Default for GpuSelector {
+    fn default() -> Self {
+        GpuSelector::Index(0)
+    }
+}
+
+impl GpuSelector {
+    fn matches(&self, gpu: &GpuInfo) -> bool {
+        match self {
+            GpuSelector::Index(index) => gpu.index == *index,
+            GpuSelector::Name(name) => gpu.name.to_lowercase().contains(&name.to_lowercase()),
+            GpuSelector::Uuid(uuid) => gpu.uuid.as_ref().is_some_and(|gpu_uuid| {
+                strip_gpu_prefix(gpu_uuid).eq_ignore_ascii_case(strip_gpu_prefix(uuid))
+            }),
+        }
+    }
+}
+
+impl FromStr for GpuSelector {
+    type Err = ChipsError;
+
+    /// Parses a number as an index, `GPU-...` (as NVML formats them) or a bare UUID as a UUID,
+    /// and anything else as a name.
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(ChipsError::InvalidGpuSelector(s.to_string()));
+        }
+
+        if let Ok(index) = s.parse::<usize>() {
+            return Ok(GpuSelector::Index(index));
+        }
+
+        let is_uuid = |uuid: &str| {
+            let groups: Vec<&str> = uuid.split('-').collect();
+            groups.iter().map(|group| group.len()).eq([8, 4, 4, 4, 12])
+                && groups
+                    .iter()
+                    .all(|group| group.chars().all(|c| c.is_ascii_hexdigit()))
+        };
+        if is_uuid(strip_gpu_prefix(s)) {
+            return Ok(GpuSelector::Uuid(s.to_string()));
+        }
+
+        Ok(GpuSelector::Name(s.to_string()))
+    }
+}
+
+/// Removes the `GPU-` prefix NVML puts in front of UUIDs, so both forms compare equal.
+fn strip_gpu_prefix(uuid: &str) -> &str {
+    uuid.get(..4)
+        .filter(|prefix| prefix.eq_ignore_ascii_case("GPU-"))
+        .map(|_| &uuid[4..])
+        .unwrap_or(uuid)
+}
+
+/// All GPUs found by a set of providers, enumerated once on creation.
+pub struct Gpus {
+    providers: Vec<Box<dyn GpuProvider>>,
+    gpus: Vec<(usize, GpuInfo)>,
+}
+
+impl Gpus {
+    /// Uses NVML for NVIDIA GPUs when it's available, and sysfs for everything else on Linux.
+    pub fn detect() -> Self {
+        let mut providers: Vec<Box<dyn GpuProvider>> = vec![];
+        if let Some(nvml) = NvmlProvider::new() {
+            providers.push(Box::new(nvml));
+        }
+        providers.push(Box::new(SysfsGpuProvider::new()));
+
+        Self::new(providers)
+    }
+
+    pub fn new(providers: Vec<Box<dyn GpuProvider>>) -> Self {
+        let mut gpus = vec![];
+        for (provider_idx, provider) in providers.iter().enumerate() {
+            match provider.gpus() {
+                Ok(provider_gpus) => {
+                    for mut gpu in provider_gpus {
+                        gpu.index = gpus.len();
+                        gpus.push((provider_idx, gpu));
+                    }
+                }
+                Err(err) => println!("{:?}", err),
+            }
+        }
+
+        Self { providers, gpus }
+    }
+
+    pub fn list(&self) -> impl Iterator<Item = &GpuInfo> {
+        self.gpus.iter().map(|(_, gpu)| gpu)
+    }
+
+    pub fn select(&self, selector: &GpuSelector) -> Option<&GpuInfo> {
+        self.list().find(|gpu| selector.matches(gpu))
+    }
+
+    /// Samples the selected GPU, or returns `None` if no GPU matches.
+    pub fn sample(&self, selector: &GpuSelector) -> Result<Option<GpuSample>> {
+        let Some((provider_idx, gpu)) = self.gpus.iter().find(|(_, gpu)| selector.matches(gpu))
+        else {
+            return Ok(None);
+        };
+
+        self.providers[*provider_idx].sample(gpu).map(Some)
+    }
+}
+
+/// NVIDIA GPUs, through NVML.
+pub struct NvmlProvider {
+    nvml: &'static Nvml,
+}
+
+impl NvmlProvider {
+    /// Returns `None` if NVML couldn't be loaded, e.g. because no NVIDIA driver is installed.
+    pub fn new() -> Option<Self> {
+        NVML.as_ref().map(|nvml| Self { nvml })
+    }
+}
+
+impl GpuProvider for NvmlProvider {
+    fn gpus(&self) -> Result<Vec<GpuInfo>> {
+        (0..self.nvml.device_count()?)
+            .map(|idx| {
+                let device = self.nvml.device_by_index(idx)?;
+                Ok(GpuInfo {
+                    index: idx as usize,
+                    name: device.name()?,
+                    uuid: device.uuid().ok(),
+                    vendor: GpuVendor::Nvidia,
+                    device_id: idx.to_string(),
+                })
+            })
+            .collect()
+    }
+
+    fn sample(&self, gpu: &GpuInfo) -> Result<GpuSample> {
+        let idx = gpu
+            .device_id
+            .parse::<u32>()
+            .map_err(|_| ChipsError::InvalidGpuSelector(gpu.device_id.clone()))?;
+        let device = self.nvml.device_by_index(idx)?;
+
+        // Not every query is supported on every card, e.g. laptop GPUs have no fans of their own,
+        // so each one is optional on its own
+        let percent = |percent: u32| percent as f64 / 100.0;
+        let watts = |milliwatts: u32| milliwatts as f64 / 1000.0;
+        let utilization = device.utilization_rates().ok();
+        let memory = device.memory_info().ok();
+        Ok(GpuSample {
+            usage: utilization.as_ref().map(|rates| percent(rates.gpu)),
+            temperature_celsius: device
+                .temperature(TemperatureSensor::Gpu)
+                .ok()
+                .map(|celsius| celsius as f64),
+            memory_used: memory.as_ref().map(|memory| memory.used),
+            memory_total: memory.as_ref().map(|memory| memory.total),
+            memory_utilization: utilization.as_ref().map(|rates| percent(rates.memory)),
+            power_watts: device.power_usage().ok().map(watts),
+            power_limit_watts: device.enforced_power_limit().ok().map(watts),
+            core_clock_mhz: device.clock_info(Clock::Graphics).ok(),
+            memory_clock_mhz: device.clock_info(Clock::Memory).ok(),
+            fan_speed: device.fan_speed(0).ok().map(percent),
+            encoder_usage: device
+                .encoder_utilization()
+                .ok()
+                .map(|info| percent(info.utilization)),
+            decoder_usage: device
+                .decoder_utilization()
+                .ok()
+                .map(|info| percent(info.utilization)),
+        })
+    }
+}
+
+/// AMD and Intel GPUs, through the DRM devices in sysfs on Linux. NVIDIA GPUs are left to NVML,
+/// since their driver doesn't report anything here. Which metrics are available depends on the
+/// driver; `amdgpu` reports all of them, while `i915` only has temperatures on some cards.
+pub struct SysfsGpuProvider {
+    root: PathBuf,
+}
+
+impl SysfsGpuProvider {
+    pub fn new() -> Self {
+        Self::with_root("/sys/class/drm")
+    }
+
+    /// Reads devices from somewhere other than `/sys/class/drm`.
+    pub fn with_root(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl GpuProvider for SysfsGpuProvider {
+    fn gpus(&self) -> Result<Vec<GpuInfo>> {
+        // Nothing to report on systems without sysfs
+        let Ok(entries) = fs::read_dir(&self.root) else {
+            return Ok(vec![]);
+        };
+
+        // Cards are named like card0, and their outputs like card0-DP-1
+        let mut cards: Vec<(u32, PathBuf)> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let number = name.strip_prefix("card")?.parse::<u32>().ok()?;
+                Some((number, entry.path()))
+            })
+            .collect();
+        cards.sort();
+
+        let mut gpus = vec![];
+        for (number, path) in cards {
+            let device = path.join("device");
+            let vendor = match read_trimmed(&device.join("vendor")).as_deref() {
+                Some(PCI_VENDOR_AMD) => GpuVendor::Amd,
+                Some(PCI_VENDOR_INTEL) => GpuVendor::Intel,
+                Some(PCI_VENDOR_NVIDIA) | None => continue,
+                Some(_) => GpuVendor::Other,
+            };
+
+            let name = read_trimmed(&device.join("product_name")).unwrap_or_else(|| {
+                let vendor_name = match vendor {
+                    GpuVendor::Amd => "AMD",
+                    GpuVendor::Intel => "Intel",
+                    _ => "Unknown",
+                };
+                format!("{} GPU (card{})", vendor_name, number)
+            });
+
+            gpus.push(GpuInfo {
+                index: gpus.len(),
+                name,
+                uuid: read_trimmed(&device.join("unique_id")),
+                vendor,
+                device_id: device.to_string_lossy().into_owned(),
+            });
+        }
+
+        Ok(gpus)
+    }
+
+    fn sample(&self, gpu: &GpuInfo) -> Result<GpuSample> {
+        let device = Path::new(&gpu.device_id);
+        let read_number = |path: &Path| read_trimmed(path)?.parse::<u64>().ok();
+
+        let percent = |path: &Path| read_number(path).map(|percent| percent as f64 / 100.0);
+
+        // The driver's sensors, in the usual hwmon units: millidegrees, microwatts and hertz. The
+        // first temperature is the edge (or package) temperature, and on amdgpu the first and
+        // second clocks are the core and memory clocks.
+        let hwmon = fs::read_dir(device.join("hwmon")).ok().and_then(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .min()
+        });
+        let read_hwmon = |name: &str| read_number(&hwmon.as_ref()?.join(name));
+        let mhz = |name: &str| read_hwmon(name).map(|hertz| (hertz / 1_000_000) as u32);
+        let watts = |name: &str| read_hwmon(name).map(|microwatts| microwatts as f64 / 1e6);
+
+        let fan_speed = match (read_hwmon("pwm1"), read_hwmon("pwm1_max").unwrap_or(255)) {
+            (Some(pwm), max) if max > 0 => Some(pwm as f64 / max as f64),
+            _ => None,
+        };
+
+        Ok(GpuSample {
+            usage: percent(&device.join("gpu_busy_percent")),
+            temperature_celsius: read_hwmon("temp1_input")
+                .map(|millidegrees| millidegrees as f64 / 1000.0),
+            memory_used: read_number(&device.join("mem_info_vram_used")),
+            memory_total: read_number(&device.join("mem_info_vram_total")),
+            memory_utilization: percent(&device.join("mem_busy_percent")),
+            // Newer kernels report the instantaneous power instead of an average
+            power_watts: watts("power1_average").or_else(|| watts("power1_input")),
+            power_limit_watts: watts("power1_cap"),
+            core_clock_mhz: mhz("freq1_input"),
+            memory_clock_mhz: mhz("freq2_input"),
+            fan_speed,
+            // Video engine usage isn't reported through sysfs
+            encoder_usage: None,
+            decoder_usage: None,
+        })
+    }
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|contents| contents.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Serves a fixed list of GPUs, each sampled with its usage set to its position in the list.
+    struct FakeGpuProvider {
+        gpus: Vec<(&'static str, Option<&'static str>)>,
+    }
+
+    impl GpuProvider for FakeGpuProvider {
+        fn gpus(&self) -> Result<Vec<GpuInfo>> {
+            Ok(self
+                .gpus
+                .iter()
+                .enumerate()
+                .map(|(idx, (name, uuid))| GpuInfo {
+                    index: idx,
+                    name: name.to_string(),
+                    uuid: uuid.map(str::to_string),
+                    vendor: GpuVendor::Other,
+                    device_id: idx.to_string(),
+                })
+                .collect())
+        }
+
+        fn sample(&self, gpu: &GpuInfo) -> Result<GpuSample> {
+            Ok(GpuSample {
+                usage: Some(gpu.device_id.parse::<f64>().unwrap()),
+                ..GpuSample::default()
+            })
+        }
+    }
+
+    fn fake_gpus() -> Gpus {
+        Gpus::new(vec![
+            Box::new(FakeGpuProvider {
+                gpus: vec![(
+                    "NVIDIA GeForce RTX 4090",
+                    Some("GPU-8f3c2a1e-55b1-4c2e-9d1a-0123456789ab"),
+                )],
+            }),
+            Box::new(FakeGpuProvider { gpus: vec![] }),
+            Box::new(FakeGpuProvider {
+                gpus: vec![("AMD GPU (card1)", None), ("Radeon RX 7900 XTX", None)],
+            }),
+        ])
+    }
+
+    #[test]
+    fn indexes_continue_across_providers() {
+        let gpus = fake_gpus();
+        let names: Vec<(usize, &str)> = gpus
+            .list()
+            .map(|gpu| (gpu.index, gpu.name.as_str()))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                (0, "NVIDIA GeForce RTX 4090"),
+                (1, "AMD GPU (card1)"),
+                (2, "Radeon RX 7900 XTX"),
+            ]
+        );
+    }
+
+    #[test]
+    fn samples_come_from_the_owning_provider() {
+        let gpus = fake_gpus();
+        let usage = |selector: GpuSelector| gpus.sample(&selector).unwrap().unwrap().usage;
+        assert_eq!(usage(GpuSelector::Index(0)), Some(0.0));
+        assert_eq!(usage(GpuSelector::Index(2)), Some(1.0));
+        assert_eq!(gpus.sample(&GpuSelector::Index(3)).unwrap(), None);
+    }
+
+    #[test]
+    fn selects_by_name_and_uuid() {
+        let gpus = fake_gpus();
+        let index = |selector: &str| gpus.select(&selector.parse().unwrap()).map(|gpu| gpu.index);
+        assert_eq!(index("1"), Some(1));
+        assert_eq!(index("radeon"), Some(2));
+        assert_eq!(index("rtx 4090"), Some(0));
+        assert_eq!(index("GPU-8F3C2A1E-55B1-4C2E-9D1A-0123456789AB"), Some(0));
+        // NVML's UUIDs have a prefix that bare UUIDs don't
+        assert_eq!(index("8f3c2a1e-55b1-4c2e-9d1a-0123456789ab"), Some(0));
+        assert_eq!(index("gpu-8f3c2a1e-55b1-4c2e-9d1a-0123456789ab"), Some(0));
+        assert_eq!(index("00000000-55b1-4c2e-9d1a-0123456789ab"), None);
+        assert_eq!(index("intel"), None);
+    }
+
+    #[test]
+    fn parses_selectors() {
+        assert_eq!("2".parse::<GpuSelector>().unwrap(), GpuSelector::Index(2));
+        assert_eq!(
+            "GPU-8f3c2a1e-55b1-4c2e-9d1a-0123456789ab"
+                .parse::<GpuSelector>()
+                .unwrap(),
+            GpuSelector::Uuid("GPU-8f3c2a1e-55b1-4c2e-9d1a-0123456789ab".to_string())
+        );
+        assert_eq!(
+            "8f3c2a1e-55b1-4c2e-9d1a-0123456789ab"
+                .parse::<GpuSelector>()
+                .unwrap(),
+            GpuSelector::Uuid("8f3c2a1e-55b1-4c2e-9d1a-0123456789ab".to_string())
+        );
+        assert_eq!(
+            "RTX 4090".parse::<GpuSelector>().unwrap(),
+            GpuSelector::Name("RTX 4090".to_string())
+        );
+        assert!(" ".parse::<GpuSelector>().is_err());
+    }
+
+    #[test]
+    fn reads_amd_gpus_from_sysfs() {
+        let root = std::env::temp_dir().join(format!("chips-sysfs-gpu-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        let write = |path: &str, contents: &str| {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        };
+        write("card0/device/vendor", "0x10de\n");
+        write("card1/device/vendor", "0x1002\n");
+        write("card1/device/gpu_busy_percent", "42\n");
+        write("card1/device/mem_info_vram_used", "1073741824\n");
+        write("card1/device/mem_info_vram_total", "8589934592\n");
+        write("card1/device/mem_busy_percent", "7\n");
+        write("card1/device/hwmon/hwmon3/temp1_input", "55000\n");
+        write("card1/device/hwmon/hwmon3/power1_average", "151000000\n");
+        write("card1/device/hwmon/hwmon3/power1_cap", "302000000\n");
+        write("card1/device/hwmon/hwmon3/freq1_input", "2105000000\n");
+        write("card1/device/hwmon/hwmon3/freq2_input", "1250000000\n");
+        write("card1/device/hwmon/hwmon3/pwm1", "51\n");
+        write("card1-DP-1/status", "connected\n");
+        write("card2/device/vendor", "0x8086\n");
+
+        let provider = SysfsGpuProvider::with_root(&root);
+        let gpus = provider.gpus().unwrap();
+        let names: Vec<&str> = gpus.iter().map(|gpu| gpu.name.as_str()).collect();
+        assert_eq!(names, vec!["AMD GPU (card1)", "Intel GPU (card2)"]);
+
+        let sample = provider.sample(&gpus[0]).unwrap();
+        assert_eq!(
+            sample,
+            GpuSample {
+                usage: Some(0.42),
+                temperature_celsius: Some(55.0),
+                memory_used: Some(1 << 30),
+                memory_total: Some(8 << 30),
+                memory_utilization: Some(0.07),
+                power_watts: Some(151.0),
+                power_limit_watts: Some(302.0),
+                core_clock_mhz: Some(2105),
+                memory_clock_mhz: Some(1250),
+                fan_speed: Some(0.2),
+                encoder_usage: None,
+                decoder_usage: None,
+            }
+        );
+        assert_eq!(provider.sample(&gpus[1]).unwrap(), GpuSample::default());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn writes_reported_metrics() {
+        let sample = GpuSample {
+            usage: Some(0.5),
+            memory_used: Some(2 << 30),
+            memory_total: Some(8 << 30),
+            power_watts: Some(150.0),
+            power_limit_watts: Some(300.0),
+            core_clock_mhz: Some(2100),
+            ..GpuSample::default()
+        };
+        let mut snapshot = MetricSnapshot::new();
+        sample.write_metrics(&mut snapshot);
+
+        let number = |name: &str| snapshot.get(name).and_then(|value| value.as_number());
+        assert_eq!(number("gpu.usage"), Some(50.0));
+        assert_eq!(number("gpu.memory.usage"), Some(25.0));
+        assert_eq!(number("gpu.power"), Some(150.0));
+        assert_eq!(number("gpu.power.usage"), Some(50.0));
+        assert_eq!(number("gpu.clock.core"), Some(2100.0));
+        assert_eq!(number("gpu.temperature"), None);
+        assert_eq!(number("gpu.fan"), None);
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::VecDeque;
+
+/// The most recent values of a metric, oldest first, for drawing graphs.
+#[derive(Debug, Clone)]
+pub struct History {
+    values: VecDeque<f64>,
+    capacity: usize,
+}
+
+impl History {
+    /// Keeps at least one value, since a graph of nothing has nothing to show.
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            values: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Adds a value, dropping the oldest one if the history is full.
+    pub fn push(&mut self, value: f64) {
+        if self.values.len() >= self.capacity {
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = f64> + '_ {
+        self.values.iter().copied()
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        self.iter().reduce(f64::max)
+    }
+
+    /// Picks a round upper bound for the graph, so that the scale only changes when the values
+    /// outgrow it. It's never below `min`, which keeps a quiet graph from magnifying noise.
+    pub fn auto_scale(&self, min: f64) -> f64 {
+        nice_ceiling(self.max().unwrap_or(0.0).max(min))
+    }
+
+    /// Scales the values to bar heights for [`crate::device::ChipsDevice::draw_line_graph`] and
+    /// friends, where `scale` maps to `height`. The result is always `capacity` long, padded with
+    /// zeros at the start until the history fills up, so the newest value is on the right.
+    pub fn graph_data(&self, height: u8, scale: f64) -> Vec<u8> {
+        let padding = self.capacity.saturating_sub(self.values.len());
+        std::iter::repeat_n(0, padding)
+            .chain(self.iter().map(|value| {
+                if scale <= 0.0 {
+                    return 0;
+                }
+                (value / scale * height as f64)
+                    .round()
+                    .clamp(0.0, height as f64) as u8
+            }))
+            .collect()
+    }
+}
+
+/// Rounds up to the nearest 1, 2 or 5 times a power of 10.
+fn nice_ceiling(value: f64) -> f64 {
+    if value <= 0.0 || !value.is_finite() {
+        return 1.0;
+    }
+
+    let magnitude = 10f64.powf(value.log10().floor());
+    [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|step| step * magnitude)
+        .find(|ceiling| *ceiling >= value)
+        .unwrap_or(10.0 * magnitude)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_oldest_values() {
+        let mut history = History::new(3);
+        for value in [1.0, 2.0, 3.0, 4.0] {
+            history.push(value);
+        }
+        assert_eq!(history.iter().collect::<Vec<_>>(), vec![2.0, 3.0, 4.0]);
+        assert_eq!(history.max(), Some(4.0));
+    }
+
+    #[test]
+    fn scales_to_round_numbers() {
+        let mut history = History::new(4);
+        assert_eq!(history.auto_scale(1000.0), 1000.0);
+
+        history.push(1300.0);
+        assert_eq!(history.auto_scale(1000.0), 2000.0);
+        history.push(4100.0);
+        assert_eq!(history.auto_scale(1000.0), 5000.0);
+        history.push(6000.0);
+        assert_eq!(history.auto_scale(1000.0), 10000.0);
+    }
+
+    #[test]
+    fn pads_graph_data() {
+        let mut history = History::new(4);
+        history.push(50.0);
+        history.push(250.0);
+        assert_eq!(history.graph_data(100, 200.0), vec![0, 0, 25, 100]);
+    }
+
+    #[test]
+    fn keeps_one_value_without_capacity() {
+        let mut history = History::new(0);
+        history.push(1.0);
+        history.push(2.0);
+        assert_eq!(history.iter().collect::<Vec<_>>(), vec![2.0]);
+        assert_eq!(history.graph_data(10, 2.0), vec![10]);
+    }
+}
@@ -0,0 +1,384 @@
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::errors::{ChipsError, Result};
+
+/// An `http://` URL. There's no TLS, since this is only meant for services on the local
+/// machine or network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpUrl {
+    pub host: String,
+    pub port: u16,
+    /// Always starts with `/`, and includes the query string.
+    pub path: String,
+}
+
+impl FromStr for HttpUrl {
+    type Err = ChipsError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || ChipsError::InvalidUrl(s.to_string());
+        let rest = s.strip_prefix("http://").ok_or_else(invalid)?;
+        let (authority, path) = match rest.find('/') {
+            Some(idx) => (&rest[..idx], &rest[idx..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| invalid())?),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for HttpUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "http://{}:{}{}", self.host, self.port, self.path)
+    }
+}
+
+/// Fetches the body of a URL, failing with [`ChipsError::TimedOut`] if the whole exchange takes
+/// longer than `timeout`.
+///
+/// This speaks HTTP/1.0 so that servers close the connection after the body instead of
+/// chunking it, which is all the small endpoints this talks to need.
+pub fn get(url: &HttpUrl, timeout: Duration) -> Result<String> {
+    let deadline = Instant::now() + timeout;
+    let addr = (url.host.as_str(), url.port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| ChipsError::InvalidUrl(url.to_string()))?;
+    let mut stream = TcpStream::connect_timeout(&addr, timeout).map_err(timed_out)?;
+    stream.set_write_timeout(Some(timeout))?;
+    write!(
+        stream,
+        "GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        url.path, url.host
+    )
+    .map_err(timed_out)?;
+
+    let mut response = vec![];
+    let mut buf = [0; 4096];
+    loop {
+        let remaining = deadline
+            .checked_duration_since(Instant::now())
+            .filter(|remaining| !remaining.is_zero())
+            .ok_or(ChipsError::TimedOut)?;
+        stream.set_read_timeout(Some(remaining))?;
+        match stream.read(&mut buf).map_err(timed_out)? {
+            0 => break,
+            len => response.extend_from_slice(&buf[..len]),
+        }
+    }
+
+    parse_response(url, &response)
+}
+
+/// Splits the body from the headers, failing on anything but a 2xx status.
+fn parse_response(url: &HttpUrl, response: &[u8]) -> Result<String> {
+    let malformed = || ChipsError::Http(format!("malformed response from {}", url));
+    let header_end = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(malformed)?;
+    let head = String::from_utf8_lossy(&response[..header_end]);
+    let status_line = head.lines().next().unwrap_or_default();
+    let status: u16 = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(malformed)?;
+    if !(200..300).contains(&status) {
+        return Err(ChipsError::Http(format!("{} returned {}", url, status)));
+    }
+
+    Ok(String::from_utf8_lossy(&response[header_end + 4..]).into_owned())
+}
+
+/// Requests with bigger bodies are turned away, since nothing sent to the control API needs more.
+const MAX_BODY_LEN: usize = 32 * 1024 * 1024;
+
+/// A request received by a server, such as the control API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    /// The path without the query string.
+    pub path: String,
+    pub query: Vec<(String, String)>,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Gets the first value of a query parameter.
+    pub fn query(&self, key: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Gets the first value of a header, whose name is matched ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Reads one request, with a body if it has a `Content-Length`. Query parameters are taken as
+/// they are, without decoding any escapes.
+pub fn read_request(stream: impl Read) -> Result<Request> {
+    let malformed = |what: &str| ChipsError::Http(format!("malformed request {}", what));
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).map_err(timed_out)?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(malformed("line"));
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (name.to_string(), value.to_string())
+        })
+        .collect();
+
+    let mut headers: Vec<(String, String)> = vec![];
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).map_err(timed_out)? == 0 {
+            return Err(malformed("headers"));
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    let content_length = headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .map_or(Ok(0), |(_, value)| value.parse())
+        .map_err(|_| malformed("content length"))?;
+    if content_length > MAX_BODY_LEN {
+        return Err(ChipsError::Http(format!(
+            "request body of {} bytes is too large",
+            content_length
+        )));
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).map_err(timed_out)?;
+
+    Ok(Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query,
+        headers,
+        body,
+    })
+}
+
+/// Reads from a stream until a deadline, after which reads fail as timed out. Unlike a read
+/// timeout, which only limits each read, this limits the whole exchange, so a client that
+/// trickles in a byte at a time can't keep it going.
+pub struct DeadlineReader<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl<'a> DeadlineReader<'a> {
+    pub fn new(stream: &'a TcpStream, timeout: Duration) -> Self {
+        Self {
+            stream,
+            deadline: Instant::now() + timeout,
+        }
+    }
+}
+
+impl Read for DeadlineReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self
+            .deadline
+            .checked_duration_since(Instant::now())
+            .filter(|remaining| !remaining.is_zero())
+            .ok_or(io::ErrorKind::TimedOut)?;
+        self.stream.set_read_timeout(Some(remaining))?;
+        let mut stream = self.stream;
+        stream.read(buf)
+    }
+}
+
+/// Writes a response with a plain text body, and asks the client to close the connection.
+pub fn write_response(mut stream: impl Write, status: u16, reason: &str, body: &str) -> Result<()> {
+    write!(
+        stream,
+        "HTTP/1.0 {} {}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    )?;
+    Ok(stream.flush()?)
+}
+
+fn timed_out(err: io::Error) -> ChipsError {
+    match err.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => ChipsError::TimedOut,
+        _ => err.into(),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::net::TcpListener;
+    use std::thread;
+
+    use super::*;
+
+    /// Starts a server on a free local port that answers one request with `response`, and
+    /// returns the URL of `path` on it.
+    pub(crate) fn serve_once(response: impl Into<String>, path: &str) -> HttpUrl {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let response = response.into();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            // Read up to the end of the request headers before answering
+            let mut reader = io::BufReader::new(&stream);
+            let mut line = String::new();
+            while reader.read_line(&mut line).is_ok_and(|len| len > 2) {
+                line.clear();
+            }
+            (&stream).write_all(response.as_bytes()).unwrap();
+        });
+        format!("http://127.0.0.1:{}{}", port, path)
+            .parse()
+            .unwrap()
+    }
+
+    /// Formats a 200 response with a body.
+    pub(crate) fn ok(body: &str) -> String {
+        format!(
+            "HTTP/1.0 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    }
+
+    #[test]
+    fn parses_urls() {
+        assert_eq!(
+            "http://localhost:9100/metrics?x=1"
+                .parse::<HttpUrl>()
+                .unwrap(),
+            HttpUrl {
+                host: "localhost".to_string(),
+                port: 9100,
+                path: "/metrics?x=1".to_string(),
+            }
+        );
+        assert_eq!(
+            "http://192.168.1.2".parse::<HttpUrl>().unwrap().to_string(),
+            "http://192.168.1.2:80/"
+        );
+        assert!("https://localhost/".parse::<HttpUrl>().is_err());
+        assert!("http://:80/".parse::<HttpUrl>().is_err());
+        assert!("http://localhost:http/".parse::<HttpUrl>().is_err());
+    }
+
+    #[test]
+    fn gets_body() {
+        let url = serve_once(ok("{\"value\": 1}"), "/status");
+        assert_eq!(get(&url, Duration::from_secs(5)).unwrap(), "{\"value\": 1}");
+    }
+
+    #[test]
+    fn fails_on_error_status() {
+        let url = serve_once("HTTP/1.0 404 Not Found\r\n\r\n", "/missing");
+        assert!(matches!(
+            get(&url, Duration::from_secs(5)),
+            Err(ChipsError::Http(_))
+        ));
+    }
+
+    #[test]
+    fn reads_requests() {
+        let request = read_request(
+            "POST /image?x=10&y=20&fit HTTP/1.1\r\nHost: localhost\r\ncontent-length: 5\r\n\r\nhello"
+                .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/image");
+        assert_eq!(request.query("y"), Some("20"));
+        assert_eq!(request.query("fit"), Some(""));
+        assert_eq!(request.query("width"), None);
+        assert_eq!(request.header("HOST"), Some("localhost"));
+        assert_eq!(request.header("Origin"), None);
+        assert_eq!(request.body, b"hello");
+
+        assert!(read_request("GET /\r\n".as_bytes()).is_err());
+        assert!(read_request("POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nhi".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn reads_until_deadline() {
+        // Keeps sending a byte at a time, each well within a read timeout
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream
+                .write_all(b"POST / HTTP/1.1\r\nContent-Length: 100\r\n\r\n")
+                .unwrap();
+            while stream.write_all(b"a").is_ok() {
+                thread::sleep(Duration::from_millis(20));
+            }
+        });
+
+        let stream = TcpStream::connect(addr).unwrap();
+        let started = Instant::now();
+        assert!(matches!(
+            read_request(DeadlineReader::new(&stream, Duration::from_millis(200))),
+            Err(ChipsError::TimedOut)
+        ));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn times_out() {
+        // Accepts the connection but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        let started = Instant::now();
+        assert!(matches!(
+            get(&url, Duration::from_millis(200)),
+            Err(ChipsError::TimedOut)
+        ));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        drop(listener);
+    }
+}
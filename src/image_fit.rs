@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImage, ImageReader};
+
+use crate::errors::{ChipsError, Result};
+
+/// Different sizes and fit modes of the same few images is all the cache is meant to hold.
+const MAX_CACHE_ENTRIES: usize = 8;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum FitMode {
+    /// Scale to fit entirely inside the target, preserving aspect ratio.
+    Contain,
+    /// Scale to fill the whole target, preserving aspect ratio and cropping the overflow.
+    Cover,
+    /// Scale to exactly the target size, ignoring aspect ratio.
+    Stretch,
+    /// Keep the original size, centered in the target and cropped to it.
+    Center,
+    /// Keep the original size, repeated from the top-left of the target.
+    Tile,
+}
+
+impl FromStr for FitMode {
+    type Err = ChipsError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim() {
+            "contain" => Ok(FitMode::Contain),
+            "cover" => Ok(FitMode::Cover),
+            "stretch" => Ok(FitMode::Stretch),
+            "center" => Ok(FitMode::Center),
+            "tile" => Ok(FitMode::Tile),
+            _ => Err(ChipsError::InvalidFitMode(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub enum ResampleFilter {
+    Nearest,
+    #[default]
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+
+impl From<ResampleFilter> for FilterType {
+    fn from(filter: ResampleFilter) -> Self {
+        match filter {
+            ResampleFilter::Nearest => FilterType::Nearest,
+            ResampleFilter::Triangle => FilterType::Triangle,
+            ResampleFilter::CatmullRom => FilterType::CatmullRom,
+            ResampleFilter::Gaussian => FilterType::Gaussian,
+            ResampleFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+/// An image prepared for a target area, offset from the target's top-left corner.
+#[derive(Debug, Clone)]
+pub struct FittedImage {
+    pub image: DynamicImage,
+    pub x: i32,
+    pub y: i32,
+}
+
+pub fn fit_image(
+    image: &DynamicImage,
+    width: u32,
+    height: u32,
+    fit: FitMode,
+    filter: ResampleFilter,
+) -> FittedImage {
+    let centered = |image: DynamicImage| {
+        let x = (width as i32 - image.width() as i32) / 2;
+        let y = (height as i32 - image.height() as i32) / 2;
+        FittedImage { image, x, y }
+    };
+
+    match fit {
+        FitMode::Contain => centered(image.resize(width, height, filter.into())),
+        FitMode::Cover => centered(image.resize_to_fill(width, height, filter.into())),
+        FitMode::Stretch => centered(image.resize_exact(width, height, filter.into())),
+        FitMode::Center => {
+            let crop_width = image.width().min(width);
+            let crop_height = image.height().min(height);
+            centered(image.crop_imm(
+                (image.width() - crop_width) / 2,
+                (image.height() - crop_height) / 2,
+                crop_width,
+                crop_height,
+            ))
+        }
+        FitMode::Tile => {
+            let mut tiled = DynamicImage::new(width, height, image.color());
+            for y in (0..height).step_by(image.height().max(1) as usize) {
+                for x in (0..width).step_by(image.width().max(1) as usize) {
+                    let tile = image.crop_imm(
+                        0,
+                        0,
+                        image.width().min(width - x),
+                        image.height().min(height - y),
+                    );
+
+                    // The tile is cropped to the remaining space, so this can't go out of bounds
+                    tiled.copy_from(&tile, x, y).unwrap();
+                }
+            }
+
+            centered(tiled)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    path: PathBuf,
+    width: u32,
+    height: u32,
+    fit: FitMode,
+    filter: ResampleFilter,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    modified: Option<SystemTime>,
+    fitted: FittedImage,
+    /// When the entry was last asked for, counted in calls to [`ImageCache::get`].
+    used: u64,
+}
+
+/// Keeps resized images around so that fitting a large wallpaper only happens once per target
+/// size. Entries are reloaded when the source file's modification time changes, and the least
+/// recently used one is dropped once there are [`MAX_CACHE_ENTRIES`].
+#[derive(Debug, Default)]
+pub struct ImageCache {
+    entries: HashMap<CacheKey, CacheEntry>,
+    uses: u64,
+}
+
+impl ImageCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(
+        &mut self,
+        path: &Path,
+        width: u32,
+        height: u32,
+        fit: FitMode,
+        filter: ResampleFilter,
+    ) -> Result<&FittedImage> {
+        let key = CacheKey {
+            path: path.to_path_buf(),
+            width,
+            height,
+            fit,
+            filter,
+        };
+        let modified = std::fs::metadata(path)?.modified().ok();
+        self.uses += 1;
+
+        let is_stale = self
+            .entries
+            .get(&key)
+            .map(|entry| entry.modified != modified)
+            .unwrap_or(true);
+        if is_stale {
+            let image = ImageReader::open(path)?.decode()?;
+            let fitted = fit_image(&image, width, height, fit, filter);
+
+            if !self.entries.contains_key(&key) && self.entries.len() >= MAX_CACHE_ENTRIES {
+                let oldest = self
+                    .entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.used)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    self.entries.remove(&oldest);
+                }
+            }
+
+            self.entries.insert(
+                key.clone(),
+                CacheEntry {
+                    modified,
+                    fitted,
+                    used: 0,
+                },
+            );
+        }
+
+        let entry = self.entries.get_mut(&key).unwrap();
+        entry.used = self.uses;
+        Ok(&entry.fitted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::time::Duration;
+
+    use image::{Rgb, RgbImage};
+
+    use super::*;
+
+    /// A 4x2 image where every pixel is different, red counting columns and green rows.
+    fn image() -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(4, 2, |x, y| {
+            Rgb([x as u8 * 60, y as u8 * 200, 0])
+        }))
+    }
+
+    fn fit(width: u32, height: u32, fit: FitMode) -> FittedImage {
+        fit_image(&image(), width, height, fit, ResampleFilter::Nearest)
+    }
+
+    fn placement(fitted: &FittedImage) -> (i32, i32, u32, u32) {
+        (
+            fitted.x,
+            fitted.y,
+            fitted.image.width(),
+            fitted.image.height(),
+        )
+    }
+
+    #[test]
+    fn scales_to_fit() {
+        // Letterboxed in the middle, or cropped to fill
+        assert_eq!(placement(&fit(20, 20, FitMode::Contain)), (0, 5, 20, 10));
+        assert_eq!(placement(&fit(6, 20, FitMode::Contain)), (0, 8, 6, 3));
+        assert_eq!(placement(&fit(20, 20, FitMode::Cover)), (0, 0, 20, 20));
+        assert_eq!(placement(&fit(20, 20, FitMode::Stretch)), (0, 0, 20, 20));
+
+        // Covering keeps the middle of the image
+        let cover = fit(2, 2, FitMode::Cover).image.to_rgb8();
+        assert_eq!(cover.get_pixel(0, 0), &Rgb([60, 0, 0]));
+        assert_eq!(cover.get_pixel(1, 1), &Rgb([120, 200, 0]));
+    }
+
+    #[test]
+    fn centers_at_original_size() {
+        assert_eq!(placement(&fit(10, 7, FitMode::Center)), (3, 2, 4, 2));
+
+        // Cropped to the middle when the target is smaller
+        let cropped = fit(2, 1, FitMode::Center);
+        assert_eq!(placement(&cropped), (0, 0, 2, 1));
+        assert_eq!(cropped.image.to_rgb8().get_pixel(0, 0), &Rgb([60, 0, 0]));
+    }
+
+    #[test]
+    fn tiles_from_top_left() {
+        let tiled = fit(10, 3, FitMode::Tile);
+        assert_eq!(placement(&tiled), (0, 0, 10, 3));
+
+        let tiled = tiled.image.to_rgb8();
+        assert_eq!(tiled.get_pixel(0, 0), &Rgb([0, 0, 0]));
+        assert_eq!(tiled.get_pixel(5, 1), &Rgb([60, 200, 0]));
+        assert_eq!(tiled.get_pixel(9, 2), &Rgb([60, 0, 0]));
+    }
+
+    #[test]
+    fn parses_fit_modes() {
+        assert_eq!("cover".parse::<FitMode>().unwrap(), FitMode::Cover);
+        assert_eq!(" tile ".parse::<FitMode>().unwrap(), FitMode::Tile);
+        assert!("fill".parse::<FitMode>().is_err());
+    }
+
+    #[test]
+    fn caches_until_file_changes() {
+        let dir = std::env::temp_dir().join(format!("chips-image-cache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("image.png");
+        image().save(&path).unwrap();
+
+        let mut cache = ImageCache::new();
+        let get = |cache: &mut ImageCache, width: u32| {
+            placement(
+                cache
+                    .get(&path, width, 10, FitMode::Contain, ResampleFilter::Nearest)
+                    .unwrap(),
+            )
+        };
+        assert_eq!(get(&mut cache, 10), (0, 2, 10, 5));
+
+        // A different image at the same path is only picked up once its time changes
+        DynamicImage::new_rgb8(2, 4).save(&path).unwrap();
+        let file = File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(get(&mut cache, 10), (2, 0, 5, 10));
+
+        // Only the most recently used sizes are kept
+        for width in 1..=MAX_CACHE_ENTRIES as u32 {
+            get(&mut cache, width);
+        }
+        assert_eq!(cache.entries.len(), MAX_CACHE_ENTRIES);
+        assert!(cache.entries.keys().all(|key| key.width != 10));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
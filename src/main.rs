@@ -1,33 +1,98 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use std::path::Path;
+use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crate::color::Color;
-use crate::device::{get_chips_id, get_chips_serial_port_info, ChipsDevice};
+use crate::color_rules::{ColorRule, Direction};
+use crate::device::{get_chips_id, get_chips_serial_port_info, ChipsDevice, ImageTiling, Rect};
+use crate::dither::{DitherMode, Rgb565Image};
 use crate::errors::Result;
-use crossbeam::channel::bounded;
+use control::{ControlCommand, ControlServer, Layout, DEFAULT_CONTROL_ADDR};
+use crossbeam::channel::{bounded, unbounded};
 use crossbeam::select;
 use device::Point;
 use eframe::egui;
-use fontdue::layout::{CoordinateSystem, Layout, TextStyle};
+use fontdue::layout::{HorizontalAlign, VerticalAlign};
 use fontdue::Font;
-use image::ImageReader;
+use gpu::{GpuSelector, Gpus};
+use history::History;
+use image::{Rgb, RgbImage};
+use image_fit::{fit_image, FitMode, ImageCache, ResampleFilter};
+use metrics::{MetricProvider, MetricRegistry};
+use mqtt::{MqttMetrics, MqttSource};
+use prometheus::{PrometheusMetrics, PrometheusTarget};
+use providers::{
+    CpuMetrics, DiskIoMetrics, DiskSpaceMetrics, GpuMetrics, MemoryMetrics, NetworkMetrics,
+    SensorMetrics,
+};
 use rand::Rng;
+use sensors::{SensorMap, Sensors};
 use serialport::SerialPortInfo;
-use system_info::SystemInfo;
+use sources::{ExternalMetrics, ExternalSource};
+use template::Template;
 use widget_renderer::WidgetRenderer;
+use widgets::animation::Animation;
+use widgets::clock::{AnalogClock, AnalogClockStyle, DigitalClock, Uptime};
+use widgets::core_grid::{CoreGrid, CoreGridStyle};
+use widgets::gauge::{Gauge, GaugeStyle, GaugeTicks};
+use widgets::progress_bar::{Orientation, ProgressBar, ProgressBarStyle, Segments};
+use widgets::ruled::Ruled;
+use widgets::text::{TextBackground, TextBox, TextBoxStyle};
+use widgets::Widget;
 
 mod color;
+mod color_rules;
+mod control;
+mod counter_sampler;
+mod cpu_sampler;
 mod device;
+mod disks;
+mod dither;
+mod drawing;
 mod errors;
+mod gpu;
+mod history;
+mod http;
+mod image_fit;
+mod metrics;
+mod mqtt;
+mod network;
+mod prometheus;
+mod providers;
+mod sensors;
+mod sources;
 mod system_info;
+mod template;
 mod widget_renderer;
+mod widgets;
 
 fn main() -> Result<()> {
     let chips_device_id = get_chips_id().unwrap().unwrap();
     let chips_port_info = get_chips_serial_port_info(&chips_device_id);
 
+    // Lets other programs on this machine drive the screen. The sender is kept for as long as
+    // the worker runs, so that the worker's end stays open even if the server couldn't start.
+    let (command_sender, commands) = unbounded();
+    let control_addr =
+        std::env::var("CHIPS_CONTROL_ADDR").unwrap_or_else(|_| DEFAULT_CONTROL_ADDR.to_string());
+    match ControlServer::bind(&control_addr) {
+        Ok(mut server) => {
+            // Other users on the machine can reach the API too, unless it needs a token
+            if let Ok(token) = std::env::var("CHIPS_CONTROL_TOKEN") {
+                server = server.with_token(token);
+            }
+            if let Ok(addr) = server.local_addr() {
+                println!("Control API listening on {}", addr);
+            }
+            server.spawn(command_sender.clone());
+        }
+        Err(err) => println!("Control API unavailable on {}: {:?}", control_addr, err),
+    }
+
+    let dither_mode = dither_mode();
     thread::scope(|s| {
         let (s1, r) = bounded(1);
 
@@ -35,19 +100,35 @@ fn main() -> Result<()> {
         s.spawn(move || {
             let mut chips_device = port_info_copy
                 .clone()
-                .and_then(|port_info| Some(ChipsDevice::new(port_info)))
+                .map(ChipsDevice::new)
                 .expect("failed to create device handle");
-            if let Err(err) = init_device(&mut chips_device) {
+            if let Err(err) = init_device(&mut chips_device, dither_mode) {
                 println!("{:?}", err);
             }
 
-            let mut sys_info =
-                SystemInfo::new().expect("failed to create system information interface");
+            let mut dashboard = match TestDashboard::new(dither_mode) {
+                Ok(dashboard) => dashboard,
+                Err(err) => {
+                    println!("Failed to create dashboard: {:?}", err);
+                    return;
+                }
+            };
             loop {
+                // Sleep until the next metrics refresh, or sooner if a clock needs to tick over
+                let timeout = dashboard
+                    .next_update()
+                    .saturating_duration_since(Instant::now());
                 select! {
                     recv(r) -> _ => break,
-                    default(Duration::from_secs(1)) => {
-                        if let Err(err) = test_device(&mut chips_device, &mut sys_info) {
+                    recv(commands) -> command => {
+                        if let Ok(command) = command {
+                            if let Err(err) = dashboard.handle(command, &mut chips_device) {
+                                println!("{:?}", err);
+                            }
+                        }
+                    }
+                    default(timeout) => {
+                        if let Err(err) = dashboard.render(&mut chips_device) {
                             println!("{:?}", err);
                         }
                     }
@@ -84,7 +165,21 @@ fn run_app(chips_port_info: Option<SerialPortInfo>) -> Result<()> {
     Ok(())
 }
 
-fn init_device(device: &mut ChipsDevice) -> Result<()> {
+/// How images are reduced to the device's colors, which is Floyd-Steinberg unless
+/// `CHIPS_DITHER` names another mode. An unknown mode is reported and falls back to the default.
+fn dither_mode() -> DitherMode {
+    match std::env::var("CHIPS_DITHER").map(|mode| mode.parse()) {
+        Ok(Ok(mode)) => mode,
+        Ok(Err(err)) => {
+            println!("{}, using Floyd-Steinberg dithering", err);
+            DitherMode::FloydSteinberg
+        }
+        Err(_) => DitherMode::FloydSteinberg,
+    }
+}
+
+fn init_device(device: &mut ChipsDevice, dither_mode: DitherMode) -> Result<()> {
+    device.set_dither_mode(dither_mode);
     device.connect()?;
     device.startup()?;
     device.set_brightness(100)?;
@@ -92,82 +187,613 @@ fn init_device(device: &mut ChipsDevice) -> Result<()> {
     // Fix screen orientation
     device.adjust_screen(true, true, true)?;
 
+    device.set_image_tiling(Some(ImageTiling::new(200, 120, 2)?));
+
     Ok(())
 }
 
-fn test_device(device: &mut ChipsDevice, sys_info: &mut SystemInfo) -> Result<()> {
-    let mut widget_renderer = WidgetRenderer::new(device);
+/// Reports a part of the dashboard that couldn't be set up, so that the rest can carry on
+/// without it.
+fn report<T>(what: &str, result: Result<T>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(err) => {
+            println!("Leaving out {}: {:?}", what, err);
+            None
+        }
+    }
+}
 
-    let cpu_usage = sys_info.get_cpu_usage()?;
-    let cpu_usage_percent = format!("{:.0}%", (cpu_usage * 100.0).ceil());
+/// Sets up something from the value of an environment variable, if it's set. A bad value is
+/// reported and leaves it out, like [`report`].
+fn from_env<T>(name: &str, setup: impl FnOnce(String) -> Result<T>) -> Option<T> {
+    let value = std::env::var(name).ok()?;
+    report(name, setup(value))
+}
 
-    let mem_usage = sys_info.get_memory_usage()?;
-    let mem_usage_percent = format!("{:.0}%", (mem_usage * 100.0).ceil());
+/// Registers a provider that started, reporting one that didn't or that clashes with another.
+fn register(
+    registry: &mut MetricRegistry,
+    what: &str,
+    provider: Result<impl MetricProvider + 'static>,
+) {
+    report(
+        what,
+        provider.and_then(|provider| registry.register(provider)),
+    );
+}
 
-    let gpu_usage = sys_info.get_gpu_usage().unwrap_or(0.0);
-    let gpu_usage_percent = format!("{:.0}%", (gpu_usage * 100.0).ceil());
+const WALLPAPER_PATH: &str = "./src/test_image_2.png";
+const NET_GRAPH_WIDTH: i32 = 100;
+const NET_GRAPH_HEIGHT: u8 = 100;
+const GIB: f64 = 1024.0 * 1024.0 * 1024.0;
+/// The panel behind notifications, with room around the text box inside it.
+const NOTIFICATION_FRAME: Rect = Rect::new(80, 170, 640, 140);
+const NOTIFICATION_RADIUS: i32 = 16;
+/// Shows the system drive, which is named after its mount on each platform.
+#[cfg(windows)]
+const DISK_TEMPLATE: &str =
+    "C: {disk.c.usage:.0|>=90:red}% R {disk.read:bytes}/s W {disk.write:bytes}/s";
+#[cfg(not(windows))]
+const DISK_TEMPLATE: &str =
+    "/ {disk._root.usage:.0|>=90:red}% R {disk.read:bytes}/s W {disk.write:bytes}/s";
+
+struct TestDashboard {
+    registry: MetricRegistry,
+    wallpaper: Arc<Rgb565Image>,
+    usage_template: Template,
+    usage_text: TextBox,
+    cpu_gauge: Ruled<Gauge>,
+    gpu_gauge: Gauge,
+    mem_bar: Ruled<ProgressBar>,
+    mem_template: Template,
+    mem_text: TextBox,
+    temp_template: Template,
+    temp_text: TextBox,
+    net_template: Template,
+    net_text: TextBox,
+    net_rx_history: History,
+    disk_template: Template,
+    disk_text: TextBox,
+    core_grid: CoreGrid,
+    clock: DigitalClock,
+    date: DigitalClock,
+    uptime: Uptime,
+    analog_clock: AnalogClock,
+    animation: Option<Animation>,
+    notification: TextBox,
+    /// When the notification stops covering the widgets, if one is showing.
+    notification_until: Option<Instant>,
+    notification_frame_drawn: bool,
+    layout: Layout,
+    background_drawn: bool,
+}
 
-    // Draw image
-    let image = ImageReader::open("./src/test_image_2.png")?.decode()?;
-    widget_renderer.render_image(&image, 0, 0)?;
+impl TestDashboard {
+    fn new(dither_mode: DitherMode) -> Result<Self> {
+        let font = include_bytes!("../resources/roboto/Roboto-Regular.ttf") as &[u8];
+        let roboto_regular = Font::from_bytes(font, fontdue::FontSettings::default()).unwrap();
+
+        let gauge_style = GaugeStyle {
+            ticks: Some(GaugeTicks {
+                count: 11,
+                length: 5,
+                color: Color::new(228, 207, 154),
+            }),
+            ..GaugeStyle::default()
+        };
+
+        let mem_bar_style = ProgressBarStyle {
+            color_stops: vec![
+                (0.75, Color::new(230, 150, 60)),
+                (0.9, Color::new(220, 70, 60)),
+            ],
+            segments: Some(Segments { count: 20, gap: 2 }),
+            ..ProgressBarStyle::default()
+        };
+
+        // The text is drawn over the wallpaper, so that's what gets restored behind it. It's
+        // dithered once, so that restoring part of it gives back exactly what's on screen.
+        let screen = Rect::screen();
+        let mut image_cache = ImageCache::new();
+        let wallpaper = report(
+            "wallpaper",
+            image_cache.get(
+                Path::new(WALLPAPER_PATH),
+                screen.width as u32,
+                screen.height as u32,
+                FitMode::Cover,
+                ResampleFilter::Triangle,
+            ),
+        )
+        .map(|wallpaper| wallpaper.image.to_rgb8())
+        .unwrap_or_else(|| {
+            RgbImage::from_pixel(screen.width as u32, screen.height as u32, Rgb([63, 67, 81]))
+        });
+        let wallpaper = Arc::new(Rgb565Image::new(&wallpaper, dither_mode));
+        let text_box = |rect: Rect, font_size: f32, horizontal_align: HorizontalAlign| {
+            TextBox::new(
+                rect,
+                TextBoxStyle {
+                    font_size,
+                    horizontal_align,
+                    vertical_align: VerticalAlign::Middle,
+                    ..TextBoxStyle::default()
+                },
+                roboto_regular.clone(),
+                TextBackground::Image(wallpaper.clone()),
+            )
+        };
+
+        // Maps hardware sensors to metrics with the lines in this file, or with the defaults
+        let sensor_map: SensorMap = from_env("CHIPS_SENSOR_MAP", |path| {
+            std::fs::read_to_string(path)?.parse()
+        })
+        .unwrap_or_default();
+
+        // Lists the sensors, GPUs, sources and metrics found at startup when this is set
+        let verbose = std::env::var_os("CHIPS_VERBOSE").is_some();
+
+        let sensors = Sensors::discover(&sensor_map);
+        if verbose {
+            for sensor in sensors.list() {
+                let value = sensor
+                    .read()
+                    .map_or("--".to_string(), |value| value.to_string());
+                let metric = sensor.metric.as_deref().unwrap_or("unmapped");
+                println!("Sensor {}: {} ({})", sensor.id, value, metric);
+            }
+        }
+
+        // Picks a GPU by index, name or UUID, defaulting to the first one
+        let gpu_selector: GpuSelector =
+            from_env("CHIPS_GPU", |selector| selector.parse()).unwrap_or_default();
+        let gpus = Gpus::detect();
+        if verbose {
+            for gpu in gpus.list() {
+                println!("GPU {}: {}", gpu.index, gpu.name);
+            }
+        }
+        if gpus.select(&gpu_selector).is_none() {
+            println!("No GPU matches {:?}", gpu_selector);
+        }
 
-    // Draw rectangle
-    let bg_color = Color::new(63, 67, 81);
-    let fg_color = Color::new(228, 207, 154);
+        // Providers that fail to start are left out, and the widgets showing them show "--"
+        let mut registry = MetricRegistry::new();
+        register(&mut registry, "CPU metrics", CpuMetrics::new());
+        register(&mut registry, "memory metrics", MemoryMetrics::new());
+        register(
+            &mut registry,
+            "GPU metrics",
+            Ok(GpuMetrics::new(gpus, gpu_selector)),
+        );
+        register(
+            &mut registry,
+            "sensor metrics",
+            Ok(SensorMetrics::new(sensors)),
+        );
+        register(&mut registry, "network metrics", NetworkMetrics::new());
+        register(&mut registry, "disk space metrics", Ok(DiskSpaceMetrics));
+        register(&mut registry, "disk I/O metrics", DiskIoMetrics::new());
+
+        // Reads metrics from the commands, files and local endpoints in this file
+        let sources = from_env("CHIPS_SOURCES", |path| {
+            ExternalSource::parse_all(&std::fs::read_to_string(path)?)
+        });
+        for source in sources.into_iter().flatten() {
+            if verbose {
+                println!("Source {}: {:?}", source.metric, source.kind);
+            }
+            let name = format!("source {}", source.metric);
+            register(&mut registry, &name, Ok(ExternalMetrics::spawn(source)));
+        }
 
-    widget_renderer.render_rectangle(0, 0, 10, 10, bg_color)?;
+        // Scrapes the Prometheus endpoints in this file, like node_exporter
+        let targets = from_env("CHIPS_PROMETHEUS", |path| {
+            PrometheusTarget::parse_all(&std::fs::read_to_string(path)?)
+        });
+        for target in targets.into_iter().flatten() {
+            if verbose {
+                println!("Scraping {} from {}", target.name, target.url);
+            }
+            let name = format!("Prometheus target {}", target.name);
+            register(&mut registry, &name, Ok(PrometheusMetrics::spawn(target)));
+        }
+        let mqtt_sources = from_env("CHIPS_MQTT", |path| {
+            MqttSource::parse_all(&std::fs::read_to_string(path)?)
+        });
+        for source in mqtt_sources.into_iter().flatten() {
+            if verbose {
+                println!(
+                    "Subscribing to {} topics on {}:{}",
+                    source.mappings.len(),
+                    source.host,
+                    source.port
+                );
+            }
+            let name = format!("MQTT broker {}:{}", source.host, source.port);
+            register(&mut registry, &name, Ok(MqttMetrics::spawn(source)));
+        }
+        if verbose {
+            for info in registry.metrics() {
+                println!("Metric {} ({})", info.name, info.unit.symbol());
+            }
+        }
 
-    // Draw bar graph
-    widget_renderer.render_graph_background(0, 250, 200, 100, bg_color)?;
+        // Plays the GIF or APNG in this file next to the graphs
+        let animation = from_env("CHIPS_ANIMATION", |path| {
+            Animation::open(
+                path,
+                Rect::new(10, 60, 120, 120),
+                FitMode::Contain,
+                ResampleFilter::Triangle,
+                Color::new(63, 67, 81),
+            )
+        });
 
-    let mut bar_graph_data = vec![0; 300];
-    let mut rng = rand::thread_rng();
-    let distr = rand::distributions::Uniform::new_inclusive(0u8, 100u8);
-    for x in &mut bar_graph_data {
-        *x = rng.sample(distr);
+        Ok(Self {
+            wallpaper: wallpaper.clone(),
+            usage_template: Template::parse(
+                "{cpu.usage:.0|>=90:red}% {mem.usage:.0|>=90:red}% {gpu.usage:.0|>=90:red}%",
+            )?,
+            usage_text: text_box(Rect::new(500, 100, 280, 45), 35.0, HorizontalAlign::Right),
+            cpu_gauge: Ruled::new(Gauge::new(
+                Point::new(560, 390),
+                gauge_style,
+                roboto_regular.clone(),
+            ))
+            .with_foreground(
+                ColorRule::new("cpu.usage", Direction::Above, gauge_style.foreground)
+                    .level(80.0, Color::new(230, 150, 60))
+                    .level(95.0, Color::new(220, 70, 60))
+                    .blinking(Duration::from_secs(1))
+                    .hysteresis(5.0),
+            ),
+            gpu_gauge: Gauge::new(Point::new(720, 390), gauge_style, roboto_regular.clone()),
+            mem_bar: Ruled::new(ProgressBar::new(
+                Rect::new(500, 160, 280, 20),
+                mem_bar_style.clone(),
+            ))
+            .with_background(
+                // Usage alone doesn't say how close the system is to paging
+                ColorRule::new("mem.available", Direction::Below, mem_bar_style.background)
+                    .level(2.0 * GIB, Color::new(110, 40, 40))
+                    .hysteresis(0.25 * GIB),
+            ),
+            mem_template: Template::parse("{mem.used:GiB.1} / {mem.total:GiB.0} GiB")?,
+            mem_text: text_box(Rect::new(500, 185, 280, 28), 22.0, HorizontalAlign::Right),
+            temp_template: Template::parse(
+                "CPU {cpu.temperature:.0|>=90:red}° GPU {gpu.temperature:.0|>=85:red}° {gpu.power:.0} W",
+            )?,
+            temp_text: text_box(Rect::new(500, 215, 280, 28), 22.0, HorizontalAlign::Right),
+            net_template: Template::parse("down {net.rx:bits}/s up {net.tx:bits}/s")?,
+            net_text: text_box(Rect::new(500, 245, 280, 28), 22.0, HorizontalAlign::Right),
+            // The line graph reads one value past its width
+            net_rx_history: History::new(NET_GRAPH_WIDTH as usize + 1),
+            disk_template: Template::parse(DISK_TEMPLATE)?,
+            disk_text: text_box(Rect::new(500, 275, 280, 28), 22.0, HorizontalAlign::Right),
+            core_grid: CoreGrid::new(
+                Rect::new(20, 2, 460, 44),
+                CoreGridStyle {
+                    columns: Some(16),
+                    bar: ProgressBarStyle {
+                        orientation: Orientation::Vertical,
+                        color_stops: vec![(1.0, Color::new(220, 70, 60))],
+                        blend_stops: true,
+                        ..ProgressBarStyle::default()
+                    },
+                    ..CoreGridStyle::default()
+                },
+            ),
+            clock: DigitalClock::new(
+                text_box(Rect::new(145, 360, 300, 55), 48.0, HorizontalAlign::Left),
+                "%H:%M:%S",
+            )?,
+            date: DigitalClock::date(text_box(
+                Rect::new(145, 420, 300, 30),
+                24.0,
+                HorizontalAlign::Left,
+            )),
+            uptime: Uptime::new(
+                text_box(Rect::new(500, 60, 280, 30), 24.0, HorizontalAlign::Right),
+                system_info::get_uptime(),
+            ),
+            analog_clock: AnalogClock::new(Point::new(70, 410), AnalogClockStyle::default()),
+            animation,
+            notification: TextBox::new(
+                Rect::new(100, 190, 600, 100),
+                TextBoxStyle {
+                    font_size: 36.0,
+                    horizontal_align: HorizontalAlign::Center,
+                    vertical_align: VerticalAlign::Middle,
+                    wrap: true,
+                    ..TextBoxStyle::default()
+                },
+                roboto_regular.clone(),
+                TextBackground::Color(Color::new(63, 67, 81)),
+            ),
+            notification_until: None,
+            notification_frame_drawn: false,
+            layout: Layout::Dashboard,
+            registry,
+            background_drawn: false,
+        })
     }
 
-    widget_renderer.render_bar_graph(0, 250, 100, bg_color, fg_color, &bar_graph_data)?;
+    /// When the dashboard next needs to be rendered.
+    fn next_update(&self) -> Instant {
+        // Frames and blinking colors only come due while the widgets are showing
+        let showing = self.layout == Layout::Dashboard && self.notification_until.is_none();
+        let animation = self
+            .animation
+            .as_ref()
+            .filter(|_| showing)
+            .and_then(|animation| animation.next_update());
+        let ruled = [self.cpu_gauge.next_update(), self.mem_bar.next_update()]
+            .into_iter()
+            .flatten()
+            .filter(|_| showing)
+            .min();
+
+        [
+            self.clock.next_update(),
+            self.date.next_update(),
+            self.uptime.next_update(),
+            self.analog_clock.next_update(),
+            animation,
+            ruled,
+        ]
+        .into_iter()
+        .flatten()
+        .chain(self.notification_until)
+        .chain(self.registry.next_due())
+        .min()
+        .unwrap_or_else(Instant::now)
+    }
 
-    // Draw line graph
-    widget_renderer.render_graph_background(320, 250, 200, 100, fg_color)?;
+    fn render(&mut self, device: &mut ChipsDevice) -> Result<()> {
+        let mut widget_renderer = WidgetRenderer::new(device);
+        let now = Instant::now();
 
-    let mut line_graph_data = vec![0; 300];
-    let mut rng = rand::thread_rng();
-    let distr = rand::distributions::Uniform::new_inclusive(0u8, 100u8);
-    for x in &mut line_graph_data {
-        *x = rng.sample(distr);
-    }
+        // Everything the notification covered is redrawn once it expires
+        if self.notification_until.is_some_and(|until| now >= until) {
+            self.notification_until = None;
+            self.background_drawn = false;
+        }
+
+        // Draw image once, since the widgets only redraw what changes on top of it
+        let redraw = !self.background_drawn;
+        if redraw {
+            widget_renderer.render_rgb565(self.wallpaper.rect(), self.wallpaper.pixels())?;
+            self.invalidate_widgets();
+            self.background_drawn = true;
+        }
+
+        // Metrics keep being sampled while hidden, so that graphs don't have gaps
+        let polled = self.registry.poll(now);
+        if polled {
+            if let Some(net_rx) = self
+                .registry
+                .snapshot()
+                .get("net.rx")
+                .and_then(|value| value.as_number())
+            {
+                self.net_rx_history.push(net_rx);
+            }
+        }
 
-    widget_renderer.render_line_graph(320, 250, 100, bg_color, fg_color, &line_graph_data)?;
+        if self.notification_until.is_some() {
+            if !self.notification_frame_drawn {
+                let mut canvas = widget_renderer.canvas();
+                canvas.fill_rounded_rectangle(
+                    NOTIFICATION_FRAME,
+                    NOTIFICATION_RADIUS,
+                    Color::new(63, 67, 81),
+                )?;
+                canvas.rounded_rectangle(
+                    NOTIFICATION_FRAME,
+                    NOTIFICATION_RADIUS,
+                    2,
+                    Color::new(228, 207, 154),
+                )?;
+                self.notification.invalidate();
+                self.notification_frame_drawn = true;
+            }
+
+            return self.notification.render(&mut widget_renderer);
+        }
+
+        if self.layout == Layout::Dashboard && (polled || redraw) {
+            self.render_metrics(&mut widget_renderer)?;
+        } else if self.layout == Layout::Dashboard {
+            // Blinking colors switch between polls
+            self.render_rules(&mut widget_renderer, now)?;
+        }
 
-    // Draw grid with pixels
-    let mut grid_points: Vec<Point> = vec![];
-    for x in 200..=400 {
-        for y in 100..=300 {
-            if x % 100 == 0 || y % 100 == 0 {
-                grid_points.push(Point::new(x - 50, y - 50));
+        // Only uploads the part of the frame that changed, if the next one is due
+        if self.layout == Layout::Dashboard {
+            if let Some(animation) = &mut self.animation {
+                animation.render(&mut widget_renderer)?;
             }
         }
+
+        // Time widgets only draw anything when their text or hands change
+        if self.layout != Layout::Wallpaper {
+            self.clock.render(&mut widget_renderer)?;
+            self.date.render(&mut widget_renderer)?;
+            self.uptime.render(&mut widget_renderer)?;
+            self.analog_clock.render(&mut widget_renderer)?;
+        }
+
+        Ok(())
     }
 
-    widget_renderer.render_pixels(fg_color, &grid_points)?;
+    /// Carries out a command from the control API, and draws whatever it changed.
+    fn handle(&mut self, command: ControlCommand, device: &mut ChipsDevice) -> Result<()> {
+        match command {
+            ControlCommand::SetBrightness(brightness) => device.set_brightness(brightness)?,
+            ControlCommand::SwitchLayout(layout) => {
+                self.layout = layout;
+                self.background_drawn = false;
+            }
+            ControlCommand::PushMetric { name, value } => self.registry.push(&name, value)?,
+            ControlCommand::DrawImage { image, rect, fit } => {
+                let fitted = fit_image(
+                    &image,
+                    rect.width as u32,
+                    rect.height as u32,
+                    fit,
+                    ResampleFilter::Triangle,
+                );
+                WidgetRenderer::new(device).render_image(
+                    &fitted.image,
+                    rect.x + fitted.x,
+                    rect.y + fitted.y,
+                )?;
+            }
+            ControlCommand::Notify { text, duration } => {
+                self.notification.set_text(text);
+                self.notification_frame_drawn = false;
+                self.notification_until = Instant::now().checked_add(duration);
+            }
+        }
 
-    // Draw text
-    let font = include_bytes!("../resources/roboto/Roboto-Regular.ttf") as &[u8];
-    let roboto_regular = Font::from_bytes(font, fontdue::FontSettings::default()).unwrap();
+        self.render(device)
+    }
 
-    let fonts = &[roboto_regular];
-    let mut layout = Layout::new(CoordinateSystem::PositiveYDown);
-    layout.append(fonts, &TextStyle::new(&cpu_usage_percent, 35.0, 0));
-    layout.append(fonts, &TextStyle::new(" ", 35.0, 0));
-    layout.append(fonts, &TextStyle::new(&mem_usage_percent, 35.0, 0));
-    layout.append(fonts, &TextStyle::new(" ", 35.0, 0));
-    layout.append(fonts, &TextStyle::new(&gpu_usage_percent, 35.0, 0));
+    fn invalidate_widgets(&mut self) {
+        self.usage_text.invalidate();
+        self.cpu_gauge.invalidate();
+        self.gpu_gauge.invalidate();
+        self.mem_bar.invalidate();
+        self.mem_text.invalidate();
+        self.temp_text.invalidate();
+        self.net_text.invalidate();
+        self.disk_text.invalidate();
+        self.core_grid.invalidate();
+        self.clock.invalidate();
+        self.date.invalidate();
+        self.uptime.invalidate();
+        self.analog_clock.invalidate();
+        if let Some(animation) = &mut self.animation {
+            animation.invalidate();
+        }
+        self.notification.invalidate();
+        self.notification_frame_drawn = false;
+    }
 
-    widget_renderer.render_text(&layout, fonts, 500, 100, fg_color)?;
+    /// Re-evaluates the color rules of the widgets that have any, redrawing those whose color
+    /// changed, such as a blinking level switching over.
+    fn render_rules(&mut self, widget_renderer: &mut WidgetRenderer, now: Instant) -> Result<()> {
+        let snapshot = self.registry.snapshot();
+        self.cpu_gauge.update(snapshot, now);
+        self.cpu_gauge.render(widget_renderer)?;
+        self.mem_bar.update(snapshot, now);
+        self.mem_bar.render(widget_renderer)
+    }
 
-    Ok(())
+    fn render_metrics(&mut self, widget_renderer: &mut WidgetRenderer) -> Result<()> {
+        // Widgets read from the same snapshot, so they all show values from the same poll
+        let snapshot = self.registry.snapshot().clone();
+
+        let bg_color = Color::new(63, 67, 81);
+        let fg_color = Color::new(228, 207, 154);
+
+        // Draw bar graph
+        widget_renderer.render_graph_background(0, 250, 200, 100, bg_color)?;
+
+        let mut bar_graph_data = vec![0; 300];
+        let mut rng = rand::thread_rng();
+        let distr = rand::distributions::Uniform::new_inclusive(0u8, 100u8);
+        for x in &mut bar_graph_data {
+            *x = rng.sample(distr);
+        }
+
+        widget_renderer.render_bar_graph(0, 250, 100, bg_color, fg_color, &bar_graph_data)?;
+
+        // Draw download graph, scaled to at least 1 Mb/s
+        widget_renderer.render_graph_background(320, 250, 200, NET_GRAPH_WIDTH, fg_color)?;
+
+        let net_scale = self.net_rx_history.auto_scale(125_000.0);
+        let line_graph_data = self.net_rx_history.graph_data(NET_GRAPH_HEIGHT, net_scale);
+        widget_renderer.render_line_graph(
+            320,
+            250,
+            NET_GRAPH_WIDTH,
+            bg_color,
+            fg_color,
+            &line_graph_data,
+        )?;
+
+        // Draw grid with pixels
+        let mut grid_points: Vec<Point> = vec![];
+        for x in 200..=400 {
+            for y in 100..=300 {
+                if x % 100 == 0 || y % 100 == 0 {
+                    grid_points.push(Point::new(x - 50, y - 50));
+                }
+            }
+        }
+
+        widget_renderer.render_pixels(fg_color, &grid_points)?;
+
+        // Draw text
+        let usage = self.usage_template.render(&snapshot);
+        self.usage_text
+            .set_foreground(usage.color.unwrap_or(fg_color));
+        self.usage_text.set_text(usage.text);
+        self.usage_text.render(widget_renderer)?;
+
+        // Draw gauges
+        // Missing metrics, like usage that couldn't be measured yet, leave the widgets as they are
+        if let Some(cpu_usage) = self.registry.ratio("cpu.usage") {
+            self.cpu_gauge.widget_mut().set_value(cpu_usage);
+        }
+        self.cpu_gauge.update(&snapshot, Instant::now());
+        self.cpu_gauge.render(widget_renderer)?;
+
+        self.gpu_gauge
+            .set_value(self.registry.ratio("gpu.usage").unwrap_or(0.0));
+        self.gpu_gauge.render(widget_renderer)?;
+
+        // Draw memory bar
+        if let Some(mem_usage) = self.registry.ratio("mem.usage") {
+            self.mem_bar.widget_mut().set_value(mem_usage);
+        }
+        self.mem_bar.update(&snapshot, Instant::now());
+        self.mem_bar.render(widget_renderer)?;
+
+        let mem_text = self.mem_template.render(&snapshot);
+        self.mem_text.set_text(mem_text.text);
+        self.mem_text.render(widget_renderer)?;
+
+        let temp_text = self.temp_template.render(&snapshot);
+        self.temp_text
+            .set_foreground(temp_text.color.unwrap_or(fg_color));
+        self.temp_text.set_text(temp_text.text);
+        self.temp_text.render(widget_renderer)?;
+
+        let net_text = self.net_template.render(&snapshot);
+        self.net_text.set_text(net_text.text);
+        self.net_text.render(widget_renderer)?;
+
+        let disk_text = self.disk_template.render(&snapshot);
+        self.disk_text
+            .set_foreground(disk_text.color.unwrap_or(fg_color));
+        self.disk_text.set_text(disk_text.text);
+        self.disk_text.render(widget_renderer)?;
+
+        // Draw per-core usage
+        let core_usage: Vec<f64> = (0..)
+            .map_while(|idx| self.registry.ratio(&format!("cpu.core.{}.usage", idx)))
+            .collect();
+        if !core_usage.is_empty() {
+            self.core_grid.set_values(&core_usage);
+        }
+        self.core_grid.render(widget_renderer)?;
+
+        Ok(())
+    }
 }
 
 struct App {
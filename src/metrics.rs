@@ -0,0 +1,440 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::errors::{ChipsError, Result};
+
+/// Providers that are due within this long of each other are sampled together, so that metrics
+/// on the same interval land in the same snapshot instead of drifting apart.
+const POLL_TOLERANCE: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetricValue {
+    Number(f64),
+    Text(String),
+}
+
+impl MetricValue {
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            MetricValue::Number(value) => Some(*value),
+            MetricValue::Text(_) => None,
+        }
+    }
+}
+
+impl From<f64> for MetricValue {
+    fn from(value: f64) -> Self {
+        MetricValue::Number(value)
+    }
+}
+
+impl From<String> for MetricValue {
+    fn from(value: String) -> Self {
+        MetricValue::Text(value)
+    }
+}
+
+impl From<&str> for MetricValue {
+    fn from(value: &str) -> Self {
+        MetricValue::Text(value.to_string())
+    }
+}
+
+/// The values of all metrics at one point in time, keyed by dotted names like `cpu.usage`.
+#[derive(Debug, Clone, Default)]
+pub struct MetricSnapshot {
+    values: HashMap<String, MetricValue>,
+}
+
+impl MetricSnapshot {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&mut self, name: impl Into<String>, value: impl Into<MetricValue>) {
+        self.values.insert(name.into(), value.into());
+    }
+
+    pub fn get(&self, name: &str) -> Option<&MetricValue> {
+        self.values.get(name)
+    }
+
+    /// Copies every value from `other`, replacing any with the same name.
+    pub fn extend(&mut self, other: &MetricSnapshot) {
+        self.values.extend(
+            other
+                .values
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone())),
+        );
+    }
+}
+
+/// Turns a device name or path into one part of a metric name. It's lowercased, with anything
+/// other than letters and digits replaced by `_` and left off the ends, so "Wi-Fi" becomes
+/// `wi_fi` and `/home/data` becomes `home_data`.
+pub fn metric_name_segment(name: &str) -> String {
+    name.trim_matches(|c: char| !c.is_alphanumeric())
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    /// Plain numbers and text.
+    None,
+    Percent,
+    Bytes,
+    BytesPerSecond,
+    Celsius,
+    Megahertz,
+    Watts,
+    Rpm,
+}
+
+impl Unit {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Unit::None => "",
+            Unit::Percent => "%",
+            Unit::Bytes => "B",
+            Unit::BytesPerSecond => "B/s",
+            Unit::Celsius => "°C",
+            Unit::Megahertz => "MHz",
+            Unit::Watts => "W",
+            Unit::Rpm => "RPM",
+        }
+    }
+}
+
+/// Describes a metric a provider sets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricInfo {
+    /// The metric's name, where a `*` segment stands for any one segment, like
+    /// `cpu.core.*.usage` for metrics whose names depend on the hardware.
+    pub name: String,
+    pub unit: Unit,
+    /// The values the metric is expected to stay within, for scaling gauges and bars.
+    pub range: Option<(f64, f64)>,
+}
+
+impl MetricInfo {
+    pub fn new(name: impl Into<String>, unit: Unit) -> Self {
+        Self {
+            name: name.into(),
+            unit,
+            range: None,
+        }
+    }
+
+    pub fn range(mut self, min: f64, max: f64) -> Self {
+        self.range = Some((min, max));
+        self
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        let mut segments = name.split('.');
+        self.name.split('.').all(|pattern| {
+            segments
+                .next()
+                .is_some_and(|s| pattern == "*" || pattern == s)
+        }) && segments.next().is_none()
+    }
+
+    /// Where the value sits within the range, from 0 to 1, or `None` without a range.
+    pub fn ratio(&self, value: f64) -> Option<f64> {
+        let (min, max) = self.range?;
+        if max <= min {
+            return None;
+        }
+
+        Some(((value - min) / (max - min)).clamp(0.0, 1.0))
+    }
+}
+
+/// A source of metrics, sampled by a [`MetricRegistry`] at its own interval.
+pub trait MetricProvider {
+    /// The metrics this provider sets. Names must be unique across all providers.
+    fn metrics(&self) -> Vec<MetricInfo>;
+
+    fn interval(&self) -> Duration;
+
+    /// Reads the current values of this provider's metrics. Returns `None` if nothing could be
+    /// measured this time, such as when a usage sample would be too short, in which case the
+    /// previous values are kept.
+    fn sample(&mut self) -> Result<Option<MetricSnapshot>>;
+}
+
+struct RegisteredProvider {
+    provider: Box<dyn MetricProvider>,
+    metrics: Vec<MetricInfo>,
+    due: Instant,
+    latest: MetricSnapshot,
+}
+
+/// Samples a set of providers, each at its own interval, and combines their latest values into
+/// one snapshot.
+#[derive(Default)]
+pub struct MetricRegistry {
+    providers: Vec<RegisteredProvider>,
+    /// Values set from outside, such as through the control API.
+    pushed: MetricSnapshot,
+    /// Whether a value was pushed since the last poll.
+    changed: bool,
+    snapshot: MetricSnapshot,
+}
+
+impl MetricRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a provider, to be sampled on the next poll. Fails if it declares a metric that
+    /// another provider already has.
+    pub fn register(&mut self, provider: impl MetricProvider + 'static) -> Result<()> {
+        let metrics = provider.metrics();
+        if let Some(duplicate) = metrics
+            .iter()
+            .find(|info| self.metrics().any(|other| other.name == info.name))
+        {
+            return Err(ChipsError::DuplicateMetric(duplicate.name.clone()));
+        }
+
+        self.providers.push(RegisteredProvider {
+            provider: Box::new(provider),
+            metrics,
+            due: Instant::now(),
+            latest: MetricSnapshot::new(),
+        });
+        Ok(())
+    }
+
+    pub fn metrics(&self) -> impl Iterator<Item = &MetricInfo> {
+        self.providers
+            .iter()
+            .flat_map(|registered| registered.metrics.iter())
+    }
+
+    pub fn info(&self, name: &str) -> Option<&MetricInfo> {
+        self.metrics().find(|info| info.matches(name))
+    }
+
+    /// When the next provider is due, or `None` without any providers.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.providers.iter().map(|registered| registered.due).min()
+    }
+
+    /// Sets a metric that no provider has, keeping it until it's pushed again. The next poll
+    /// reports a change.
+    pub fn push(&mut self, name: &str, value: impl Into<MetricValue>) -> Result<()> {
+        if self.info(name).is_some() {
+            return Err(ChipsError::DuplicateMetric(name.to_string()));
+        }
+
+        let value = value.into();
+        self.pushed.set(name, value.clone());
+        self.snapshot.set(name, value);
+        self.changed = true;
+        Ok(())
+    }
+
+    /// Samples every provider that's due, and returns whether any were or a value was pushed. A
+    /// provider that fails has its metrics removed until it succeeds again, so that stale values
+    /// aren't shown.
+    pub fn poll(&mut self, now: Instant) -> bool {
+        let mut sampled = false;
+        for registered in &mut self.providers {
+            if registered.due > now + POLL_TOLERANCE {
+                continue;
+            }
+
+            registered.due = now + registered.provider.interval();
+            match registered.provider.sample() {
+                Ok(Some(snapshot)) => registered.latest = snapshot,
+                Ok(None) => {}
+                Err(err) => {
+                    println!("{:?}", err);
+                    registered.latest = MetricSnapshot::new();
+                }
+            }
+            sampled = true;
+        }
+
+        if sampled {
+            self.snapshot = self.pushed.clone();
+            for registered in &self.providers {
+                self.snapshot.extend(&registered.latest);
+            }
+        }
+        sampled || std::mem::take(&mut self.changed)
+    }
+
+    /// The latest values of every provider, which only changes when polled.
+    pub fn snapshot(&self) -> &MetricSnapshot {
+        &self.snapshot
+    }
+
+    /// Gets where a metric's latest value sits within its range, from 0 to 1.
+    pub fn ratio(&self, name: &str) -> Option<f64> {
+        let value = self.snapshot.get(name)?.as_number()?;
+        self.info(name)?.ratio(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    /// Replays scripted samples, which are shared so that tests can check what's left.
+    struct FakeProvider {
+        metrics: Vec<MetricInfo>,
+        interval: Duration,
+        samples: Rc<RefCell<Vec<Result<Option<MetricSnapshot>>>>>,
+    }
+
+    impl FakeProvider {
+        fn new(name: &str, interval: Duration) -> Self {
+            Self {
+                metrics: vec![MetricInfo::new(name, Unit::Percent).range(0.0, 100.0)],
+                interval,
+                samples: Rc::new(RefCell::new(vec![])),
+            }
+        }
+    }
+
+    impl MetricProvider for FakeProvider {
+        fn metrics(&self) -> Vec<MetricInfo> {
+            self.metrics.clone()
+        }
+
+        fn interval(&self) -> Duration {
+            self.interval
+        }
+
+        fn sample(&mut self) -> Result<Option<MetricSnapshot>> {
+            self.samples.borrow_mut().remove(0)
+        }
+    }
+
+    fn snapshot_of(name: &str, value: f64) -> Result<Option<MetricSnapshot>> {
+        let mut snapshot = MetricSnapshot::new();
+        snapshot.set(name, value);
+        Ok(Some(snapshot))
+    }
+
+    fn number(registry: &MetricRegistry, name: &str) -> Option<f64> {
+        registry.snapshot().get(name)?.as_number()
+    }
+
+    #[test]
+    fn samples_providers_at_their_own_interval() {
+        let fast = FakeProvider::new("fast", Duration::from_secs(1));
+        let slow = FakeProvider::new("slow", Duration::from_secs(10));
+        let fast_samples = fast.samples.clone();
+        let slow_samples = slow.samples.clone();
+        fast_samples
+            .borrow_mut()
+            .extend([snapshot_of("fast", 1.0), snapshot_of("fast", 2.0)]);
+        slow_samples.borrow_mut().push(snapshot_of("slow", 10.0));
+
+        let mut registry = MetricRegistry::new();
+        registry.register(fast).unwrap();
+        registry.register(slow).unwrap();
+
+        let start = Instant::now();
+        assert!(registry.poll(start));
+        assert_eq!(number(&registry, "fast"), Some(1.0));
+        assert_eq!(number(&registry, "slow"), Some(10.0));
+        assert_eq!(registry.next_due(), Some(start + Duration::from_secs(1)));
+
+        // Nothing is due yet
+        assert!(!registry.poll(start + Duration::from_millis(500)));
+
+        // Only the fast provider is sampled again, and the slow one's value is kept
+        assert!(registry.poll(start + Duration::from_secs(1)));
+        assert_eq!(number(&registry, "fast"), Some(2.0));
+        assert_eq!(number(&registry, "slow"), Some(10.0));
+        assert!(slow_samples.borrow().is_empty());
+    }
+
+    #[test]
+    fn keeps_values_when_nothing_was_measured_and_clears_them_on_errors() {
+        let provider = FakeProvider::new("value", Duration::from_secs(1));
+        let samples = provider.samples.clone();
+        samples.borrow_mut().extend([
+            snapshot_of("value", 50.0),
+            Ok(None),
+            Err(ChipsError::InvalidSystemInfo("unplugged".to_string())),
+        ]);
+
+        let mut registry = MetricRegistry::new();
+        registry.register(provider).unwrap();
+
+        let start = Instant::now();
+        registry.poll(start);
+        assert_eq!(registry.ratio("value"), Some(0.5));
+        registry.poll(start + Duration::from_secs(1));
+        assert_eq!(number(&registry, "value"), Some(50.0));
+        registry.poll(start + Duration::from_secs(2));
+        assert_eq!(number(&registry, "value"), None);
+    }
+
+    #[test]
+    fn rejects_duplicate_metrics() {
+        let mut registry = MetricRegistry::new();
+        registry
+            .register(FakeProvider::new("cpu.usage", Duration::from_secs(1)))
+            .unwrap();
+        assert!(matches!(
+            registry.register(FakeProvider::new("cpu.usage", Duration::from_secs(2))),
+            Err(ChipsError::DuplicateMetric(name)) if name == "cpu.usage"
+        ));
+    }
+
+    #[test]
+    fn keeps_pushed_values() {
+        let provider = FakeProvider::new("provided", Duration::from_secs(1));
+        provider
+            .samples
+            .borrow_mut()
+            .extend([snapshot_of("provided", 1.0), snapshot_of("provided", 2.0)]);
+
+        let mut registry = MetricRegistry::new();
+        registry.register(provider).unwrap();
+        assert!(registry.push("provided", 5.0).is_err());
+
+        let start = Instant::now();
+        registry.poll(start);
+        registry.push("room.temperature", 21.5).unwrap();
+        assert_eq!(number(&registry, "room.temperature"), Some(21.5));
+
+        // The push counts as a change even though no provider is due
+        assert!(registry.poll(start + Duration::from_millis(500)));
+        assert!(!registry.poll(start + Duration::from_millis(600)));
+
+        registry.poll(start + Duration::from_secs(1));
+        assert_eq!(number(&registry, "provided"), Some(2.0));
+        assert_eq!(number(&registry, "room.temperature"), Some(21.5));
+    }
+
+    #[test]
+    fn matches_wildcard_segments() {
+        let info = MetricInfo::new("cpu.core.*.usage", Unit::Percent).range(0.0, 100.0);
+        assert!(info.matches("cpu.core.12.usage"));
+        assert!(!info.matches("cpu.core.12.frequency"));
+        assert!(!info.matches("cpu.core.usage"));
+        assert!(!info.matches("cpu.core.1.usage.max"));
+        assert_eq!(info.ratio(150.0), Some(1.0));
+        assert_eq!(MetricInfo::new("mem.used", Unit::Bytes).ratio(1.0), None);
+    }
+}
@@ -0,0 +1,727 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossbeam::channel::{unbounded, Receiver, Sender};
+
+use crate::errors::{error_message, ChipsError, Result};
+use crate::metrics::{MetricInfo, MetricProvider, MetricSnapshot, MetricValue, Unit};
+use crate::sources::{json_value, parse_duration, parse_sections, parse_value};
+
+const DEFAULT_PORT: u16 = 1883;
+const DEFAULT_KEEP_ALIVE: Duration = Duration::from_secs(30);
+
+/// How long connecting, and reading the rest of a packet once it's started, may take.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// How often received messages are picked up, as with external sources.
+const RESULT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// Control packet types, from the top 4 bits of the first byte
+const CONNECT: u8 = 1;
+const CONNACK: u8 = 2;
+const PUBLISH: u8 = 3;
+const PUBACK: u8 = 4;
+const SUBSCRIBE: u8 = 8;
+const SUBACK: u8 = 9;
+const PINGREQ: u8 = 12;
+
+/// Sets a metric from the messages on a topic.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopicMapping {
+    pub metric: String,
+    /// A topic, which may have `+` and `#` wildcards.
+    pub filter: String,
+    /// Picks a value out of JSON payloads, as with [`crate::sources::ExternalSource`].
+    /// Without one, the payload is the value.
+    pub json_path: Option<String>,
+}
+
+impl TopicMapping {
+    fn value(&self, payload: &[u8]) -> Result<MetricValue> {
+        let text = std::str::from_utf8(payload)
+            .map_err(|_| ChipsError::Mqtt("payload isn't UTF-8 text".to_string()))?;
+        match &self.json_path {
+            Some(path) => json_value(&serde_json::from_str(text)?, path),
+            None => Ok(parse_value(text)),
+        }
+    }
+}
+
+/// A broker to subscribe to, like the one Home Assistant or zigbee2mqtt publish to. Brokers
+/// are configured in sections with a `broker` address, optionally a `client_id`, `username`,
+/// `password` and `keep_alive`, and a topic for each metric, followed by a JSON path for JSON
+/// payloads. Without a `client_id`, the broker assigns a unique one, so that several screens
+/// can share a broker without taking over each other's connection:
+///
+/// ```text
+/// [home]
+/// broker = 192.168.1.10:1883
+/// username = screen
+/// password = hunter2
+/// room.temperature = zigbee2mqtt/living_room | temperature
+/// room.humidity = zigbee2mqtt/living_room | humidity
+/// solar.power = home/solar/+/power
+/// ```
+///
+/// Metrics keep their last value until another message comes in. The section name is only used
+/// for `<name>.error`, which is set when the broker can't be reached or a payload can't be read,
+/// until the next value comes in.
+/// Messages are received at most once, without TLS.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MqttSource {
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub keep_alive: Duration,
+    pub mappings: Vec<TopicMapping>,
+}
+
+impl MqttSource {
+    pub fn parse_all(config: &str) -> Result<Vec<Self>> {
+        parse_sections(config)?
+            .into_iter()
+            .map(|(name, options)| {
+                let invalid = |message: &str| {
+                    ChipsError::InvalidSourceConfig(format!("[{}] {}", name, message))
+                };
+
+                let mut broker = None;
+                let mut source = Self {
+                    name: name.clone(),
+                    host: String::new(),
+                    port: DEFAULT_PORT,
+                    client_id: String::new(),
+                    username: None,
+                    password: None,
+                    keep_alive: DEFAULT_KEEP_ALIVE,
+                    mappings: vec![],
+                };
+                for (key, value) in options {
+                    let bad_value = || invalid(&format!("has invalid {} {:?}", key, value));
+                    match key.as_str() {
+                        "broker" => broker = Some(value),
+                        "client_id" => source.client_id = value,
+                        "username" => source.username = Some(value),
+                        "password" => source.password = Some(value),
+                        "keep_alive" => {
+                            // Sent in whole seconds, where 0 would turn the keep alive off
+                            source.keep_alive = parse_duration(&value)
+                                .filter(|keep_alive| {
+                                    (1..=u16::MAX as u64).contains(&keep_alive.as_secs())
+                                })
+                                .ok_or_else(bad_value)?
+                        }
+                        _ => {
+                            let (filter, json_path) = match value.split_once('|') {
+                                Some((filter, path)) => (filter.trim(), Some(path.trim())),
+                                None => (value.as_str(), None),
+                            };
+                            if filter.is_empty() || json_path.is_some_and(str::is_empty) {
+                                return Err(bad_value());
+                            }
+                            source.mappings.push(TopicMapping {
+                                metric: key,
+                                filter: filter.to_string(),
+                                json_path: json_path.map(str::to_string),
+                            });
+                        }
+                    }
+                }
+
+                let broker = broker.ok_or_else(|| invalid("needs a broker"))?;
+                (source.host, source.port) = match broker.rsplit_once(':') {
+                    Some((host, port)) => (
+                        host.to_string(),
+                        port.parse()
+                            .map_err(|_| invalid(&format!("has invalid broker {:?}", broker)))?,
+                    ),
+                    None => (broker, DEFAULT_PORT),
+                };
+                // Brokers close the connection on a SUBSCRIBE without any topics
+                if source.mappings.is_empty() {
+                    return Err(invalid("needs a topic for at least one metric"));
+                }
+                if source.password.is_some() && source.username.is_none() {
+                    return Err(invalid("needs a username to go with the password"));
+                }
+
+                Ok(source)
+            })
+            .collect()
+    }
+
+    /// The distinct topic filters to subscribe to.
+    fn filters(&self) -> Vec<&str> {
+        let mut filters: Vec<&str> = vec![];
+        for mapping in &self.mappings {
+            if !filters.contains(&mapping.filter.as_str()) {
+                filters.push(&mapping.filter);
+            }
+        }
+        filters
+    }
+}
+
+/// Whether a topic matches a filter, where `+` matches one level and a trailing `#` matches
+/// any number of levels. Topics starting with `$` are only matched explicitly.
+fn topic_matches(filter: &str, topic: &str) -> bool {
+    if topic.starts_with('$') && filter.starts_with(['+', '#']) {
+        return false;
+    }
+
+    let mut topic_levels = topic.split('/');
+    for level in filter.split('/') {
+        if level == "#" {
+            return true;
+        }
+        match topic_levels.next() {
+            Some(topic_level) if level == "+" || level == topic_level => {}
+            _ => return false,
+        }
+    }
+    topic_levels.next().is_none()
+}
+
+/// Frames a packet, prefixing the body with its length.
+fn packet(first_byte: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![first_byte];
+    let mut len = body.len();
+    loop {
+        let byte = (len % 128) as u8;
+        len /= 128;
+        if len == 0 {
+            packet.push(byte);
+            break;
+        }
+        packet.push(byte | 0x80);
+    }
+    packet.extend_from_slice(body);
+    packet
+}
+
+fn push_str(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    buf.extend_from_slice(s.as_bytes());
+}
+
+/// Splits a length-prefixed string off the start of `buf`.
+fn split_str(buf: &[u8]) -> Result<(String, &[u8])> {
+    let truncated = || ChipsError::Mqtt("truncated packet".to_string());
+    let len = u16::from_be_bytes([
+        *buf.first().ok_or_else(truncated)?,
+        *buf.get(1).ok_or_else(truncated)?,
+    ]) as usize;
+    let s = buf.get(2..2 + len).ok_or_else(truncated)?;
+    Ok((String::from_utf8_lossy(s).into_owned(), &buf[2 + len..]))
+}
+
+fn connect_packet(source: &MqttSource) -> Vec<u8> {
+    // Always starts a clean session, since nothing is kept between connections anyway
+    let mut flags = 0x02;
+    if source.username.is_some() {
+        flags |= 0x80;
+    }
+    if source.password.is_some() {
+        flags |= 0x40;
+    }
+
+    let mut body = vec![];
+    push_str(&mut body, "MQTT");
+    body.push(4); // Protocol level of MQTT 3.1.1
+    body.push(flags);
+    body.extend_from_slice(&(source.keep_alive.as_secs() as u16).to_be_bytes());
+    push_str(&mut body, &source.client_id);
+    for credential in [&source.username, &source.password].into_iter().flatten() {
+        push_str(&mut body, credential);
+    }
+    packet(CONNECT << 4, &body)
+}
+
+/// Subscribes to every filter at QoS 0, so that brokers send each message at most once.
+fn subscribe_packet(filters: &[&str]) -> Vec<u8> {
+    let mut body = 1u16.to_be_bytes().to_vec();
+    for filter in filters {
+        push_str(&mut body, filter);
+        body.push(0);
+    }
+    packet((SUBSCRIBE << 4) | 0x02, &body)
+}
+
+/// Reads a packet, returning its first byte and its body. Fails with
+/// [`ChipsError::TimedOut`] if nothing arrives within the stream's read timeout.
+fn read_packet(mut stream: &TcpStream) -> Result<(u8, Vec<u8>)> {
+    let closed = || ChipsError::Mqtt("connection closed".to_string());
+    let mut first_byte = [0];
+    match stream.read(&mut first_byte) {
+        Ok(0) => return Err(closed()),
+        Ok(_) => {}
+        Err(err)
+            if matches!(
+                err.kind(),
+                io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+            ) =>
+        {
+            return Err(ChipsError::TimedOut)
+        }
+        Err(err) => return Err(err.into()),
+    }
+
+    // The rest of the packet should follow right away
+    stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+    let mut len = 0;
+    for shift in (0..4).map(|idx| idx * 7) {
+        let mut byte = [0];
+        stream.read_exact(&mut byte)?;
+        len |= ((byte[0] & 0x7f) as usize) << shift;
+        if byte[0] & 0x80 == 0 {
+            let mut body = vec![0; len];
+            stream.read_exact(&mut body)?;
+            return Ok((first_byte[0], body));
+        }
+    }
+    Err(ChipsError::Mqtt("invalid packet length".to_string()))
+}
+
+/// Splits a PUBLISH packet into its topic, packet ID if it needs acknowledging, and payload.
+fn parse_publish(first_byte: u8, body: &[u8]) -> Result<(String, Option<u16>, &[u8])> {
+    let (topic, rest) = split_str(body)?;
+    if (first_byte >> 1) & 0x03 == 0 {
+        return Ok((topic, None, rest));
+    }
+
+    let packet_id = rest
+        .get(..2)
+        .ok_or_else(|| ChipsError::Mqtt("truncated packet".to_string()))?;
+    Ok((
+        topic,
+        Some(u16::from_be_bytes([packet_id[0], packet_id[1]])),
+        &rest[2..],
+    ))
+}
+
+enum Update {
+    Connected,
+    Value(String, MetricValue),
+    Failed(String),
+}
+
+/// Connects and forwards messages until the connection fails, or returns `Ok` once the
+/// updates are no longer received.
+fn run(source: &MqttSource, updates: &Sender<Update>) -> Result<()> {
+    let addr = (source.host.as_str(), source.port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| ChipsError::Mqtt(format!("can't resolve {}", source.host)))?;
+    let mut stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?;
+    stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+    stream.write_all(&connect_packet(source))?;
+
+    let (first_byte, body) = read_packet(&stream)?;
+    if first_byte >> 4 != CONNACK || body.len() < 2 {
+        return Err(ChipsError::Mqtt("expected CONNACK".to_string()));
+    }
+    if body[1] != 0 {
+        return Err(ChipsError::Mqtt(format!(
+            "connection refused with code {}",
+            body[1]
+        )));
+    }
+    stream.write_all(&subscribe_packet(&source.filters()))?;
+    let mut last_sent = Instant::now();
+    if updates.send(Update::Connected).is_err() {
+        return Ok(());
+    }
+
+    loop {
+        // The broker disconnects clients that stay quiet for longer than the keep alive
+        let ping_at = last_sent + source.keep_alive / 2;
+        let wait = ping_at.saturating_duration_since(Instant::now());
+        if wait.is_zero() {
+            stream.write_all(&packet(PINGREQ << 4, &[]))?;
+            last_sent = Instant::now();
+            continue;
+        }
+
+        stream.set_read_timeout(Some(wait))?;
+        let (first_byte, body) = match read_packet(&stream) {
+            Ok(packet) => packet,
+            Err(ChipsError::TimedOut) => continue,
+            Err(err) => return Err(err),
+        };
+        match first_byte >> 4 {
+            PUBLISH => {
+                let (topic, packet_id, payload) = parse_publish(first_byte, &body)?;
+                if let Some(packet_id) = packet_id {
+                    stream.write_all(&packet(PUBACK << 4, &packet_id.to_be_bytes()))?;
+                    last_sent = Instant::now();
+                }
+
+                for mapping in source
+                    .mappings
+                    .iter()
+                    .filter(|mapping| topic_matches(&mapping.filter, &topic))
+                {
+                    let update = match mapping.value(payload) {
+                        Ok(value) => Update::Value(mapping.metric.clone(), value),
+                        Err(err) => Update::Failed(format!("{}: {}", topic, error_message(&err))),
+                    };
+                    if updates.send(update).is_err() {
+                        return Ok(());
+                    }
+                }
+            }
+            SUBACK if body.iter().skip(2).any(|code| *code == 0x80) => {
+                return Err(ChipsError::Mqtt("subscription refused".to_string()));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Provides the metrics of an [`MqttSource`]. The connection is kept on its own thread, and
+/// is retried every few seconds after it fails.
+pub struct MqttMetrics {
+    name: String,
+    metrics: Vec<String>,
+    updates: Receiver<Update>,
+    values: MetricSnapshot,
+    error: Option<String>,
+}
+
+impl MqttMetrics {
+    /// Connects to the broker in the background. The thread stops after this is dropped, once
+    /// it has something to send.
+    pub fn spawn(source: MqttSource) -> Self {
+        let name = source.name.clone();
+        let mut metrics: Vec<String> = vec![];
+        for mapping in &source.mappings {
+            if !metrics.contains(&mapping.metric) {
+                metrics.push(mapping.metric.clone());
+            }
+        }
+
+        let (sender, updates) = unbounded();
+        thread::spawn(move || loop {
+            if let Err(err) = run(&source, &sender) {
+                if sender.send(Update::Failed(error_message(&err))).is_err() {
+                    break;
+                }
+            } else {
+                break;
+            }
+            thread::sleep(RECONNECT_DELAY);
+        });
+
+        Self {
+            name,
+            metrics,
+            updates,
+            values: MetricSnapshot::new(),
+            error: None,
+        }
+    }
+}
+
+impl MetricProvider for MqttMetrics {
+    fn metrics(&self) -> Vec<MetricInfo> {
+        self.metrics
+            .iter()
+            .map(|metric| MetricInfo::new(metric.as_str(), Unit::None))
+            .chain([MetricInfo::new(format!("{}.error", self.name), Unit::None)])
+            .collect()
+    }
+
+    fn interval(&self) -> Duration {
+        RESULT_CHECK_INTERVAL
+    }
+
+    fn sample(&mut self) -> Result<Option<MetricSnapshot>> {
+        let mut received = false;
+        for update in self.updates.try_iter() {
+            match update {
+                Update::Connected => self.error = None,
+                Update::Value(metric, value) => {
+                    // A message coming through means the connection and its payloads are fine
+                    self.error = None;
+                    self.values.set(metric, value);
+                }
+                Update::Failed(err) => {
+                    // Reconnecting fails the same way until the broker is back
+                    if self.error.as_ref() != Some(&err) {
+                        println!("{}: {}", self.name, err);
+                    }
+                    self.error = Some(err);
+                }
+            }
+            received = true;
+        }
+        if !received {
+            return Ok(None);
+        }
+
+        let mut snapshot = self.values.clone();
+        if let Some(err) = &self.error {
+            snapshot.set(format!("{}.error", self.name), err.as_str());
+        }
+        Ok(Some(snapshot))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    fn source(port: u16, mappings: &[(&str, &str, Option<&str>)]) -> MqttSource {
+        MqttSource {
+            name: "home".to_string(),
+            host: "127.0.0.1".to_string(),
+            port,
+            client_id: String::new(),
+            username: Some("screen".to_string()),
+            password: None,
+            keep_alive: DEFAULT_KEEP_ALIVE,
+            mappings: mappings
+                .iter()
+                .map(|(metric, filter, json_path)| TopicMapping {
+                    metric: metric.to_string(),
+                    filter: filter.to_string(),
+                    json_path: json_path.map(str::to_string),
+                })
+                .collect(),
+        }
+    }
+
+    fn publish(topic: &str, payload: &str, packet_id: Option<u16>) -> Vec<u8> {
+        let mut body = vec![];
+        push_str(&mut body, topic);
+        let mut first_byte = PUBLISH << 4;
+        if let Some(packet_id) = packet_id {
+            first_byte |= 0x02;
+            body.extend_from_slice(&packet_id.to_be_bytes());
+        }
+        body.extend_from_slice(payload.as_bytes());
+        packet(first_byte, &body)
+    }
+
+    /// The filters a stub broker was subscribed to, and the packet ID it got a PUBACK for.
+    type Received = (Vec<String>, Vec<u8>);
+
+    /// Accepts one client, checks its CONNECT and SUBSCRIBE, and sends it `messages`. Returns
+    /// the port, and the filters and acknowledgements the broker received.
+    fn stub_broker(messages: Vec<Vec<u8>>) -> (u16, thread::JoinHandle<Received>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let broker = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.set_read_timeout(Some(CONNECT_TIMEOUT)).unwrap();
+
+            let (first_byte, body) = read_packet(&stream).unwrap();
+            assert_eq!(first_byte, CONNECT << 4);
+            let (protocol, rest) = split_str(&body).unwrap();
+            assert_eq!(protocol, "MQTT");
+            assert_eq!(rest[1], 0x82, "clean session with a username");
+            let (client_id, rest) = split_str(&rest[4..]).unwrap();
+            assert_eq!(client_id, "");
+            assert_eq!(split_str(rest).unwrap().0, "screen");
+            stream.write_all(&packet(CONNACK << 4, &[0, 0])).unwrap();
+
+            let (first_byte, body) = read_packet(&stream).unwrap();
+            assert_eq!(first_byte, (SUBSCRIBE << 4) | 0x02);
+            let mut filters = vec![];
+            let mut rest = &body[2..];
+            while !rest.is_empty() {
+                let (filter, after) = split_str(rest).unwrap();
+                filters.push(filter);
+                rest = &after[1..];
+            }
+            stream
+                .write_all(&packet(SUBACK << 4, &[0, 1, 0, 0]))
+                .unwrap();
+
+            for message in messages {
+                stream.write_all(&message).unwrap();
+            }
+
+            // Collect the acknowledgement of the QoS 1 message
+            let (first_byte, body) = read_packet(&stream).unwrap();
+            assert_eq!(first_byte, PUBACK << 4);
+            (filters, body)
+        });
+        (port, broker)
+    }
+
+    #[test]
+    fn parses_config() {
+        let sources = MqttSource::parse_all(
+            "
+            [home]
+            broker = 192.168.1.10
+            keep_alive = 60s
+            room.temperature = zigbee2mqtt/living_room | temperature
+            solar.power = home/solar/+/power
+            ",
+        )
+        .unwrap();
+        assert_eq!(sources[0].host, "192.168.1.10");
+        assert_eq!(sources[0].port, DEFAULT_PORT);
+        assert_eq!(sources[0].keep_alive, Duration::from_secs(60));
+        assert_eq!(
+            sources[0].mappings,
+            vec![
+                TopicMapping {
+                    metric: "room.temperature".to_string(),
+                    filter: "zigbee2mqtt/living_room".to_string(),
+                    json_path: Some("temperature".to_string()),
+                },
+                TopicMapping {
+                    metric: "solar.power".to_string(),
+                    filter: "home/solar/+/power".to_string(),
+                    json_path: None,
+                },
+            ]
+        );
+
+        for invalid in [
+            "[home]\nroom.temperature = a/b",
+            "[home]\nbroker = localhost",
+            "[home]\nbroker = localhost:mqtt",
+            "[home]\nbroker = localhost\npassword = hunter2",
+            "[home]\nbroker = localhost\nroom.temperature = a/b |",
+            "[home]\nbroker = localhost\nkeep_alive = 500ms",
+            "[home]\nbroker = localhost\nkeep_alive = 1200m",
+        ] {
+            assert!(MqttSource::parse_all(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn matches_topics() {
+        assert!(topic_matches("home/solar/+/power", "home/solar/roof/power"));
+        assert!(!topic_matches("home/solar/+/power", "home/solar/power"));
+        assert!(topic_matches("home/#", "home"));
+        assert!(topic_matches("home/#", "home/kitchen/temperature"));
+        assert!(!topic_matches("home/kitchen", "home/kitchen/temperature"));
+        assert!(!topic_matches("#", "$SYS/broker/uptime"));
+        assert!(topic_matches("$SYS/broker/uptime", "$SYS/broker/uptime"));
+    }
+
+    #[test]
+    fn frames_packets() {
+        assert_eq!(packet(PINGREQ << 4, &[]), vec![0xc0, 0x00]);
+        let long = packet(PUBLISH << 4, &[0; 321]);
+        assert_eq!(&long[..3], &[0x30, 0xc1, 0x02]);
+        assert_eq!(long.len(), 3 + 321);
+    }
+
+    #[test]
+    fn receives_messages_from_broker() {
+        let (port, broker) = stub_broker(vec![
+            publish(
+                "zigbee2mqtt/living_room",
+                r#"{"temperature": 21.5, "humidity": 40}"#,
+                None,
+            ),
+            publish("home/solar/roof/power", "1250", None),
+            publish("home/solar/shed/power", "not json", None),
+            publish("home/door", "open", Some(7)),
+        ]);
+        let mut metrics = MqttMetrics::spawn(source(
+            port,
+            &[
+                (
+                    "room.temperature",
+                    "zigbee2mqtt/living_room",
+                    Some("temperature"),
+                ),
+                ("room.humidity", "zigbee2mqtt/living_room", Some("humidity")),
+                ("solar.power", "home/solar/+/power", None),
+                ("door", "home/door", None),
+            ],
+        ));
+
+        let (filters, ack) = broker.join().unwrap();
+        assert_eq!(
+            filters,
+            vec!["zigbee2mqtt/living_room", "home/solar/+/power", "home/door"]
+        );
+        assert_eq!(ack, vec![0, 7]);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut snapshot = MetricSnapshot::new();
+        while snapshot.get("door").is_none() {
+            assert!(Instant::now() < deadline);
+            if let Some(sampled) = metrics.sample().unwrap() {
+                snapshot = sampled;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(
+            snapshot.get("room.temperature"),
+            Some(&MetricValue::Number(21.5))
+        );
+        assert_eq!(
+            snapshot.get("room.humidity"),
+            Some(&MetricValue::Number(40.0))
+        );
+        // The later message wasn't a number, but without a JSON path it's still a value
+        assert_eq!(
+            snapshot.get("solar.power"),
+            Some(&MetricValue::Text("not json".to_string()))
+        );
+        assert_eq!(
+            snapshot.get("door"),
+            Some(&MetricValue::Text("open".to_string()))
+        );
+    }
+
+    #[test]
+    fn clears_error_once_values_arrive() {
+        let (sender, updates) = unbounded();
+        let mut metrics = MqttMetrics {
+            name: "home".to_string(),
+            metrics: vec!["door".to_string()],
+            updates,
+            values: MetricSnapshot::new(),
+            error: None,
+        };
+        let error = |snapshot: &MetricSnapshot| snapshot.get("home.error").cloned();
+
+        sender
+            .send(Update::Failed("door: not a number".to_string()))
+            .unwrap();
+        let snapshot = metrics.sample().unwrap().unwrap();
+        assert_eq!(error(&snapshot), Some("door: not a number".into()));
+
+        sender
+            .send(Update::Value("door".to_string(), "open".into()))
+            .unwrap();
+        let snapshot = metrics.sample().unwrap().unwrap();
+        assert_eq!(error(&snapshot), None);
+        assert_eq!(snapshot.get("door"), Some(&"open".into()));
+
+        assert!(metrics.sample().unwrap().is_none());
+    }
+
+    #[test]
+    fn reports_bad_payloads() {
+        let mapping = TopicMapping {
+            metric: "room.temperature".to_string(),
+            filter: "room".to_string(),
+            json_path: Some("temperature".to_string()),
+        };
+        assert_eq!(
+            mapping.value(br#"{"temperature": "19.5"}"#).unwrap(),
+            MetricValue::Number(19.5)
+        );
+        assert!(mapping.value(b"19.5 degrees").is_err());
+        assert!(mapping.value(br#"{"humidity": 40}"#).is_err());
+    }
+}
@@ -0,0 +1,218 @@
+#[cfg(windows)]
+use windows::Win32::NetworkManagement::{
+    IpHelper::{FreeMibTable, GetIfTable2, IF_TYPE_SOFTWARE_LOOPBACK, MIB_IF_TABLE2},
+    Ndis::IfOperStatusUp,
+};
+
+use crate::counter_sampler::{Counters, Rates};
+#[cfg(target_os = "linux")]
+use crate::errors::ChipsError;
+use crate::errors::Result;
+use crate::metrics::{metric_name_segment, MetricSnapshot};
+
+/// Cumulative traffic through one network interface.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceCounters {
+    pub name: String,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    /// Whether the interface is a physical adapter, as opposed to loopback or virtual interfaces
+    /// whose traffic would be counted twice in the total.
+    pub physical: bool,
+}
+
+/// Transfer rates, in bytes per second.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Throughput {
+    pub rx: f64,
+    pub tx: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NetworkThroughput {
+    /// Summed over the physical interfaces.
+    pub total: Throughput,
+    pub interfaces: Vec<(String, Throughput)>,
+}
+
+impl NetworkThroughput {
+    /// Collects the rates from a [`crate::counter_sampler::CounterSampler`], totalling only the
+    /// physical interfaces.
+    pub fn from_rates(rates: Rates<InterfaceCounters>) -> Self {
+        let mut throughput = Self::default();
+        for (interface, [rx, tx]) in rates {
+            if interface.physical {
+                throughput.total.rx += rx;
+                throughput.total.tx += tx;
+            }
+            throughput
+                .interfaces
+                .push((interface.name, Throughput { rx, tx }));
+        }
+        throughput
+    }
+
+    /// Sets `net.rx` and `net.tx` to the total, and `net.<interface>.rx` and `net.<interface>.tx`
+    /// for each interface, in bytes per second. Interfaces are named as by
+    /// [`metric_name_segment`], so "Wi-Fi" becomes `net.wi_fi.rx`.
+    pub fn write_metrics(&self, snapshot: &mut MetricSnapshot) {
+        snapshot.set("net.rx", self.total.rx);
+        snapshot.set("net.tx", self.total.tx);
+        for (name, throughput) in &self.interfaces {
+            let name = metric_name_segment(name);
+            snapshot.set(format!("net.{}.rx", name), throughput.rx);
+            snapshot.set(format!("net.{}.tx", name), throughput.tx);
+        }
+    }
+}
+
+impl Counters for InterfaceCounters {
+    fn key(&self) -> &str {
+        &self.name
+    }
+
+    fn values(&self) -> [u64; 2] {
+        [self.rx_bytes, self.tx_bytes]
+    }
+}
+
+/// Reads the counters of every interface that's up, skipping the filter drivers Windows lists
+/// alongside each adapter.
+#[cfg(windows)]
+pub fn read_interface_counters() -> Result<Vec<InterfaceCounters>> {
+    // Bits of InterfaceAndOperStatusFlags
+    const HARDWARE_INTERFACE: u8 = 1 << 0;
+    const FILTER_INTERFACE: u8 = 1 << 1;
+
+    let mut table: *mut MIB_IF_TABLE2 = std::ptr::null_mut();
+    unsafe { GetIfTable2(&mut table) }.ok()?;
+
+    let rows = unsafe {
+        std::slice::from_raw_parts((*table).Table.as_ptr(), (*table).NumEntries as usize)
+    };
+    let counters = rows
+        .iter()
+        .filter(|row| {
+            row.InterfaceAndOperStatusFlags._bitfield & FILTER_INTERFACE == 0
+                && row.OperStatus == IfOperStatusUp
+        })
+        .map(|row| {
+            let alias_len = row
+                .Alias
+                .iter()
+                .position(|c| *c == 0)
+                .unwrap_or(row.Alias.len());
+            InterfaceCounters {
+                name: String::from_utf16_lossy(&row.Alias[..alias_len]),
+                rx_bytes: row.InOctets,
+                tx_bytes: row.OutOctets,
+                physical: row.Type != IF_TYPE_SOFTWARE_LOOPBACK
+                    && row.InterfaceAndOperStatusFlags._bitfield & HARDWARE_INTERFACE != 0,
+            }
+        })
+        .collect();
+
+    unsafe { FreeMibTable(table as *const _) };
+    Ok(counters)
+}
+
+/// Reads the counters of every interface. Physical interfaces are told apart by having a
+/// device in sysfs.
+#[cfg(target_os = "linux")]
+pub fn read_interface_counters() -> Result<Vec<InterfaceCounters>> {
+    let mut counters = parse_net_dev(&std::fs::read_to_string("/proc/net/dev")?)?;
+    for interface in &mut counters {
+        interface.physical = std::path::Path::new("/sys/class/net")
+            .join(&interface.name)
+            .join("device")
+            .exists();
+    }
+    Ok(counters)
+}
+
+/// Parses `/proc/net/dev`, where each interface has 8 receive counters followed by 8 transmit
+/// counters, starting with the byte counts. Everything but loopback is assumed to be physical.
+#[cfg(target_os = "linux")]
+fn parse_net_dev(net_dev: &str) -> Result<Vec<InterfaceCounters>> {
+    // The first two lines are headers
+    net_dev
+        .lines()
+        .skip(2)
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let invalid = || ChipsError::InvalidSystemInfo(format!("net/dev line {:?}", line));
+            let (name, fields) = line.split_once(':').ok_or_else(invalid)?;
+            let fields = fields
+                .split_whitespace()
+                .map(|field| field.parse::<u64>().map_err(|_| invalid()))
+                .collect::<Result<Vec<u64>>>()?;
+            if fields.len() < 16 {
+                return Err(invalid());
+            }
+
+            let name = name.trim().to_string();
+            Ok(InterfaceCounters {
+                physical: name != "lo",
+                name,
+                rx_bytes: fields[0],
+                tx_bytes: fields[8],
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counters(name: &str, rx_bytes: u64, tx_bytes: u64, physical: bool) -> InterfaceCounters {
+        InterfaceCounters {
+            name: name.to_string(),
+            rx_bytes,
+            tx_bytes,
+            physical,
+        }
+    }
+
+    #[test]
+    fn totals_physical_interfaces() {
+        let throughput = NetworkThroughput::from_rates(vec![
+            (counters("eth0", 5000, 2500, true), [2000.0, 1000.0]),
+            (counters("lo", 8000, 8000, false), [4000.0, 4000.0]),
+            (counters("Wi-Fi", 100, 100, true), [50.0, 0.0]),
+        ]);
+        assert_eq!(
+            throughput.total,
+            Throughput {
+                rx: 2050.0,
+                tx: 1000.0
+            }
+        );
+
+        let mut snapshot = MetricSnapshot::new();
+        throughput.write_metrics(&mut snapshot);
+        let number = |name: &str| snapshot.get(name).and_then(|value| value.as_number());
+        assert_eq!(number("net.rx"), Some(2050.0));
+        assert_eq!(number("net.lo.tx"), Some(4000.0));
+        assert_eq!(number("net.wi_fi.rx"), Some(50.0));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn parses_net_dev() {
+        let net_dev = "\
+Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+    lo: 1296418   12345    0    0    0     0          0         0  1296418   12345    0    0    0     0       0          0
+enp5s0: 987654321 654321    0   12    0     0          0      3456 123456789 234567    0    0    0     0       0          0
+";
+        assert_eq!(
+            parse_net_dev(net_dev).unwrap(),
+            vec![
+                counters("lo", 1296418, 1296418, false),
+                counters("enp5s0", 987654321, 123456789, true),
+            ]
+        );
+        assert!(parse_net_dev("\n\neth0: 1 2 3\n").is_err());
+    }
+}
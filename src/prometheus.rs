@@ -0,0 +1,520 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crossbeam::channel::Receiver;
+
+use crate::errors::{ChipsError, Result};
+use crate::http::{self, HttpUrl};
+use crate::metrics::{MetricInfo, MetricProvider, MetricSnapshot, Unit};
+use crate::sources::{parse_duration, parse_sections, spawn_polling};
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(5);
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How often finished scrapes are picked up, as with external sources.
+const RESULT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// One sample of a scrape, like `node_load1 0.42` or
+/// `node_filesystem_avail_bytes{mountpoint="/"} 1.2e+11`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Series {
+    pub name: String,
+    /// Sorted by name, so that the same series always has the same labels.
+    pub labels: Vec<(String, String)>,
+    pub value: f64,
+}
+
+/// Parses the Prometheus text format. Comments, including `# HELP` and `# TYPE`, are skipped,
+/// as are timestamps.
+pub fn parse_exposition(text: &str) -> Result<Vec<Series>> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let invalid = || ChipsError::InvalidExposition(line.to_string());
+            let (name, rest) = split_name(line).ok_or_else(invalid)?;
+            let (matchers, rest) = split_labels(rest).ok_or_else(invalid)?;
+            let mut labels = matchers
+                .into_iter()
+                .map(|(label, op, value)| (op == MatchOp::Equal).then_some((label, value)))
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(invalid)?;
+            labels.sort();
+
+            let value = rest.split_whitespace().next().ok_or_else(invalid)?;
+            Ok(Series {
+                name: name.to_string(),
+                labels,
+                value: parse_float(value).ok_or_else(invalid)?,
+            })
+        })
+        .collect()
+}
+
+/// Parses sample values, which may be `NaN`, `+Inf` or `-Inf` as well as numbers.
+fn parse_float(value: &str) -> Option<f64> {
+    match value {
+        "+Inf" | "Inf" => Some(f64::INFINITY),
+        "-Inf" => Some(f64::NEG_INFINITY),
+        "NaN" => Some(f64::NAN),
+        value => value.parse().ok(),
+    }
+}
+
+/// Splits a metric name, made of letters, digits, `_` and `:`, off the start of a line.
+fn split_name(s: &str) -> Option<(&str, &str)> {
+    let end = s
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == ':'))
+        .unwrap_or(s.len());
+    let name = &s[..end];
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    Some((name, &s[end..]))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MatchOp {
+    Equal,
+    NotEqual,
+}
+
+type Matcher = (String, MatchOp, String);
+
+/// Splits a `{label="value",...}` block off the start of `s`, if there is one. Samples only
+/// use `=`, while selectors can also use `!=`.
+fn split_labels(s: &str) -> Option<(Vec<Matcher>, &str)> {
+    let Some(mut rest) = s.trim_start().strip_prefix('{') else {
+        return Some((vec![], s));
+    };
+
+    let mut labels = vec![];
+    loop {
+        rest = rest.trim_start();
+        if let Some(rest) = rest.strip_prefix('}') {
+            return Some((labels, rest));
+        }
+
+        let (label, after) = split_name(rest)?;
+        let after = after.trim_start();
+        let (op, after) = if let Some(after) = after.strip_prefix("!=") {
+            (MatchOp::NotEqual, after)
+        } else {
+            (MatchOp::Equal, after.strip_prefix('=')?)
+        };
+
+        // Label values are quoted, with `\\`, `\"` and `\n` escapes
+        let mut chars = after.trim_start().strip_prefix('"')?.char_indices();
+        let mut value = String::new();
+        let end = loop {
+            match chars.next()? {
+                (idx, '"') => break idx,
+                (_, '\\') => match chars.next()?.1 {
+                    'n' => value.push('\n'),
+                    c => value.push(c),
+                },
+                (_, c) => value.push(c),
+            }
+        };
+        labels.push((label.to_string(), op, value));
+
+        rest = after.trim_start()[1 + end + 1..].trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest);
+    }
+}
+
+/// Picks series out of a scrape, like `node_network_receive_bytes_total{device!="lo"}`.
+/// Labels a series doesn't have count as empty, as in PromQL.
+///
+/// Wrapping a selector in `rate(...)` turns counters into per-second rates between scrapes.
+/// When several series match, their values or rates are summed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selector {
+    name: String,
+    matchers: Vec<Matcher>,
+    rate: bool,
+}
+
+impl FromStr for Selector {
+    type Err = ChipsError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || ChipsError::InvalidSourceConfig(format!("invalid selector {:?}", s));
+        let (selector, rate) = match s.trim().strip_prefix("rate(") {
+            Some(inner) => (inner.strip_suffix(')').ok_or_else(invalid)?, true),
+            None => (s.trim(), false),
+        };
+
+        let (name, rest) = split_name(selector.trim()).ok_or_else(invalid)?;
+        let (matchers, rest) = split_labels(rest).ok_or_else(invalid)?;
+        if !rest.trim().is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            matchers,
+            rate,
+        })
+    }
+}
+
+impl Selector {
+    fn matches(&self, series: &Series) -> bool {
+        series.name == self.name
+            && self.matchers.iter().all(|(label, op, value)| {
+                let actual = series
+                    .labels
+                    .iter()
+                    .find(|(name, _)| name == label)
+                    .map_or("", |(_, value)| value.as_str());
+                (actual == value) == (*op == MatchOp::Equal)
+            })
+    }
+}
+
+/// An endpoint to scrape, and the metrics to take from it. Targets are configured in sections
+/// with a `url`, optionally an `interval` and `timeout`, and a selector for each metric:
+///
+/// ```text
+/// [node]
+/// url = http://127.0.0.1:9100/metrics
+/// interval = 5s
+/// node.load = node_load1
+/// node.root.available = node_filesystem_avail_bytes{mountpoint="/"}
+/// node.net.rx = rate(node_network_receive_bytes_total{device!="lo"})
+/// ```
+///
+/// The section name is only used for `<name>.error`, which is set while scrapes fail.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrometheusTarget {
+    pub name: String,
+    pub url: HttpUrl,
+    pub interval: Duration,
+    pub timeout: Duration,
+    pub metrics: Vec<(String, Selector)>,
+}
+
+impl PrometheusTarget {
+    pub fn parse_all(config: &str) -> Result<Vec<Self>> {
+        parse_sections(config)?
+            .into_iter()
+            .map(|(name, options)| {
+                let invalid = |message: &str| {
+                    ChipsError::InvalidSourceConfig(format!("[{}] {}", name, message))
+                };
+
+                let mut url = None;
+                let mut interval = DEFAULT_INTERVAL;
+                let mut timeout = DEFAULT_TIMEOUT;
+                let mut metrics = vec![];
+                for (key, value) in options {
+                    let bad_value = || invalid(&format!("has invalid {} {:?}", key, value));
+                    match key.as_str() {
+                        "url" => url = Some(value.parse()?),
+                        "interval" => interval = parse_duration(&value).ok_or_else(bad_value)?,
+                        "timeout" => timeout = parse_duration(&value).ok_or_else(bad_value)?,
+                        _ => metrics.push((key, value.parse()?)),
+                    }
+                }
+
+                Ok(Self {
+                    url: url.ok_or_else(|| invalid("needs a url"))?,
+                    name,
+                    interval,
+                    timeout,
+                    metrics,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Identifies a series across scrapes.
+type SeriesKey = (String, Vec<(String, String)>);
+
+/// Turns scrapes of a target into metrics, keeping the counters from the previous scrape for
+/// rates.
+struct Scraper {
+    target: PrometheusTarget,
+    previous: Option<(Instant, HashMap<SeriesKey, f64>)>,
+}
+
+impl Scraper {
+    fn new(target: PrometheusTarget) -> Self {
+        Self {
+            target,
+            previous: None,
+        }
+    }
+
+    fn scrape(&mut self) -> Result<MetricSnapshot> {
+        let text = http::get(&self.target.url, self.target.timeout)?;
+        Ok(self.evaluate(&parse_exposition(&text)?, Instant::now()))
+    }
+
+    /// Sets each metric with matching series. Rates are left out of the first scrape, and
+    /// series whose counters went backwards are left out of their rate.
+    fn evaluate(&mut self, scrape: &[Series], now: Instant) -> MetricSnapshot {
+        let seconds = self
+            .previous
+            .as_ref()
+            .and_then(|(at, _)| now.checked_duration_since(*at))
+            .map(|elapsed| elapsed.as_secs_f64())
+            .filter(|seconds| *seconds > 0.0);
+
+        let mut snapshot = MetricSnapshot::new();
+        let mut counters = HashMap::new();
+        for (metric, selector) in &self.target.metrics {
+            let mut total = None;
+            for series in scrape.iter().filter(|series| selector.matches(series)) {
+                let value = if selector.rate {
+                    let key = (series.name.clone(), series.labels.clone());
+                    let last = self
+                        .previous
+                        .as_ref()
+                        .and_then(|(_, previous)| previous.get(&key).copied());
+                    counters.insert(key, series.value);
+                    match (last, seconds) {
+                        (Some(last), Some(seconds)) if series.value >= last => {
+                            (series.value - last) / seconds
+                        }
+                        _ => continue,
+                    }
+                } else {
+                    series.value
+                };
+                *total.get_or_insert(0.0) += value;
+            }
+
+            if let Some(total) = total {
+                snapshot.set(metric.as_str(), total);
+            }
+        }
+
+        self.previous = Some((now, counters));
+        snapshot
+    }
+}
+
+/// Provides the metrics of a [`PrometheusTarget`], scraped on its own thread like
+/// [`crate::sources::ExternalMetrics`].
+pub struct PrometheusMetrics {
+    name: String,
+    metrics: Vec<String>,
+    results: Receiver<Result<MetricSnapshot, String>>,
+    /// The last error printed, so that a target failing the same way isn't printed every time.
+    last_error: Option<String>,
+}
+
+impl PrometheusMetrics {
+    /// Starts scraping the target on its interval. The thread stops once this is dropped.
+    pub fn spawn(target: PrometheusTarget) -> Self {
+        let name = target.name.clone();
+        let metrics = target
+            .metrics
+            .iter()
+            .map(|(metric, _)| metric.clone())
+            .collect();
+        let interval = target.interval;
+        let mut scraper = Scraper::new(target);
+        Self {
+            name,
+            metrics,
+            results: spawn_polling(interval, move || scraper.scrape()),
+            last_error: None,
+        }
+    }
+}
+
+impl MetricProvider for PrometheusMetrics {
+    fn metrics(&self) -> Vec<MetricInfo> {
+        self.metrics
+            .iter()
+            .map(|metric| MetricInfo::new(metric.as_str(), Unit::None))
+            .chain([MetricInfo::new(format!("{}.error", self.name), Unit::None)])
+            .collect()
+    }
+
+    fn interval(&self) -> Duration {
+        RESULT_CHECK_INTERVAL
+    }
+
+    fn sample(&mut self) -> Result<Option<MetricSnapshot>> {
+        match self.results.try_iter().last() {
+            None => Ok(None),
+            Some(Ok(snapshot)) => {
+                self.last_error = None;
+                Ok(Some(snapshot))
+            }
+            Some(Err(err)) => {
+                if self.last_error.as_ref() != Some(&err) {
+                    println!("{}: {}", self.name, err);
+                }
+                let mut snapshot = MetricSnapshot::new();
+                snapshot.set(format!("{}.error", self.name), err.as_str());
+                self.last_error = Some(err);
+                Ok(Some(snapshot))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::tests::{ok, serve_once};
+    use crate::metrics::MetricValue;
+
+    const NODE_EXPORTER: &str = r#"# HELP node_load1 1m load average.
+# TYPE node_load1 gauge
+node_load1 0.42
+# TYPE node_filesystem_avail_bytes gauge
+node_filesystem_avail_bytes{device="/dev/nvme0n1p2",fstype="ext4",mountpoint="/"} 1.2e+11
+node_filesystem_avail_bytes{device="/dev/nvme0n1p1",fstype="vfat",mountpoint="/boot/efi"} 5.0e+08
+node_network_receive_bytes_total{device="eth0"} 1000
+node_network_receive_bytes_total{device="lo"} 5000
+node_network_receive_bytes_total{device="wlan0"} 200
+node_hwmon_chip_names{chip="platform_coretemp_0",chip_name="coretemp"} 1 1700000000000
+node_textfile_scrape_error{path="C:\\metrics",note="say \"hi\""} NaN
+"#;
+
+    fn target(metrics: &[(&str, &str)]) -> PrometheusTarget {
+        PrometheusTarget {
+            name: "node".to_string(),
+            url: "http://127.0.0.1:9100/metrics".parse().unwrap(),
+            interval: DEFAULT_INTERVAL,
+            timeout: DEFAULT_TIMEOUT,
+            metrics: metrics
+                .iter()
+                .map(|(metric, selector)| (metric.to_string(), selector.parse().unwrap()))
+                .collect(),
+        }
+    }
+
+    fn number(snapshot: &MetricSnapshot, name: &str) -> Option<f64> {
+        snapshot.get(name)?.as_number()
+    }
+
+    #[test]
+    fn parses_exposition() {
+        let series = parse_exposition(NODE_EXPORTER).unwrap();
+        assert_eq!(series.len(), 8);
+        assert_eq!(
+            series[0],
+            Series {
+                name: "node_load1".to_string(),
+                labels: vec![],
+                value: 0.42,
+            }
+        );
+        assert_eq!(series[1].value, 1.2e11);
+        assert_eq!(
+            series[1].labels[2],
+            ("mountpoint".to_string(), "/".to_string())
+        );
+        assert_eq!(series[6].value, 1.0);
+        assert_eq!(
+            series[7].labels,
+            vec![
+                ("note".to_string(), "say \"hi\"".to_string()),
+                ("path".to_string(), "C:\\metrics".to_string()),
+            ]
+        );
+        assert!(series[7].value.is_nan());
+
+        assert!(parse_exposition("node_load1").is_err());
+        assert!(parse_exposition("node_load1 high").is_err());
+        assert!(parse_exposition("node_load1{cpu=\"0} 1").is_err());
+        assert!(parse_exposition("node_load1{cpu!=\"0\"} 1").is_err());
+    }
+
+    #[test]
+    fn parses_config() {
+        let targets = PrometheusTarget::parse_all(
+            r#"
+            [node]
+            url = http://127.0.0.1:9100/metrics
+            interval = 10s
+            node.load = node_load1
+            node.net.rx = rate(node_network_receive_bytes_total{device!="lo"})
+            "#,
+        )
+        .unwrap();
+        assert_eq!(targets.len(), 1);
+        assert_eq!(targets[0].interval, Duration::from_secs(10));
+        assert_eq!(targets[0].metrics[1].0, "node.net.rx");
+        assert!(targets[0].metrics[1].1.rate);
+
+        assert!(PrometheusTarget::parse_all("[node]\nnode.load = node_load1").is_err());
+        for selector in [
+            "1node",
+            "rate(node_load1",
+            "node_load1{cpu}",
+            "node_load1 extra",
+        ] {
+            assert!(selector.parse::<Selector>().is_err(), "{}", selector);
+        }
+    }
+
+    #[test]
+    fn selects_and_sums_series() {
+        let mut scraper = Scraper::new(target(&[
+            ("load", "node_load1"),
+            ("root", r#"node_filesystem_avail_bytes{mountpoint="/"}"#),
+            ("disks", "node_filesystem_avail_bytes"),
+            (
+                "physical",
+                r#"node_network_receive_bytes_total{device!="lo"}"#,
+            ),
+            ("tagged", r#"node_load1{instance=""}"#),
+            ("missing", "node_cpu_seconds_total"),
+        ]));
+        let snapshot = scraper.evaluate(&parse_exposition(NODE_EXPORTER).unwrap(), Instant::now());
+        assert_eq!(number(&snapshot, "load"), Some(0.42));
+        assert_eq!(number(&snapshot, "root"), Some(1.2e11));
+        assert_eq!(number(&snapshot, "disks"), Some(1.205e11));
+        assert_eq!(number(&snapshot, "physical"), Some(1200.0));
+        assert_eq!(number(&snapshot, "tagged"), Some(0.42));
+        assert_eq!(snapshot.get("missing"), None);
+    }
+
+    #[test]
+    fn computes_rates_between_scrapes() {
+        let mut scraper = Scraper::new(target(&[(
+            "rx",
+            r#"rate(node_network_receive_bytes_total{device!="lo"})"#,
+        )]));
+        let scrape = |eth0: u64, wlan0: u64| {
+            parse_exposition(&format!(
+                "node_network_receive_bytes_total{{device=\"eth0\"}} {}\n\
+                 node_network_receive_bytes_total{{device=\"wlan0\"}} {}\n",
+                eth0, wlan0
+            ))
+            .unwrap()
+        };
+
+        let start = Instant::now();
+        assert_eq!(scraper.evaluate(&scrape(1000, 200), start).get("rx"), None);
+        let snapshot = scraper.evaluate(&scrape(3000, 400), start + Duration::from_secs(2));
+        assert_eq!(number(&snapshot, "rx"), Some(1100.0));
+
+        // wlan0 was reset, so only eth0 counts
+        let snapshot = scraper.evaluate(&scrape(5000, 10), start + Duration::from_secs(4));
+        assert_eq!(number(&snapshot, "rx"), Some(1000.0));
+    }
+
+    #[test]
+    fn scrapes_endpoint() {
+        let url = serve_once(ok(NODE_EXPORTER), "/metrics");
+        let mut scraper = Scraper::new(PrometheusTarget {
+            url,
+            ..target(&[("load", "node_load1")])
+        });
+        assert_eq!(
+            scraper.scrape().unwrap().get("load"),
+            Some(&MetricValue::Number(0.42))
+        );
+    }
+}
@@ -0,0 +1,310 @@
+use std::time::{Duration, Instant};
+
+use crate::counter_sampler::CounterSampler;
+use crate::cpu_sampler::CpuSampler;
+use crate::disks::{
+    read_disk_counters, read_disk_space, write_space_metrics, DiskCounters, DiskThroughput,
+};
+use crate::errors::Result;
+use crate::gpu::{GpuSelector, Gpus};
+use crate::metrics::{MetricInfo, MetricProvider, MetricSnapshot, Unit};
+use crate::network::{read_interface_counters, InterfaceCounters, NetworkThroughput};
+use crate::sensors::{SensorKind, Sensors};
+use crate::system_info::{get_core_frequencies, read_memory_stats, SystemTimes};
+
+/// How often usage and throughput metrics are sampled.
+const USAGE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Disk space changes slowly, and checking it can wake up sleeping drives.
+const DISK_SPACE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The usual range of temperature gauges.
+const TEMPERATURE_RANGE: (f64, f64) = (0.0, 100.0);
+
+fn percent(name: &str) -> MetricInfo {
+    MetricInfo::new(name, Unit::Percent).range(0.0, 100.0)
+}
+
+/// `cpu.usage`, and `cpu.core.<n>.usage` and `cpu.core.<n>.frequency` for each logical
+/// processor.
+pub struct CpuMetrics {
+    sampler: CpuSampler<SystemTimes>,
+}
+
+impl CpuMetrics {
+    /// Takes a baseline sample of the CPU times, which the first reading is relative to.
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            sampler: CpuSampler::new(SystemTimes)?,
+        })
+    }
+}
+
+impl MetricProvider for CpuMetrics {
+    fn metrics(&self) -> Vec<MetricInfo> {
+        vec![
+            percent("cpu.usage"),
+            percent("cpu.core.*.usage"),
+            MetricInfo::new("cpu.core.*.frequency", Unit::Megahertz),
+        ]
+    }
+
+    fn interval(&self) -> Duration {
+        USAGE_INTERVAL
+    }
+
+    fn sample(&mut self) -> Result<Option<MetricSnapshot>> {
+        // TODO: This is an undercount compared to Task Manager, why?
+        let Some(usage) = self.sampler.cpu_usage()? else {
+            return Ok(None);
+        };
+
+        let mut snapshot = MetricSnapshot::new();
+        snapshot.set("cpu.usage", usage.value * 100.0);
+
+        // Only covers the logical processors in the current processor group
+        if let Some(cores) = self.sampler.core_usage()? {
+            let frequencies = get_core_frequencies(cores.value.len());
+            for (idx, usage) in cores.value.iter().enumerate() {
+                snapshot.set(format!("cpu.core.{}.usage", idx), usage * 100.0);
+                if let Some(mhz) = frequencies.as_ref().and_then(|mhz| mhz.get(idx)) {
+                    snapshot.set(format!("cpu.core.{}.frequency", idx), *mhz);
+                }
+            }
+        }
+
+        Ok(Some(snapshot))
+    }
+}
+
+/// `mem.*` and `swap.*`, in bytes except for `mem.usage`.
+pub struct MemoryMetrics {
+    total: u64,
+}
+
+impl MemoryMetrics {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            total: read_memory_stats()?.total,
+        })
+    }
+}
+
+impl MetricProvider for MemoryMetrics {
+    fn metrics(&self) -> Vec<MetricInfo> {
+        let bytes = |name: &str| MetricInfo::new(name, Unit::Bytes).range(0.0, self.total as f64);
+        vec![
+            percent("mem.usage"),
+            bytes("mem.used"),
+            bytes("mem.total"),
+            bytes("mem.available"),
+            bytes("mem.cached"),
+            MetricInfo::new("swap.used", Unit::Bytes),
+            MetricInfo::new("swap.total", Unit::Bytes),
+        ]
+    }
+
+    fn interval(&self) -> Duration {
+        USAGE_INTERVAL
+    }
+
+    fn sample(&mut self) -> Result<Option<MetricSnapshot>> {
+        let stats = read_memory_stats()?;
+        let mut snapshot = MetricSnapshot::new();
+        snapshot.set("mem.usage", stats.usage() * 100.0);
+        snapshot.set("mem.used", stats.used as f64);
+        snapshot.set("mem.total", stats.total as f64);
+        snapshot.set("mem.available", stats.available as f64);
+        snapshot.set("mem.cached", stats.cached as f64);
+        snapshot.set("swap.used", stats.swap_used as f64);
+        snapshot.set("swap.total", stats.swap_total as f64);
+        Ok(Some(snapshot))
+    }
+}
+
+/// The `gpu.*` metrics of one GPU, as listed on [`crate::gpu::GpuSample::write_metrics`].
+pub struct GpuMetrics {
+    gpus: Gpus,
+    selector: GpuSelector,
+}
+
+impl GpuMetrics {
+    pub fn new(gpus: Gpus, selector: GpuSelector) -> Self {
+        Self { gpus, selector }
+    }
+}
+
+impl MetricProvider for GpuMetrics {
+    fn metrics(&self) -> Vec<MetricInfo> {
+        let (min, max) = TEMPERATURE_RANGE;
+        vec![
+            percent("gpu.usage"),
+            MetricInfo::new("gpu.temperature", Unit::Celsius).range(min, max),
+            MetricInfo::new("gpu.memory.used", Unit::Bytes),
+            MetricInfo::new("gpu.memory.total", Unit::Bytes),
+            percent("gpu.memory.usage"),
+            percent("gpu.memory.utilization"),
+            MetricInfo::new("gpu.power", Unit::Watts),
+            MetricInfo::new("gpu.power.limit", Unit::Watts),
+            percent("gpu.power.usage"),
+            MetricInfo::new("gpu.clock.core", Unit::Megahertz),
+            MetricInfo::new("gpu.clock.memory", Unit::Megahertz),
+            percent("gpu.fan"),
+            percent("gpu.encoder"),
+            percent("gpu.decoder"),
+        ]
+    }
+
+    fn interval(&self) -> Duration {
+        USAGE_INTERVAL
+    }
+
+    fn sample(&mut self) -> Result<Option<MetricSnapshot>> {
+        let mut snapshot = MetricSnapshot::new();
+        if let Some(sample) = self.gpus.sample(&self.selector)? {
+            sample.write_metrics(&mut snapshot);
+        }
+        Ok(Some(snapshot))
+    }
+}
+
+/// Whichever metrics the hardware sensors are mapped to.
+pub struct SensorMetrics {
+    sensors: Sensors,
+}
+
+impl SensorMetrics {
+    pub fn new(sensors: Sensors) -> Self {
+        Self { sensors }
+    }
+}
+
+impl MetricProvider for SensorMetrics {
+    fn metrics(&self) -> Vec<MetricInfo> {
+        let (min, max) = TEMPERATURE_RANGE;
+        self.sensors
+            .list()
+            .filter_map(|sensor| {
+                let metric = sensor.metric.as_deref()?;
+                Some(match sensor.kind {
+                    SensorKind::Temperature => {
+                        MetricInfo::new(metric, Unit::Celsius).range(min, max)
+                    }
+                    SensorKind::Fan => MetricInfo::new(metric, Unit::Rpm),
+                })
+            })
+            .collect()
+    }
+
+    fn interval(&self) -> Duration {
+        USAGE_INTERVAL
+    }
+
+    fn sample(&mut self) -> Result<Option<MetricSnapshot>> {
+        let mut snapshot = MetricSnapshot::new();
+        self.sensors.write_metrics(&mut snapshot);
+        Ok(Some(snapshot))
+    }
+}
+
+/// `net.rx` and `net.tx` in total and for each interface, as listed on
+/// [`crate::network::NetworkThroughput::write_metrics`].
+pub struct NetworkMetrics {
+    sampler: CounterSampler<InterfaceCounters>,
+}
+
+impl NetworkMetrics {
+    /// Takes a baseline sample of the interface counters, which the first reading is relative to.
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            sampler: CounterSampler::new(read_interface_counters()?, Instant::now()),
+        })
+    }
+}
+
+impl MetricProvider for NetworkMetrics {
+    fn metrics(&self) -> Vec<MetricInfo> {
+        ["net.rx", "net.tx", "net.*.rx", "net.*.tx"]
+            .into_iter()
+            .map(|name| MetricInfo::new(name, Unit::BytesPerSecond))
+            .collect()
+    }
+
+    fn interval(&self) -> Duration {
+        USAGE_INTERVAL
+    }
+
+    fn sample(&mut self) -> Result<Option<MetricSnapshot>> {
+        let counters = read_interface_counters()?;
+        let Some(throughput) = self.sampler.sample(counters, Instant::now()) else {
+            return Ok(None);
+        };
+
+        let mut snapshot = MetricSnapshot::new();
+        NetworkThroughput::from_rates(throughput.value).write_metrics(&mut snapshot);
+        Ok(Some(snapshot))
+    }
+}
+
+/// `disk.<mount>.*`, as listed on [`crate::disks::write_space_metrics`].
+pub struct DiskSpaceMetrics;
+
+impl MetricProvider for DiskSpaceMetrics {
+    fn metrics(&self) -> Vec<MetricInfo> {
+        vec![
+            MetricInfo::new("disk.*.used", Unit::Bytes),
+            MetricInfo::new("disk.*.total", Unit::Bytes),
+            MetricInfo::new("disk.*.available", Unit::Bytes),
+            percent("disk.*.usage"),
+        ]
+    }
+
+    fn interval(&self) -> Duration {
+        DISK_SPACE_INTERVAL
+    }
+
+    fn sample(&mut self) -> Result<Option<MetricSnapshot>> {
+        let mut snapshot = MetricSnapshot::new();
+        write_space_metrics(&read_disk_space()?, &mut snapshot);
+        Ok(Some(snapshot))
+    }
+}
+
+/// `disk.read` and `disk.write` in total and for each disk, as listed on
+/// [`crate::disks::DiskThroughput::write_metrics`].
+pub struct DiskIoMetrics {
+    sampler: CounterSampler<DiskCounters>,
+}
+
+impl DiskIoMetrics {
+    /// Takes a baseline sample of the disk counters, which the first reading is relative to.
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            sampler: CounterSampler::new(read_disk_counters()?, Instant::now()),
+        })
+    }
+}
+
+impl MetricProvider for DiskIoMetrics {
+    fn metrics(&self) -> Vec<MetricInfo> {
+        ["disk.read", "disk.write", "disk.*.read", "disk.*.write"]
+            .into_iter()
+            .map(|name| MetricInfo::new(name, Unit::BytesPerSecond))
+            .collect()
+    }
+
+    fn interval(&self) -> Duration {
+        USAGE_INTERVAL
+    }
+
+    fn sample(&mut self) -> Result<Option<MetricSnapshot>> {
+        let counters = read_disk_counters()?;
+        let Some(throughput) = self.sampler.sample(counters, Instant::now()) else {
+            return Ok(None);
+        };
+
+        let mut snapshot = MetricSnapshot::new();
+        DiskThroughput::from_rates(throughput.value).write_metrics(&mut snapshot);
+        Ok(Some(snapshot))
+    }
+}
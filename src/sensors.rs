@@ -0,0 +1,442 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::errors::{ChipsError, Result};
+use crate::metrics::MetricSnapshot;
+
+/// The mapping used when none is configured, covering the usual CPU, drive and chipset sensors.
+const DEFAULT_SENSOR_MAP: &str = "
+# Intel CPUs
+coretemp/Package id 0 = cpu.temperature
+coretemp/Core * = cpu.core.$1.temperature
+# AMD CPUs, where Tctl can be offset from the real temperature on older models
+k10temp/Tdie = cpu.temperature
+k10temp/Tctl = cpu.temperature
+k10temp/Tccd* = cpu.ccd.$1.temperature
+zenpower/Tdie = cpu.temperature
+thermal/x86_pkg_temp = cpu.temperature
+# NVMe drives, numbered when there's more than one
+nvme*/Composite = disk.nvme$1.temperature
+# Intel chipsets
+pch_*/temp1 = chipset.temperature
+# Motherboard fans, from whichever chip reports them
+*/fan* = fan.$2
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SensorKind {
+    /// In degrees Celsius.
+    Temperature,
+    /// In RPM.
+    Fan,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sensor {
+    /// Identifies the sensor as `chip/label`, like `coretemp/Package id 0`. Thermal zones use
+    /// `thermal` as the chip and their type as the label, like `thermal/acpitz`. Chips or thermal
+    /// zones that share a name are numbered in the order the kernel lists them, like `nvme0` and
+    /// `nvme1`.
+    pub id: String,
+    pub kind: SensorKind,
+    /// The metric this sensor is mapped to, if any.
+    pub metric: Option<String>,
+    path: PathBuf,
+}
+
+impl Sensor {
+    pub fn read(&self) -> Result<f64> {
+        let contents = fs::read_to_string(&self.path)?;
+        let value = contents.trim().parse::<i64>().map_err(|_| {
+            ChipsError::InvalidSystemInfo(format!("sensor {} reads {:?}", self.id, contents))
+        })?;
+
+        Ok(match self.kind {
+            // Temperatures are in millidegrees
+            SensorKind::Temperature => value as f64 / 1000.0,
+            SensorKind::Fan => value as f64,
+        })
+    }
+}
+
+/// Maps sensor ids to metric names, from lines like `coretemp/Core * = cpu.core.$1.temperature`.
+///
+/// Each `*` in a pattern matches any text, which can be used in the metric name as `$1`, `$2` and
+/// so on. Matching ignores ASCII case. When several sensors map to the same metric, the one
+/// matching the earliest line wins, so fallbacks can be listed after the preferred sensor. Blank
+/// lines and lines starting with `#` are ignored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SensorMap {
+    rules: Vec<(String, String)>,
+}
+
+impl Default for SensorMap {
+    fn default() -> Self {
+        DEFAULT_SENSOR_MAP.parse().unwrap()
+    }
+}
+
+impl SensorMap {
+    /// Finds the first matching line, returning its position and the metric name.
+    fn metric(&self, id: &str) -> Option<(usize, String)> {
+        self.rules
+            .iter()
+            .enumerate()
+            .find_map(|(idx, (pattern, metric))| {
+                let captures = wildcard_captures(pattern, id)?;
+                Some((idx, substitute_captures(metric, &captures)))
+            })
+    }
+}
+
+impl FromStr for SensorMap {
+    type Err = ChipsError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut rules = vec![];
+        for line in s.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || ChipsError::InvalidSensorMapping(line.to_string());
+            let (pattern, metric) = line.split_once('=').ok_or_else(invalid)?;
+            let (pattern, metric) = (pattern.trim(), metric.trim());
+            if pattern.is_empty() || metric.is_empty() {
+                return Err(invalid());
+            }
+
+            // Every capture the metric uses must exist in the pattern
+            let wildcards = pattern.matches('*').count();
+            let mut references = metric.split('$').skip(1);
+            if references.any(|reference| {
+                let digits: String = reference.chars().take_while(char::is_ascii_digit).collect();
+                !digits
+                    .parse::<usize>()
+                    .is_ok_and(|n| n >= 1 && n <= wildcards)
+            }) {
+                return Err(invalid());
+            }
+
+            rules.push((pattern.to_string(), metric.to_string()));
+        }
+
+        Ok(Self { rules })
+    }
+}
+
+/// All temperature and fan sensors the kernel reports, discovered once on creation.
+pub struct Sensors {
+    sensors: Vec<Sensor>,
+}
+
+impl Sensors {
+    /// Reads sensors from `/sys/class/hwmon` and `/sys/class/thermal`. On systems without these,
+    /// such as Windows, no sensors are found.
+    pub fn discover(map: &SensorMap) -> Self {
+        Self::with_roots("/sys/class/hwmon", "/sys/class/thermal", map)
+    }
+
+    /// Reads sensors from somewhere other than `/sys/class`.
+    pub fn with_roots(hwmon: impl AsRef<Path>, thermal: impl AsRef<Path>, map: &SensorMap) -> Self {
+        let mut sensors = hwmon_sensors(hwmon.as_ref());
+        sensors.extend(thermal_sensors(thermal.as_ref()));
+
+        // Resolve the mapping in order of the lines the sensors matched, so that each metric
+        // goes to the sensor with the earliest line
+        let mut matches: Vec<(usize, usize, String)> = sensors
+            .iter()
+            .enumerate()
+            .filter_map(|(sensor_idx, sensor)| {
+                let (rule_idx, metric) = map.metric(&sensor.id)?;
+                Some((rule_idx, sensor_idx, metric))
+            })
+            .collect();
+        matches.sort();
+
+        let mut taken = HashSet::new();
+        for (_, sensor_idx, metric) in matches {
+            if taken.insert(metric.clone()) {
+                sensors[sensor_idx].metric = Some(metric);
+            }
+        }
+
+        Self { sensors }
+    }
+
+    pub fn list(&self) -> impl Iterator<Item = &Sensor> {
+        self.sensors.iter()
+    }
+
+    /// Reads each mapped sensor into the snapshot. Sensors that can't be read are left out, since
+    /// some devices stop reporting while they're asleep.
+    pub fn write_metrics(&self, snapshot: &mut MetricSnapshot) {
+        for sensor in &self.sensors {
+            if let Some(metric) = &sensor.metric {
+                if let Ok(value) = sensor.read() {
+                    snapshot.set(metric.clone(), value);
+                }
+            }
+        }
+    }
+}
+
+fn hwmon_sensors(root: &Path) -> Vec<Sensor> {
+    let chips: Vec<(String, PathBuf)> = numbered_entries(root, "hwmon")
+        .into_iter()
+        .filter_map(|(_, path)| Some((read_trimmed(&path.join("name"))?, path)))
+        .collect();
+    let chip_names = number_duplicates(chips.iter().map(|(name, _)| name.as_str()));
+
+    let mut sensors = vec![];
+    for (chip_name, (_, path)) in chip_names.iter().zip(&chips) {
+        // Attributes are named like temp1_input, with an optional temp1_label beside them
+        let mut inputs: Vec<(SensorKind, u32, String)> = fs::read_dir(path)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let channel = name.strip_suffix("_input")?;
+                let (kind, number) = if let Some(number) = channel.strip_prefix("temp") {
+                    (SensorKind::Temperature, number)
+                } else {
+                    (SensorKind::Fan, channel.strip_prefix("fan")?)
+                };
+                Some((kind, number.parse().ok()?, channel.to_string()))
+            })
+            .collect();
+        inputs.sort_by_key(|(kind, number, _)| (*kind == SensorKind::Fan, *number));
+
+        for (kind, _, channel) in inputs {
+            let label = read_trimmed(&path.join(format!("{}_label", channel)))
+                .unwrap_or_else(|| channel.clone());
+            sensors.push(Sensor {
+                id: format!("{}/{}", chip_name, label),
+                kind,
+                metric: None,
+                path: path.join(format!("{}_input", channel)),
+            });
+        }
+    }
+
+    sensors
+}
+
+fn thermal_sensors(root: &Path) -> Vec<Sensor> {
+    let zones: Vec<(String, PathBuf)> = numbered_entries(root, "thermal_zone")
+        .into_iter()
+        .filter_map(|(_, path)| Some((read_trimmed(&path.join("type"))?, path)))
+        .collect();
+    let zone_names = number_duplicates(zones.iter().map(|(name, _)| name.as_str()));
+
+    zone_names
+        .into_iter()
+        .zip(zones)
+        .map(|(name, (_, path))| Sensor {
+            id: format!("thermal/{}", name),
+            kind: SensorKind::Temperature,
+            metric: None,
+            path: path.join("temp"),
+        })
+        .collect()
+}
+
+/// Lists entries named like `hwmon3`, in numeric rather than alphabetical order.
+fn numbered_entries(root: &Path, prefix: &str) -> Vec<(u32, PathBuf)> {
+    let Ok(entries) = fs::read_dir(root) else {
+        return vec![];
+    };
+
+    let mut numbered: Vec<(u32, PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let number = name.strip_prefix(prefix)?.parse::<u32>().ok()?;
+            Some((number, entry.path()))
+        })
+        .collect();
+    numbered.sort();
+    numbered
+}
+
+/// Appends a number to each name that appears more than once, counting from 0.
+fn number_duplicates<'a>(names: impl Iterator<Item = &'a str> + Clone) -> Vec<String> {
+    let mut seen: Vec<&str> = vec![];
+    names
+        .clone()
+        .map(|name| {
+            let is_duplicate = names.clone().filter(|other| *other == name).count() > 1;
+            let number = seen.iter().filter(|other| **other == name).count();
+            seen.push(name);
+            if is_duplicate {
+                format!("{}{}", name, number)
+            } else {
+                name.to_string()
+            }
+        })
+        .collect()
+}
+
+/// Matches `text` against a pattern where `*` matches any text, returning what each `*` matched.
+fn wildcard_captures<'a>(pattern: &str, text: &'a str) -> Option<Vec<&'a str>> {
+    let Some((prefix, rest)) = pattern.split_once('*') else {
+        return text.eq_ignore_ascii_case(pattern).then(Vec::new);
+    };
+
+    let head = text.get(..prefix.len())?;
+    if !head.eq_ignore_ascii_case(prefix) {
+        return None;
+    }
+
+    // Try the shortest capture first, so later wildcards get the rest
+    let remaining = &text[prefix.len()..];
+    remaining
+        .char_indices()
+        .map(|(idx, _)| idx)
+        .chain([remaining.len()])
+        .find_map(|end| {
+            let mut captures = vec![&remaining[..end]];
+            captures.extend(wildcard_captures(rest, &remaining[end..])?);
+            Some(captures)
+        })
+}
+
+/// Replaces `$1`, `$2` and so on with the corresponding capture.
+fn substitute_captures(metric: &str, captures: &[&str]) -> String {
+    let mut parts = metric.split('$');
+    let mut substituted = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        let digits = part.chars().take_while(char::is_ascii_digit).count();
+        let capture = part[..digits]
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| captures.get(n.checked_sub(1)?));
+        substituted.push_str(capture.copied().unwrap_or_default());
+        substituted.push_str(&part[digits..]);
+    }
+    substituted
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|contents| contents.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn captures_wildcards() {
+        assert_eq!(
+            wildcard_captures("coretemp/Core *", "CoreTemp/core 12"),
+            Some(vec!["12"])
+        );
+        assert_eq!(
+            wildcard_captures("*/fan*", "nct6798/fan2"),
+            Some(vec!["nct6798", "2"])
+        );
+        assert_eq!(
+            wildcard_captures("nvme*/Composite", "nvme/Composite"),
+            Some(vec![""])
+        );
+        assert_eq!(wildcard_captures("k10temp/Tctl", "k10temp/Tdie"), None);
+        assert_eq!(
+            substitute_captures("cpu.core.$1.temperature", &["12"]),
+            "cpu.core.12.temperature"
+        );
+    }
+
+    #[test]
+    fn parses_mappings() {
+        let map: SensorMap = "
+            # Comments and blank lines are skipped
+
+            acpitz* = board.temperature
+            it8686/fan* = case.fan.$1
+        "
+        .parse()
+        .unwrap();
+        assert_eq!(
+            map.metric("it8686/fan3"),
+            Some((1, "case.fan.3".to_string()))
+        );
+        assert_eq!(map.metric("thermal/acpitz"), None);
+
+        assert!("coretemp/Core 0".parse::<SensorMap>().is_err());
+        assert!(" = cpu.temperature".parse::<SensorMap>().is_err());
+        assert!("coretemp/Core * = cpu.core.$2"
+            .parse::<SensorMap>()
+            .is_err());
+        assert!("coretemp/Core * = cpu.core.$".parse::<SensorMap>().is_err());
+    }
+
+    #[test]
+    fn discovers_and_maps_sensors() {
+        let root = std::env::temp_dir().join(format!("chips-sensors-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        let write = |path: &str, contents: &str| {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        };
+        write("hwmon/hwmon0/name", "nvme\n");
+        write("hwmon/hwmon0/temp1_input", "38850\n");
+        write("hwmon/hwmon0/temp1_label", "Composite\n");
+        write("hwmon/hwmon1/name", "coretemp\n");
+        write("hwmon/hwmon1/temp1_input", "62000\n");
+        write("hwmon/hwmon1/temp1_label", "Package id 0\n");
+        write("hwmon/hwmon1/temp10_input", "58000\n");
+        write("hwmon/hwmon1/temp10_label", "Core 8\n");
+        write("hwmon/hwmon1/temp2_input", "61000\n");
+        write("hwmon/hwmon1/temp2_label", "Core 0\n");
+        write("hwmon/hwmon10/name", "nvme\n");
+        write("hwmon/hwmon10/temp1_input", "41850\n");
+        write("hwmon/hwmon10/temp1_label", "Composite\n");
+        write("hwmon/hwmon2/name", "nct6798\n");
+        write("hwmon/hwmon2/fan1_input", "1200\n");
+        write("hwmon/hwmon2/pwm1", "128\n");
+        write("thermal/thermal_zone0/type", "x86_pkg_temp\n");
+        write("thermal/thermal_zone0/temp", "63000\n");
+        write("thermal/thermal_zone1/type", "acpitz\n");
+        write("thermal/thermal_zone1/temp", "27800\n");
+
+        let sensors = Sensors::with_roots(
+            root.join("hwmon"),
+            root.join("thermal"),
+            &SensorMap::default(),
+        );
+        let mapped: Vec<(&str, Option<&str>)> = sensors
+            .list()
+            .map(|sensor| (sensor.id.as_str(), sensor.metric.as_deref()))
+            .collect();
+        assert_eq!(
+            mapped,
+            vec![
+                ("nvme0/Composite", Some("disk.nvme0.temperature")),
+                ("coretemp/Package id 0", Some("cpu.temperature")),
+                ("coretemp/Core 0", Some("cpu.core.0.temperature")),
+                ("coretemp/Core 8", Some("cpu.core.8.temperature")),
+                ("nct6798/fan1", Some("fan.1")),
+                ("nvme1/Composite", Some("disk.nvme1.temperature")),
+                // The package temperature is already taken by coretemp
+                ("thermal/x86_pkg_temp", None),
+                ("thermal/acpitz", None),
+            ]
+        );
+
+        let mut snapshot = MetricSnapshot::new();
+        sensors.write_metrics(&mut snapshot);
+        let number = |name: &str| snapshot.get(name).and_then(|value| value.as_number());
+        assert_eq!(number("cpu.temperature"), Some(62.0));
+        assert_eq!(number("disk.nvme1.temperature"), Some(41.85));
+        assert_eq!(number("fan.1"), Some(1200.0));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,541 @@
+use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossbeam::channel::{unbounded, Receiver};
+use serde_json::Value;
+
+use crate::errors::{error_message, ChipsError, Result};
+use crate::http::{self, HttpUrl};
+use crate::metrics::{MetricInfo, MetricProvider, MetricSnapshot, MetricValue, Unit};
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(5);
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How often finished reads are picked up. Sources are read on their own threads, so this
+/// only bounds how stale a new value can be by the time it's shown.
+const RESULT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often a running command is checked on while waiting for it to exit.
+const COMMAND_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SourceKind {
+    /// Runs through the shell, `sh -c` or `cmd /C`, and reads the standard output.
+    Command(String),
+    File(PathBuf),
+    Http(HttpUrl),
+}
+
+/// A metric read from outside the program, like the output of a script or a value from a
+/// local web service.
+///
+/// Sources are configured in sections named after the metric they set:
+///
+/// ```text
+/// [room.temperature]
+/// http = http://127.0.0.1:8123/api/room
+/// json = sensors[0].temperature
+/// interval = 30s
+/// timeout = 2s
+/// range = 0..40
+///
+/// [backup.age]
+/// command = ./backup-age.sh
+///
+/// [ups.load]
+/// file = /run/ups/status
+/// line = 3
+/// ```
+///
+/// Each section needs one of `command`, `file` or `http`. `line` picks a line of the output,
+/// counting from 1, and `json` picks a value out of the output with a path like
+/// `sensors[0].temperature`. Output that reads as a number becomes a number, and anything else
+/// is shown as text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternalSource {
+    pub metric: String,
+    pub kind: SourceKind,
+    pub line: Option<usize>,
+    pub json_path: Option<String>,
+    pub interval: Duration,
+    /// Commands are killed and requests are abandoned after this long. Files are read without
+    /// a timeout.
+    pub timeout: Duration,
+    pub range: Option<(f64, f64)>,
+}
+
+impl ExternalSource {
+    /// Parses every section of a source config.
+    pub fn parse_all(config: &str) -> Result<Vec<Self>> {
+        parse_sections(config)?
+            .into_iter()
+            .map(|(metric, options)| Self::from_options(metric, options))
+            .collect()
+    }
+
+    fn from_options(metric: String, options: Options) -> Result<Self> {
+        let invalid =
+            |message: &str| ChipsError::InvalidSourceConfig(format!("[{}] {}", metric, message));
+
+        let mut kind = None;
+        let mut source = Self {
+            metric: metric.clone(),
+            kind: SourceKind::Command(String::new()),
+            line: None,
+            json_path: None,
+            interval: DEFAULT_INTERVAL,
+            timeout: DEFAULT_TIMEOUT,
+            range: None,
+        };
+        for (key, value) in options {
+            let bad_value = || invalid(&format!("has invalid {} {:?}", key, value));
+            match key.as_str() {
+                "command" | "file" | "http" if kind.is_some() => {
+                    return Err(invalid("has more than one of command, file and http"))
+                }
+                "command" => kind = Some(SourceKind::Command(value)),
+                "file" => kind = Some(SourceKind::File(value.into())),
+                "http" => kind = Some(SourceKind::Http(value.parse()?)),
+                "line" => {
+                    source.line = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|line| *line >= 1)
+                            .ok_or_else(bad_value)?,
+                    )
+                }
+                "json" => source.json_path = Some(value),
+                "interval" => source.interval = parse_duration(&value).ok_or_else(bad_value)?,
+                "timeout" => source.timeout = parse_duration(&value).ok_or_else(bad_value)?,
+                "range" => {
+                    let (min, max) = value.split_once("..").ok_or_else(bad_value)?;
+                    let min: f64 = min.trim().parse().map_err(|_| bad_value())?;
+                    let max: f64 = max.trim().parse().map_err(|_| bad_value())?;
+                    if min >= max {
+                        return Err(bad_value());
+                    }
+                    source.range = Some((min, max));
+                }
+                _ => return Err(invalid(&format!("has unknown option {:?}", key))),
+            }
+        }
+
+        source.kind = kind.ok_or_else(|| invalid("needs one of command, file or http"))?;
+        Ok(source)
+    }
+
+    /// Reads the source once, blocking for up to the timeout.
+    pub fn read(&self) -> Result<MetricValue> {
+        let output = match &self.kind {
+            SourceKind::Command(command) => run_command(command, self.timeout)?,
+            SourceKind::File(path) => fs::read_to_string(path)?,
+            SourceKind::Http(url) => http::get(url, self.timeout)?,
+        };
+        let output = match self.line {
+            Some(line) => output
+                .lines()
+                .nth(line - 1)
+                .ok_or_else(|| ChipsError::MissingValue(format!("line {}", line)))?,
+            None => output.as_str(),
+        };
+
+        match &self.json_path {
+            Some(path) => {
+                let json: Value = serde_json::from_str(output)?;
+                json_value(&json, path)
+            }
+            None => Ok(parse_value(output)),
+        }
+    }
+}
+
+/// The `key = value` lines of a config section, in order.
+pub(crate) type Options = Vec<(String, String)>;
+
+/// Splits a config into `[name]` sections of `key = value` options, skipping blank lines and
+/// `#` comments.
+pub(crate) fn parse_sections(config: &str) -> Result<Vec<(String, Options)>> {
+    let mut sections: Vec<(String, Options)> = vec![];
+    for line in config.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let invalid = || ChipsError::InvalidSourceConfig(format!("{:?}", line));
+        if let Some(name) = line.strip_prefix('[') {
+            let name = name.strip_suffix(']').ok_or_else(invalid)?.trim();
+            if name.is_empty() {
+                return Err(invalid());
+            }
+            sections.push((name.to_string(), vec![]));
+            continue;
+        }
+
+        let (key, value) = line.split_once('=').ok_or_else(invalid)?;
+        let (_, options) = sections.last_mut().ok_or_else(invalid)?;
+        options.push((key.trim().to_string(), value.trim().to_string()));
+    }
+    Ok(sections)
+}
+
+/// Parses durations like `500ms`, `30s` or `5m`.
+pub(crate) fn parse_duration(s: &str) -> Option<Duration> {
+    let split = s.find(|c: char| !c.is_ascii_digit())?;
+    let (amount, unit) = s.split_at(split);
+    let amount: u64 = amount.parse().ok()?;
+    let duration = match unit.trim() {
+        "ms" => Duration::from_millis(amount),
+        "s" => Duration::from_secs(amount),
+        "m" => Duration::from_secs(amount.checked_mul(60)?),
+        _ => return None,
+    };
+    Some(duration).filter(|duration| !duration.is_zero())
+}
+
+/// Reads text as a number if it is one, and as text otherwise.
+pub(crate) fn parse_value(text: &str) -> MetricValue {
+    let text = text.trim();
+    match text.parse::<f64>() {
+        Ok(value) => MetricValue::Number(value),
+        Err(_) => MetricValue::Text(text.to_string()),
+    }
+}
+
+/// Looks up a value with a path like `sensors[0].temperature`. Strings are read with
+/// [`parse_value`], since plenty of services send numbers as strings.
+pub(crate) fn json_value(json: &Value, path: &str) -> Result<MetricValue> {
+    let missing = || ChipsError::MissingValue(path.to_string());
+    let mut value = json;
+    for segment in path.split('.') {
+        let (key, indices) = segment.split_at(segment.find('[').unwrap_or(segment.len()));
+        if !key.is_empty() {
+            value = value.get(key).ok_or_else(missing)?;
+        }
+        for index in indices.split_terminator(']') {
+            let index: usize = index
+                .strip_prefix('[')
+                .and_then(|index| index.parse().ok())
+                .ok_or_else(missing)?;
+            value = value.get(index).ok_or_else(missing)?;
+        }
+    }
+
+    match value {
+        Value::Null => Err(missing()),
+        Value::Number(number) => number.as_f64().map(MetricValue::Number).ok_or_else(missing),
+        Value::String(text) => Ok(parse_value(text)),
+        value => Ok(MetricValue::Text(value.to_string())),
+    }
+}
+
+/// Runs a command through the shell and returns its standard output, killing it if it runs
+/// longer than `timeout`.
+fn run_command(command: &str, timeout: Duration) -> Result<String> {
+    #[cfg(windows)]
+    let mut shell = {
+        let mut shell = Command::new("cmd");
+        shell.args(["/C", command]);
+        shell
+    };
+    #[cfg(not(windows))]
+    let mut shell = {
+        let mut shell = Command::new("sh");
+        shell.args(["-c", command]);
+        shell
+    };
+    let mut child = shell
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    // Read the output as it comes, so that the command can't fill the pipe and stall
+    let stdout = child.stdout.take();
+    let output = thread::spawn(move || {
+        let mut output = String::new();
+        if let Some(mut stdout) = stdout {
+            stdout.read_to_string(&mut output)?;
+        }
+        Ok::<_, std::io::Error>(output)
+    });
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            child.kill()?;
+            child.wait()?;
+            return Err(ChipsError::TimedOut);
+        }
+        thread::sleep(COMMAND_POLL_INTERVAL);
+    };
+    if !status.success() {
+        return Err(ChipsError::CommandFailed(format!(
+            "{:?} exited with {}",
+            command, status
+        )));
+    }
+
+    output
+        .join()
+        .map_err(|_| ChipsError::CommandFailed(format!("{:?} output was lost", command)))?
+        .map_err(ChipsError::from)
+}
+
+/// Provides the metric of an [`ExternalSource`], along with `<metric>.error` while the latest
+/// read failed. The source is read on its own thread so that slow commands and requests don't
+/// hold up drawing.
+pub struct ExternalMetrics {
+    metric: String,
+    range: Option<(f64, f64)>,
+    results: Receiver<Result<MetricValue, String>>,
+    /// The last error printed, so that a source failing the same way isn't printed every time.
+    last_error: Option<String>,
+}
+
+impl ExternalMetrics {
+    /// Starts reading the source on its interval. The thread stops once this is dropped.
+    pub fn spawn(source: ExternalSource) -> Self {
+        Self {
+            metric: source.metric.clone(),
+            range: source.range,
+            results: spawn_polling(source.interval, move || source.read()),
+            last_error: None,
+        }
+    }
+}
+
+/// Calls `read` on its own thread every `interval`, for as long as the results are received.
+/// Errors are sent as messages, since not every error can cross threads.
+pub(crate) fn spawn_polling<T: Send + 'static>(
+    interval: Duration,
+    mut read: impl FnMut() -> Result<T> + Send + 'static,
+) -> Receiver<Result<T, String>> {
+    let (sender, results) = unbounded();
+    thread::spawn(move || loop {
+        let started = Instant::now();
+        if sender
+            .send(read().map_err(|err| error_message(&err)))
+            .is_err()
+        {
+            break;
+        }
+        thread::sleep(interval.saturating_sub(started.elapsed()));
+    });
+    results
+}
+
+impl MetricProvider for ExternalMetrics {
+    fn metrics(&self) -> Vec<MetricInfo> {
+        let mut info = MetricInfo::new(&self.metric, Unit::None);
+        if let Some((min, max)) = self.range {
+            info = info.range(min, max);
+        }
+        vec![
+            info,
+            MetricInfo::new(format!("{}.error", self.metric), Unit::None),
+        ]
+    }
+
+    fn interval(&self) -> Duration {
+        RESULT_CHECK_INTERVAL
+    }
+
+    fn sample(&mut self) -> Result<Option<MetricSnapshot>> {
+        let Some(result) = self.results.try_iter().last() else {
+            return Ok(None);
+        };
+
+        let mut snapshot = MetricSnapshot::new();
+        match result {
+            Ok(value) => {
+                self.last_error = None;
+                snapshot.set(&self.metric, value);
+            }
+            Err(err) => {
+                if self.last_error.as_ref() != Some(&err) {
+                    println!("{}: {}", self.metric, err);
+                }
+                snapshot.set(format!("{}.error", self.metric), err.as_str());
+                self.last_error = Some(err);
+            }
+        }
+        Ok(Some(snapshot))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::tests::{ok, serve_once};
+
+    fn source(kind: SourceKind) -> ExternalSource {
+        ExternalSource {
+            metric: "test".to_string(),
+            kind,
+            line: None,
+            json_path: None,
+            interval: DEFAULT_INTERVAL,
+            timeout: DEFAULT_TIMEOUT,
+            range: None,
+        }
+    }
+
+    #[test]
+    fn parses_config() {
+        let sources = ExternalSource::parse_all(
+            "
+            # Comments and blank lines are skipped
+
+            [room.temperature]
+            http = http://127.0.0.1:8123/api/room
+            json = sensors[0].temperature
+            interval = 30s
+            timeout = 500ms
+            range = 0..40
+
+            [backup.age]
+            command = echo 3
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            sources,
+            vec![
+                ExternalSource {
+                    metric: "room.temperature".to_string(),
+                    kind: SourceKind::Http("http://127.0.0.1:8123/api/room".parse().unwrap()),
+                    json_path: Some("sensors[0].temperature".to_string()),
+                    interval: Duration::from_secs(30),
+                    timeout: Duration::from_millis(500),
+                    range: Some((0.0, 40.0)),
+                    ..source(SourceKind::Command(String::new()))
+                },
+                ExternalSource {
+                    metric: "backup.age".to_string(),
+                    ..source(SourceKind::Command("echo 3".to_string()))
+                },
+            ]
+        );
+
+        for invalid in [
+            "http = http://localhost/",
+            "[empty]",
+            "[both]\ncommand = true\nfile = /tmp/x",
+            "[x]\nfile = /tmp/x\nline = 0",
+            "[x]\nfile = /tmp/x\ninterval = 5 hours",
+            "[x]\nfile = /tmp/x\nrange = 10..0",
+            "[x]\nfile = /tmp/x\ncolour = red",
+        ] {
+            assert!(ExternalSource::parse_all(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_duration("30s"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_duration("5m"), Some(Duration::from_secs(300)));
+        assert_eq!(parse_duration("0s"), None);
+        assert_eq!(parse_duration("5h"), None);
+        assert_eq!(parse_duration(&format!("{}m", u64::MAX)), None);
+    }
+
+    #[test]
+    fn looks_up_json_paths() {
+        let json: Value = serde_json::from_str(
+            r#"{"sensors": [{"temperature": 21.5, "state": "22.0", "name": "den"}], "on": null}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            json_value(&json, "sensors[0].temperature").unwrap(),
+            MetricValue::Number(21.5)
+        );
+        assert_eq!(
+            json_value(&json, "sensors[0].state").unwrap(),
+            MetricValue::Number(22.0)
+        );
+        assert_eq!(
+            json_value(&json, "sensors[0].name").unwrap(),
+            MetricValue::Text("den".to_string())
+        );
+        assert!(json_value(&json, "sensors[1].temperature").is_err());
+        assert!(json_value(&json, "on").is_err());
+    }
+
+    #[test]
+    fn reads_file_lines() {
+        let path = std::env::temp_dir().join(format!("chips-source-{}", std::process::id()));
+        fs::write(&path, "status: online\n42.5\n").unwrap();
+
+        let mut file = source(SourceKind::File(path.clone()));
+        assert_eq!(
+            file.read().unwrap(),
+            MetricValue::Text("status: online\n42.5".to_string())
+        );
+        file.line = Some(2);
+        assert_eq!(file.read().unwrap(), MetricValue::Number(42.5));
+        file.line = Some(3);
+        assert!(file.read().is_err());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reads_json_over_http() {
+        let url = serve_once(ok(r#"{"current": {"temperature": 18.25}}"#), "/weather");
+        let mut http = source(SourceKind::Http(url));
+        http.json_path = Some("current.temperature".to_string());
+        assert_eq!(http.read().unwrap(), MetricValue::Number(18.25));
+    }
+
+    #[test]
+    fn runs_commands() {
+        assert_eq!(
+            source(SourceKind::Command("echo 42".to_string()))
+                .read()
+                .unwrap(),
+            MetricValue::Number(42.0)
+        );
+        assert!(matches!(
+            source(SourceKind::Command("exit 3".to_string())).read(),
+            Err(ChipsError::CommandFailed(_))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn kills_slow_commands() {
+        let mut slow = source(SourceKind::Command("sleep 10".to_string()));
+        slow.timeout = Duration::from_millis(100);
+        let started = Instant::now();
+        assert!(matches!(slow.read(), Err(ChipsError::TimedOut)));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn shows_errors() {
+        let mut metrics =
+            ExternalMetrics::spawn(source(SourceKind::File("/nonexistent/chips-source".into())));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let snapshot = loop {
+            if let Some(snapshot) = metrics.sample().unwrap() {
+                break snapshot;
+            }
+            assert!(Instant::now() < deadline);
+            thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!(snapshot.get("test"), None);
+        assert!(matches!(
+            snapshot.get("test.error"),
+            Some(MetricValue::Text(_))
+        ));
+    }
+}
@@ -1,37 +1,68 @@
-use nvml_wrapper::Nvml;
-use once_cell::sync::Lazy;
+use std::time::{Duration, Instant};
+
+#[cfg(windows)]
+use windows::Wdk::System::SystemInformation::{
+    NtQuerySystemInformation, SystemProcessorPerformanceInformation,
+};
+#[cfg(windows)]
 use windows::Win32::{
     Foundation::FILETIME,
     System::{
-        SystemInformation::{GlobalMemoryStatusEx, MEMORYSTATUSEX},
+        Power::{CallNtPowerInformation, ProcessorInformation, PROCESSOR_POWER_INFORMATION},
+        ProcessStatus::{GetPerformanceInfo, PERFORMANCE_INFORMATION},
+        SystemInformation::{GetTickCount64, GlobalMemoryStatusEx, MEMORYSTATUSEX},
         Threading::GetSystemTimes,
+        WindowsProgramming::SYSTEM_PROCESSOR_PERFORMANCE_INFORMATION,
     },
 };
 
+use crate::cpu_sampler::{CpuTimes, TimeSource};
+#[cfg(target_os = "linux")]
+use crate::errors::ChipsError;
 use crate::errors::Result;
 
-static NVML: Lazy<Option<Nvml>> = Lazy::new(|| match Nvml::init() {
-    Err(err) => {
-        println!("{}", err);
-        None
-    }
-    Ok(nvml) => Some(nvml),
-});
+// A processor group never has more logical processors than this
+#[cfg(windows)]
+const MAX_GROUP_PROCESSORS: usize = 64;
 
-pub struct SystemInfo {
-    last_total_time: u64,
-    last_total_exec_time: u64,
+/// Physical memory, swap and commit charge, in bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    pub total: u64,
+    /// Memory in use, not counting what could be reclaimed from caches.
+    pub used: u64,
+    pub available: u64,
+    /// File cache, which is counted as available.
+    pub cached: u64,
+    pub swap_total: u64,
+    pub swap_used: u64,
+    /// Memory promised to processes, which can be more than what's in use.
+    pub commit_total: u64,
+    /// How much can be committed before allocations fail.
+    pub commit_limit: u64,
 }
 
-impl SystemInfo {
-    pub fn new() -> Result<Self> {
-        Ok(Self {
-            last_total_time: 0,
-            last_total_exec_time: 0,
-        })
+impl MemoryStats {
+    /// The ratio of used to total physical memory.
+    pub fn usage(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+
+        self.used as f64 / self.total as f64
+    }
+}
+
+/// Reads CPU times from the system, in 100ns units on Windows and clock ticks on Linux.
+pub struct SystemTimes;
+
+#[cfg(windows)]
+impl TimeSource for SystemTimes {
+    fn now(&self) -> Instant {
+        Instant::now()
     }
 
-    pub fn get_cpu_usage(&mut self) -> Result<f64> {
+    fn cpu_times(&mut self) -> Result<CpuTimes> {
         let mut idle_time = unsafe { std::mem::zeroed::<FILETIME>() };
         let mut kernel_time = unsafe { std::mem::zeroed::<FILETIME>() };
         let mut user_time = unsafe { std::mem::zeroed::<FILETIME>() };
@@ -50,44 +81,319 @@ impl SystemInfo {
 
         // Kernel time includes idle time
         let total_time = kernel_exec_time + user_exec_time;
-        let total_exec_time = total_time - idle_exec_time;
+        Ok(CpuTimes {
+            total: total_time,
+            busy: total_time - idle_exec_time,
+        })
+    }
 
-        let total_time_diff = total_time - self.last_total_time;
-        let total_exec_time_diff = total_exec_time - self.last_total_exec_time;
+    /// Only covers the logical processors in the current processor group.
+    fn core_times(&mut self) -> Result<Vec<CpuTimes>> {
+        let mut core_times =
+            vec![SYSTEM_PROCESSOR_PERFORMANCE_INFORMATION::default(); MAX_GROUP_PROCESSORS];
+        let mut returned_len = 0u32;
+        unsafe {
+            NtQuerySystemInformation(
+                SystemProcessorPerformanceInformation,
+                core_times.as_mut_ptr() as *mut _,
+                std::mem::size_of_val(core_times.as_slice()) as u32,
+                &mut returned_len,
+            )
+        }
+        .ok()?;
+        core_times.truncate(
+            returned_len as usize / std::mem::size_of::<SYSTEM_PROCESSOR_PERFORMANCE_INFORMATION>(),
+        );
 
-        self.last_total_time = total_time;
-        self.last_total_exec_time = total_exec_time;
+        Ok(core_times
+            .iter()
+            .map(|times| {
+                // Kernel time includes idle time, same as with GetSystemTimes
+                let total_time = (times.KernelTime + times.UserTime) as u64;
+                CpuTimes {
+                    total: total_time,
+                    busy: total_time - times.IdleTime as u64,
+                }
+            })
+            .collect())
+    }
+}
 
-        // TODO: This is an undercount compared to Task Manager, why?
-        let exec_time_ratio = (total_exec_time_diff as f64) / (total_time_diff as f64);
+#[cfg(target_os = "linux")]
+impl TimeSource for SystemTimes {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
 
-        Ok(exec_time_ratio)
+    fn cpu_times(&mut self) -> Result<CpuTimes> {
+        let (total, _) = parse_proc_stat(&std::fs::read_to_string("/proc/stat")?)?;
+        Ok(total)
     }
 
-    pub fn get_memory_usage(&self) -> Result<f64> {
-        let mut mem_info = unsafe { std::mem::zeroed::<MEMORYSTATUSEX>() };
-        mem_info.dwLength = std::mem::size_of::<MEMORYSTATUSEX>() as u32;
-        unsafe { GlobalMemoryStatusEx(&mut mem_info) }?;
+    fn core_times(&mut self) -> Result<Vec<CpuTimes>> {
+        let (_, cores) = parse_proc_stat(&std::fs::read_to_string("/proc/stat")?)?;
+        Ok(cores)
+    }
+}
 
-        let physical_mem_used = mem_info.ullTotalPhys - mem_info.ullAvailPhys;
-        let mem_ratio = (physical_mem_used as f64) / (mem_info.ullTotalPhys as f64);
+/// Parses the CPU times of `/proc/stat`, returning the total and the times of each logical
+/// processor.
+#[cfg(target_os = "linux")]
+fn parse_proc_stat(stat: &str) -> Result<(CpuTimes, Vec<CpuTimes>)> {
+    let mut total = None;
+    let mut cores = vec![];
+    for line in stat.lines() {
+        let mut fields = line.split_whitespace();
+        let Some(name) = fields.next().filter(|name| name.starts_with("cpu")) else {
+            continue;
+        };
 
-        Ok(mem_ratio)
+        // user, nice, system, idle, iowait, irq, softirq and steal. Guest time comes after, but
+        // it's already counted as user time.
+        let ticks: Vec<u64> = fields
+            .take(8)
+            .map_while(|field| field.parse().ok())
+            .collect();
+        if ticks.len() < 4 {
+            return Err(ChipsError::InvalidSystemInfo(format!(
+                "stat has invalid line {:?}",
+                line
+            )));
+        }
+        let total_time: u64 = ticks.iter().sum();
+        let idle_time = ticks[3] + ticks.get(4).copied().unwrap_or(0);
+        let times = CpuTimes {
+            total: total_time,
+            busy: total_time - idle_time,
+        };
+        if name == "cpu" {
+            total = Some(times);
+        } else {
+            cores.push(times);
+        }
     }
 
-    pub fn get_gpu_usage(&self) -> Result<f64> {
-        if let Some(nvml) = NVML.as_ref() {
-            // TODO: Support other GPUs somehow
-            let gpu = nvml.device_by_index(0)?;
-            let usage = gpu.utilization_rates()?;
-            let usage_ratio = usage.gpu as f64 / 100.0;
-            return Ok(usage_ratio);
-        }
+    let total =
+        total.ok_or_else(|| ChipsError::InvalidSystemInfo("stat has no cpu line".to_string()))?;
+    Ok((total, cores))
+}
+
+#[cfg(windows)]
+pub fn get_uptime() -> Duration {
+    Duration::from_millis(unsafe { GetTickCount64() })
+}
+
+/// Reads `/proc/uptime`, which starts with the seconds since boot.
+#[cfg(target_os = "linux")]
+pub fn get_uptime() -> Duration {
+    std::fs::read_to_string("/proc/uptime")
+        .ok()
+        .and_then(|uptime| uptime.split_whitespace().next()?.parse().ok())
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .unwrap_or_default()
+}
 
-        Ok(0.0)
+#[cfg(windows)]
+pub fn read_memory_stats() -> Result<MemoryStats> {
+    let mut mem_info = unsafe { std::mem::zeroed::<MEMORYSTATUSEX>() };
+    mem_info.dwLength = std::mem::size_of::<MEMORYSTATUSEX>() as u32;
+    unsafe { GlobalMemoryStatusEx(&mut mem_info) }?;
+
+    let mut perf_info = unsafe { std::mem::zeroed::<PERFORMANCE_INFORMATION>() };
+    perf_info.cb = std::mem::size_of::<PERFORMANCE_INFORMATION>() as u32;
+    unsafe { GetPerformanceInfo(&mut perf_info, perf_info.cb) }?;
+
+    let page_size = perf_info.PageSize as u64;
+    let total = mem_info.ullTotalPhys;
+    let used = total - mem_info.ullAvailPhys;
+    let commit_total = perf_info.CommitTotal as u64 * page_size;
+    let commit_limit = perf_info.CommitLimit as u64 * page_size;
+
+    // The commit limit is physical memory plus the page files. Windows doesn't report page file
+    // usage here, so it's estimated as the commit charge that doesn't fit in physical memory.
+    let swap_total = commit_limit.saturating_sub(total);
+    let swap_used = commit_total.saturating_sub(used).min(swap_total);
+
+    Ok(MemoryStats {
+        total,
+        used,
+        available: mem_info.ullAvailPhys,
+        cached: perf_info.SystemCache as u64 * page_size,
+        swap_total,
+        swap_used,
+        commit_total,
+        commit_limit,
+    })
+}
+
+#[cfg(target_os = "linux")]
+pub fn read_memory_stats() -> Result<MemoryStats> {
+    parse_meminfo(&std::fs::read_to_string("/proc/meminfo")?)
+}
+
+/// Parses `/proc/meminfo`, where values are in KiB.
+#[cfg(target_os = "linux")]
+fn parse_meminfo(meminfo: &str) -> Result<MemoryStats> {
+    let fields: std::collections::HashMap<&str, u64> = meminfo
+        .lines()
+        .filter_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            let kib = value
+                .trim()
+                .trim_end_matches("kB")
+                .trim()
+                .parse::<u64>()
+                .ok()?;
+            Some((name, kib * 1024))
+        })
+        .collect();
+    let field = |name: &str| fields.get(name).copied().unwrap_or(0);
+
+    let total = *fields
+        .get("MemTotal")
+        .ok_or_else(|| ChipsError::InvalidSystemInfo("meminfo has no MemTotal".to_string()))?;
+
+    // MemAvailable only exists since Linux 3.14, before which free memory and caches are the
+    // closest equivalent
+    let cached = field("Cached") + field("Buffers") + field("SReclaimable");
+    let available = fields
+        .get("MemAvailable")
+        .copied()
+        .unwrap_or_else(|| field("MemFree") + cached)
+        .min(total);
+
+    let swap_total = field("SwapTotal");
+    Ok(MemoryStats {
+        total,
+        used: total - available,
+        available,
+        cached,
+        swap_total,
+        swap_used: swap_total.saturating_sub(field("SwapFree")),
+        commit_total: field("Committed_AS"),
+        commit_limit: field("CommitLimit"),
+    })
+}
+
+/// Gets the current clock speeds of the first `core_count` logical processors in MHz, as reported
+/// by the power manager.
+#[cfg(windows)]
+pub fn get_core_frequencies(core_count: usize) -> Option<Vec<f64>> {
+    let mut info = vec![PROCESSOR_POWER_INFORMATION::default(); core_count];
+    unsafe {
+        CallNtPowerInformation(
+            ProcessorInformation,
+            None,
+            0,
+            Some(info.as_mut_ptr() as *mut _),
+            std::mem::size_of_val(info.as_slice()) as u32,
+        )
     }
+    .ok()
+    .ok()?;
+
+    Some(info.iter().map(|info| info.CurrentMhz as f64).collect())
+}
+
+/// Gets the current clock speeds of the first `core_count` logical processors in MHz, from
+/// cpufreq. Processors without a driver have no frequency.
+#[cfg(target_os = "linux")]
+pub fn get_core_frequencies(core_count: usize) -> Option<Vec<f64>> {
+    (0..core_count)
+        .map(|idx| {
+            let path = format!(
+                "/sys/devices/system/cpu/cpu{}/cpufreq/scaling_cur_freq",
+                idx
+            );
+            let khz: f64 = std::fs::read_to_string(path).ok()?.trim().parse().ok()?;
+            Some(khz / 1000.0)
+        })
+        .collect()
 }
 
+#[cfg(windows)]
 fn filetime_as_u64(filetime: FILETIME) -> u64 {
     ((filetime.dwHighDateTime as u64) << 32) | (filetime.dwLowDateTime as u64)
 }
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    const KIB: u64 = 1024;
+
+    #[test]
+    fn parses_meminfo() {
+        let stats = parse_meminfo(
+            "MemTotal:       16000000 kB
+MemFree:         2000000 kB
+MemAvailable:   10000000 kB
+Buffers:          500000 kB
+Cached:          6000000 kB
+SwapTotal:       4000000 kB
+SwapFree:        3000000 kB
+CommitLimit:    12000000 kB
+Committed_AS:    9000000 kB
+SReclaimable:     300000 kB
+HugePages_Total:       0
+",
+        )
+        .unwrap();
+        assert_eq!(
+            stats,
+            MemoryStats {
+                total: 16000000 * KIB,
+                used: 6000000 * KIB,
+                available: 10000000 * KIB,
+                cached: 6800000 * KIB,
+                swap_total: 4000000 * KIB,
+                swap_used: 1000000 * KIB,
+                commit_total: 9000000 * KIB,
+                commit_limit: 12000000 * KIB,
+            }
+        );
+
+        // Older kernels without MemAvailable count free memory and caches instead
+        let stats = parse_meminfo("MemTotal: 1000 kB\nMemFree: 200 kB\nCached: 300 kB\n").unwrap();
+        assert_eq!(stats.available, 500 * KIB);
+        assert_eq!(stats.used, 500 * KIB);
+
+        assert!(parse_meminfo("MemFree: 200 kB\n").is_err());
+    }
+
+    #[test]
+    fn parses_proc_stat() {
+        let (total, cores) = parse_proc_stat(
+            "cpu  100 10 50 800 40 0 0 0 20 0
+cpu0 60 5 30 380 25 0 0 0 20 0
+cpu1 40 5 20 420 15 0 0 0 0 0
+intr 12345 0 0
+ctxt 6789
+",
+        )
+        .unwrap();
+        assert_eq!(
+            total,
+            CpuTimes {
+                total: 1000,
+                busy: 160,
+            }
+        );
+        assert_eq!(
+            cores,
+            vec![
+                CpuTimes {
+                    total: 500,
+                    busy: 95,
+                },
+                CpuTimes {
+                    total: 500,
+                    busy: 65,
+                },
+            ]
+        );
+
+        assert!(parse_proc_stat("intr 12345\n").is_err());
+        assert!(parse_proc_stat("cpu 1 2\n").is_err());
+    }
+}
@@ -0,0 +1,410 @@
+use std::str::FromStr;
+
+use crate::color::Color;
+use crate::errors::{ChipsError, Result};
+use crate::metrics::{MetricSnapshot, MetricValue};
+
+/// Shown in place of metrics that are missing from the snapshot.
+const MISSING_VALUE: &str = "--";
+
+const BYTE_UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+const BIT_UNITS: [&str; 5] = ["b", "kb", "Mb", "Gb", "Tb"];
+
+/// How a number is converted before it's printed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Conversion {
+    None,
+    /// Multiplies a ratio by 100.
+    Percent,
+    /// Picks the largest binary unit that keeps the value at or above 1, and appends it.
+    Bytes,
+    /// Converts bytes to bits, then picks the largest decimal unit that keeps the value at or
+    /// above 1 and appends it, as network speeds are usually given.
+    Bits,
+    /// Divides by a fixed binary unit without appending it, for text like "12.3 / 32 GiB".
+    ByteUnit(i32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ColorCondition {
+    comparison: Comparison,
+    threshold: f64,
+    color: Color,
+}
+
+impl ColorCondition {
+    fn matches(&self, value: f64) -> bool {
+        match self.comparison {
+            Comparison::Less => value < self.threshold,
+            Comparison::LessOrEqual => value <= self.threshold,
+            Comparison::Greater => value > self.threshold,
+            Comparison::GreaterOrEqual => value >= self.threshold,
+            Comparison::Equal => value == self.threshold,
+            Comparison::NotEqual => value != self.threshold,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Placeholder {
+    metric: String,
+    conversion: Conversion,
+    precision: Option<usize>,
+    conditions: Vec<ColorCondition>,
+}
+
+impl Placeholder {
+    fn format(&self, value: Option<&MetricValue>) -> String {
+        let value = match value {
+            Some(MetricValue::Number(value)) => *value,
+            Some(MetricValue::Text(text)) => return text.clone(),
+            None => return MISSING_VALUE.to_string(),
+        };
+
+        let (value, suffix) = match self.conversion {
+            Conversion::None => (value, None),
+            Conversion::Percent => (value * 100.0, None),
+            Conversion::Bytes => scale_to_unit(value, 1024.0, &BYTE_UNITS),
+            Conversion::Bits => scale_to_unit(value * 8.0, 1000.0, &BIT_UNITS),
+            Conversion::ByteUnit(exponent) => (value / 1024f64.powi(exponent), None),
+        };
+
+        let precision = self.precision.unwrap_or(match self.conversion {
+            Conversion::Bytes | Conversion::Bits => 1,
+            _ => 0,
+        });
+        match suffix {
+            Some(suffix) => format!("{:.*} {}", precision, value, suffix),
+            None => format!("{:.*}", precision, value),
+        }
+    }
+
+    fn color(&self, value: Option<&MetricValue>) -> Option<Color> {
+        let value = value.and_then(MetricValue::as_number)?;
+        self.conditions
+            .iter()
+            .find(|condition| condition.matches(value))
+            .map(|condition| condition.color)
+    }
+}
+
+/// Picks the largest unit that keeps the value at or above 1, where each unit is `base` times
+/// the previous one.
+fn scale_to_unit(value: f64, base: f64, units: &[&'static str]) -> (f64, Option<&'static str>) {
+    let mut exponent = 0;
+    while exponent < units.len() - 1 && value.abs() >= base.powi(exponent as i32 + 1) {
+        exponent += 1;
+    }
+    (value / base.powi(exponent as i32), Some(units[exponent]))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    Placeholder(Placeholder),
+}
+
+/// The result of evaluating a [`Template`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderedText {
+    pub text: String,
+    /// The color picked by the first placeholder with a matching condition, if any.
+    pub color: Option<Color>,
+}
+
+/// Label text with metric placeholders, evaluated against a [`MetricSnapshot`] on every tick.
+///
+/// Placeholders look like `{name:spec|condition|...}`, where everything after the name is
+/// optional:
+///
+/// * `spec` is an optional conversion followed by an optional precision, e.g. `.1`, `%.0`,
+///   `bytes` or `GiB.2`. `%` turns a ratio into a percentage, `bytes` picks a binary unit and
+///   appends it ("12.3 GiB"), `bits` converts bytes to bits and picks a decimal unit
+///   ("12.3 Mb"), and `KiB`/`MiB`/`GiB`/`TiB` divide by that unit without appending it. Without
+///   a precision, numbers are rounded to integers, or one decimal for `bytes` and `bits`.
+/// * Each `condition` is a comparison and a color, like `>=80:red` or `<10:#3080ff`, checked in
+///   order against the raw value. The first one that matches sets the color of the text.
+///
+/// Use `{{` and `}}` for literal braces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut segments = vec![];
+        let mut literal = String::new();
+        let mut chars = source.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => placeholder.push(c),
+                            None => return Err(invalid(source, "unclosed placeholder")),
+                        }
+                    }
+
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Placeholder(parse_placeholder(
+                        source,
+                        &placeholder,
+                    )?));
+                }
+                '}' => return Err(invalid(source, "unmatched '}'")),
+                c => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Self { segments })
+    }
+
+    pub fn render(&self, snapshot: &MetricSnapshot) -> RenderedText {
+        let mut text = String::new();
+        let mut color = None;
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => text.push_str(literal),
+                Segment::Placeholder(placeholder) => {
+                    let value = snapshot.get(&placeholder.metric);
+                    text.push_str(&placeholder.format(value));
+                    color = color.or_else(|| placeholder.color(value));
+                }
+            }
+        }
+
+        RenderedText { text, color }
+    }
+}
+
+impl FromStr for Template {
+    type Err = ChipsError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+fn invalid(source: &str, reason: &str) -> ChipsError {
+    ChipsError::InvalidTemplate(format!("{} in {:?}", reason, source))
+}
+
+fn parse_placeholder(source: &str, placeholder: &str) -> Result<Placeholder> {
+    let mut parts = placeholder.split('|');
+    let head = parts.next().unwrap_or_default();
+    let (metric, spec) = head.split_once(':').unwrap_or((head, ""));
+    let (metric, spec) = (metric.trim(), spec.trim());
+    if metric.is_empty() {
+        return Err(invalid(source, "empty placeholder"));
+    }
+
+    let (conversion, precision) = match spec.find('.') {
+        Some(idx) => (&spec[..idx], Some(&spec[idx + 1..])),
+        None => (spec, None),
+    };
+    let conversion = match conversion {
+        "" => Conversion::None,
+        "%" => Conversion::Percent,
+        "bytes" => Conversion::Bytes,
+        "bits" => Conversion::Bits,
+        unit => match BYTE_UNITS[1..].iter().position(|name| *name == unit) {
+            Some(idx) => Conversion::ByteUnit(idx as i32 + 1),
+            None => return Err(invalid(source, &format!("unknown conversion {:?}", unit))),
+        },
+    };
+    let precision = precision
+        .map(|precision| {
+            precision
+                .parse::<usize>()
+                .map_err(|_| invalid(source, &format!("invalid precision {:?}", precision)))
+        })
+        .transpose()?;
+
+    let conditions = parts
+        .map(|condition| parse_condition(source, condition.trim()))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Placeholder {
+        metric: metric.to_string(),
+        conversion,
+        precision,
+        conditions,
+    })
+}
+
+fn parse_condition(source: &str, condition: &str) -> Result<ColorCondition> {
+    let (comparison, rest) = [
+        (">=", Comparison::GreaterOrEqual),
+        ("<=", Comparison::LessOrEqual),
+        ("==", Comparison::Equal),
+        ("!=", Comparison::NotEqual),
+        (">", Comparison::Greater),
+        ("<", Comparison::Less),
+    ]
+    .into_iter()
+    .find_map(|(operator, comparison)| {
+        condition
+            .strip_prefix(operator)
+            .map(|rest| (comparison, rest))
+    })
+    .ok_or_else(|| invalid(source, &format!("invalid condition {:?}", condition)))?;
+
+    let (threshold, color) = rest
+        .split_once(':')
+        .ok_or_else(|| invalid(source, &format!("condition {:?} has no color", condition)))?;
+    let threshold = threshold
+        .trim()
+        .parse::<f64>()
+        .map_err(|_| invalid(source, &format!("invalid threshold {:?}", threshold)))?;
+    let color = color.trim().parse::<Color>()?;
+
+    Ok(ColorCondition {
+        comparison,
+        threshold,
+        color,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GIB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+    fn render(template: &str, metrics: &[(&str, MetricValue)]) -> RenderedText {
+        let mut snapshot = MetricSnapshot::new();
+        for (name, value) in metrics {
+            snapshot.set(*name, value.clone());
+        }
+        Template::parse(template).unwrap().render(&snapshot)
+    }
+
+    fn text(template: &str, metrics: &[(&str, MetricValue)]) -> String {
+        render(template, metrics).text
+    }
+
+    #[test]
+    fn escapes_braces() {
+        assert_eq!(
+            text("{{cpu}} {{{cpu}}}", &[("cpu", 5.0.into())]),
+            "{cpu} {5}"
+        );
+        assert_eq!(text("}}", &[]), "}");
+    }
+
+    #[test]
+    fn converts_values() {
+        let usage = [("cpu.usage", MetricValue::Number(0.4567))];
+        assert_eq!(text("{cpu.usage:%}%", &usage), "46%");
+        assert_eq!(text("{cpu.usage:%.1}%", &usage), "45.7%");
+
+        let used = [("mem.used", MetricValue::Number(12.34 * GIB))];
+        assert_eq!(text("{mem.used:bytes}", &used), "12.3 GiB");
+        assert_eq!(text("{mem.used:GiB.1} GiB", &used), "12.3 GiB");
+        assert_eq!(text("{mem.used:MiB}", &used), "12636");
+        assert_eq!(text("{rx:bytes}", &[("rx", 512.0.into())]), "512.0 B");
+
+        // 1.5 MB/s is 12 Mb/s
+        assert_eq!(
+            text("{rx:bits}/s", &[("rx", 1_500_000.0.into())]),
+            "12.0 Mb/s"
+        );
+        assert_eq!(text("{rx:bits.0}/s", &[("rx", 100.0.into())]), "800 b/s");
+    }
+
+    #[test]
+    fn defaults_precision() {
+        // Integers for plain numbers, one decimal where a unit is picked
+        assert_eq!(text("{temp}", &[("temp", 41.6.into())]), "42");
+        assert_eq!(text("{temp:.2}", &[("temp", 41.6.into())]), "41.60");
+        assert_eq!(text("{disk:bytes}", &[("disk", 2048.0.into())]), "2.0 KiB");
+    }
+
+    #[test]
+    fn shows_text_and_missing_values() {
+        assert_eq!(
+            text(
+                "{host} {cpu.temperature:.1}°",
+                &[("host", MetricValue::Text("desk".to_string()))]
+            ),
+            "desk --°"
+        );
+    }
+
+    #[test]
+    fn picks_first_matching_color() {
+        let template = "{cpu:.0|>=95:red|>=80:orange} {gpu|<10:#3080ff}";
+        let color = |cpu: f64| render(template, &[("cpu", cpu.into()), ("gpu", 50.0.into())]).color;
+        assert_eq!(color(97.0), Some(Color::new(255, 0, 0)));
+        assert_eq!(color(80.0), Some(Color::new(255, 165, 0)));
+        assert_eq!(color(79.9), None);
+
+        // Conditions compare the raw value, and a missing metric never matches
+        let rendered = render("{cpu:%|>0.5:red}", &[("cpu", 0.6.into())]);
+        assert_eq!(rendered.color, Some(Color::new(255, 0, 0)));
+        assert_eq!(render("{cpu|>=80:red}", &[]).color, None);
+
+        // The first placeholder with a match wins
+        let rendered = render(
+            "{cpu|>=80:red} {gpu|<10:#3080ff}",
+            &[("cpu", 50.0.into()), ("gpu", 5.0.into())],
+        );
+        assert_eq!(rendered.color, Some(Color::new(0x30, 0x80, 0xff)));
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        for template in [
+            "{cpu",
+            "cpu}",
+            "{}",
+            "{ :.1}",
+            "{cpu:furlongs}",
+            "{cpu:.x}",
+            "{cpu|80:red}",
+            "{cpu|>=80}",
+            "{cpu|>=lots:red}",
+        ] {
+            assert!(
+                matches!(
+                    Template::parse(template),
+                    Err(ChipsError::InvalidTemplate(_))
+                ),
+                "{}",
+                template
+            );
+        }
+        assert!(matches!(
+            Template::parse("{cpu|>=80:notacolor}"),
+            Err(ChipsError::InvalidColor(_))
+        ));
+    }
+}
@@ -3,7 +3,8 @@ use fontdue::Font;
 use image::DynamicImage;
 
 use crate::color::Color;
-use crate::device::{ChipsDevice, Point};
+use crate::device::{ChipsDevice, Point, Rect};
+use crate::drawing::Canvas;
 use crate::errors::Result;
 
 pub struct WidgetRenderer<'a> {
@@ -15,20 +16,17 @@ impl<'a> WidgetRenderer<'a> {
         Self { device }
     }
 
+    /// Borrows the device for drawing lines, circles and other primitives.
+    pub fn canvas(&mut self) -> Canvas<'_> {
+        Canvas::new(self.device)
+    }
+
     pub fn render_image(&mut self, image: &DynamicImage, x: i32, y: i32) -> Result<()> {
         self.device.draw_image(image, x, y)
     }
 
-    pub fn render_rectangle(
-        &mut self,
-        x: i32,
-        y: i32,
-        width: i32,
-        height: i32,
-        color: Color,
-    ) -> Result<()> {
-        self.device
-            .draw_rectangle(x, y, x + width, y + height, color)
+    pub fn render_rgb565(&mut self, rect: Rect, pixels: &[u16]) -> Result<()> {
+        self.device.draw_rgb565(rect, pixels)
     }
 
     pub fn render_bar_graph(
@@ -0,0 +1,317 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::{AnimationDecoder, DynamicImage, Frame, ImageFormat, ImageReader, Rgb, RgbImage};
+
+use crate::color::Color;
+use crate::device::Rect;
+use crate::dither::{image_to_rgb565, DitherMode};
+use crate::errors::Result;
+use crate::image_fit::{fit_image, FitMode, ResampleFilter};
+use crate::widget_renderer::WidgetRenderer;
+use crate::widgets::Widget;
+
+// Browsers play frames with delays this short at the default speed, since many files rely on it
+const MAX_CLAMPED_DELAY: Duration = Duration::from_millis(10);
+const DEFAULT_FRAME_DELAY: Duration = Duration::from_millis(100);
+
+struct AnimationFrame {
+    pixels: Vec<u16>,
+    delay: Duration,
+}
+
+/// Plays a GIF or APNG inside a rectangle. Frames are decoded, fitted and converted to RGB565
+/// once when the animation is opened, and only the area that differs from the frame currently on
+/// screen is uploaded when advancing.
+pub struct Animation {
+    rect: Rect,
+    frames: Vec<AnimationFrame>,
+    current: usize,
+    current_started: Option<Instant>,
+    drawn: Option<usize>,
+}
+
+impl Animation {
+    pub fn open(
+        path: impl AsRef<Path>,
+        rect: Rect,
+        fit: FitMode,
+        filter: ResampleFilter,
+        background: Color,
+    ) -> Result<Self> {
+        let frames = decode_frames(path.as_ref())?
+            .into_iter()
+            .map(|frame| {
+                let delay = frame_delay(Duration::from(frame.delay()));
+
+                let image = DynamicImage::ImageRgba8(frame.into_buffer());
+                let canvas = fit_to_canvas(&image, rect, fit, filter, background);
+
+                // Ordered dithering keeps unchanged areas identical between frames, which
+                // error diffusion would not
+                AnimationFrame {
+                    pixels: image_to_rgb565(&canvas, DitherMode::Bayer),
+                    delay,
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            rect,
+            frames,
+            current: 0,
+            current_started: None,
+            drawn: None,
+        })
+    }
+
+    /// When the next frame is due, or `None` if the animation is static or hasn't started.
+    pub fn next_frame_at(&self) -> Option<Instant> {
+        if self.frames.len() < 2 {
+            return None;
+        }
+
+        self.current_started
+            .map(|started| started + self.frames[self.current].delay)
+    }
+
+    fn advance(&mut self, now: Instant) {
+        let started = match self.current_started {
+            Some(started) => started,
+            None => {
+                self.current_started = Some(now);
+                return;
+            }
+        };
+
+        if self.frames.len() < 2 {
+            return;
+        }
+
+        // Skip over any frames we were too late to show, keeping the original timeline
+        let mut started = started;
+        while now >= started + self.frames[self.current].delay {
+            started += self.frames[self.current].delay;
+            self.current = (self.current + 1) % self.frames.len();
+        }
+
+        self.current_started = Some(started);
+    }
+
+    /// Finds the bounding box of the pixels that differ between two frames.
+    fn changed_region(&self, from: usize, to: usize) -> Option<Rect> {
+        let width = self.rect.width as usize;
+        let from = &self.frames[from].pixels;
+        let to = &self.frames[to].pixels;
+
+        let (mut left, mut top, mut right, mut bottom) = (usize::MAX, usize::MAX, 0, 0);
+        for (idx, (a, b)) in from.iter().zip(to).enumerate() {
+            if a != b {
+                let (x, y) = (idx % width, idx / width);
+                left = left.min(x);
+                top = top.min(y);
+                right = right.max(x);
+                bottom = bottom.max(y);
+            }
+        }
+
+        if left == usize::MAX {
+            return None;
+        }
+
+        Some(Rect::new(
+            left as i32,
+            top as i32,
+            (right - left + 1) as i32,
+            (bottom - top + 1) as i32,
+        ))
+    }
+}
+
+impl Widget for Animation {
+    fn render(&mut self, renderer: &mut WidgetRenderer) -> Result<()> {
+        if self.frames.is_empty() {
+            return Ok(());
+        }
+
+        self.advance(Instant::now());
+
+        let region = match self.drawn {
+            Some(drawn) if drawn == self.current => return Ok(()),
+            Some(drawn) => self.changed_region(drawn, self.current),
+            None => Some(Rect::new(0, 0, self.rect.width, self.rect.height)),
+        };
+
+        if let Some(region) = region {
+            let width = self.rect.width as usize;
+            let pixels = &self.frames[self.current].pixels;
+            let mut buf = Vec::with_capacity((region.width * region.height) as usize);
+            for y in region.y..region.bottom() {
+                let row_start = y as usize * width + region.x as usize;
+                buf.extend_from_slice(&pixels[row_start..row_start + region.width as usize]);
+            }
+
+            renderer.render_rgb565(
+                Rect::new(
+                    self.rect.x + region.x,
+                    self.rect.y + region.y,
+                    region.width,
+                    region.height,
+                ),
+                &buf,
+            )?;
+        }
+
+        self.drawn = Some(self.current);
+
+        Ok(())
+    }
+
+    fn invalidate(&mut self) {
+        self.drawn = None;
+    }
+
+    fn next_update(&self) -> Option<Instant> {
+        self.next_frame_at()
+    }
+}
+
+/// How long a frame is shown, treating the near-zero delays of many GIFs as the default.
+fn frame_delay(delay: Duration) -> Duration {
+    if delay <= MAX_CLAMPED_DELAY {
+        DEFAULT_FRAME_DELAY
+    } else {
+        delay
+    }
+}
+
+fn decode_frames(path: &Path) -> Result<Vec<Frame>> {
+    let format = ImageFormat::from_path(path)?;
+    let frames = match format {
+        ImageFormat::Gif => {
+            let reader = BufReader::new(File::open(path)?);
+            GifDecoder::new(reader)?.into_frames().collect_frames()?
+        }
+        ImageFormat::Png => {
+            let reader = BufReader::new(File::open(path)?);
+            let decoder = PngDecoder::new(reader)?;
+            if decoder.is_apng()? {
+                decoder.apng()?.into_frames().collect_frames()?
+            } else {
+                vec![]
+            }
+        }
+        _ => vec![],
+    };
+
+    if !frames.is_empty() {
+        return Ok(frames);
+    }
+
+    // Not animated, so treat it as a single frame that never advances
+    let image = ImageReader::open(path)?.decode()?;
+    Ok(vec![Frame::new(image.to_rgba8())])
+}
+
+/// Fits a frame into the target rectangle, flattening transparency onto the background color.
+fn fit_to_canvas(
+    image: &DynamicImage,
+    rect: Rect,
+    fit: FitMode,
+    filter: ResampleFilter,
+    background: Color,
+) -> RgbImage {
+    let width = rect.width.max(0) as u32;
+    let height = rect.height.max(0) as u32;
+    let fitted = fit_image(image, width, height, fit, filter);
+    let fitted_image = fitted.image.to_rgba8();
+
+    RgbImage::from_fn(width, height, |x, y| {
+        let fitted_x = x as i32 - fitted.x;
+        let fitted_y = y as i32 - fitted.y;
+        let in_bounds = fitted_x >= 0
+            && fitted_y >= 0
+            && (fitted_x as u32) < fitted_image.width()
+            && (fitted_y as u32) < fitted_image.height();
+
+        let color = if in_bounds {
+            let pixel = fitted_image.get_pixel(fitted_x as u32, fitted_y as u32);
+            Color::new(pixel.0[0], pixel.0[1], pixel.0[2]).blend(background, pixel.0[3])
+        } else {
+            background
+        };
+
+        Rgb([color.r(), color.g(), color.b()])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn animation(delays: &[u64]) -> Animation {
+        Animation {
+            rect: Rect::new(0, 0, 2, 1),
+            frames: delays
+                .iter()
+                .enumerate()
+                .map(|(idx, delay)| AnimationFrame {
+                    pixels: vec![idx as u16, 0],
+                    delay: Duration::from_millis(*delay),
+                })
+                .collect(),
+            current: 0,
+            current_started: None,
+            drawn: None,
+        }
+    }
+
+    #[test]
+    fn clamps_only_near_zero_delays() {
+        assert_eq!(frame_delay(Duration::ZERO), DEFAULT_FRAME_DELAY);
+        assert_eq!(frame_delay(Duration::from_millis(10)), DEFAULT_FRAME_DELAY);
+        assert_eq!(
+            frame_delay(Duration::from_millis(11)),
+            Duration::from_millis(11)
+        );
+        assert_eq!(
+            frame_delay(Duration::from_millis(16)),
+            Duration::from_millis(16)
+        );
+    }
+
+    #[test]
+    fn keeps_timeline_when_late() {
+        let mut animation = animation(&[100, 50, 200]);
+        let start = Instant::now();
+        assert_eq!(animation.next_frame_at(), None);
+
+        animation.advance(start);
+        assert_eq!(
+            animation.next_frame_at(),
+            Some(start + Duration::from_millis(100))
+        );
+
+        // Both the first and second frames are over, so the third one started at 150ms
+        animation.advance(start + Duration::from_millis(170));
+        assert_eq!(animation.current, 2);
+        assert_eq!(
+            animation.next_frame_at(),
+            Some(start + Duration::from_millis(350))
+        );
+
+        animation.advance(start + Duration::from_millis(360));
+        assert_eq!(animation.current, 0);
+    }
+
+    #[test]
+    fn finds_changed_region() {
+        let animation = animation(&[100, 100]);
+        assert_eq!(animation.changed_region(0, 1), Some(Rect::new(0, 0, 1, 1)));
+        assert_eq!(animation.changed_region(1, 1), None);
+    }
+}
@@ -0,0 +1,415 @@
+use std::time::{Duration, Instant};
+
+use chrono::format::{Fixed, Item, Numeric, StrftimeItems};
+use chrono::{Local, Timelike};
+
+use crate::color::Color;
+use crate::device::Point;
+use crate::errors::{ChipsError, Result};
+use crate::widget_renderer::WidgetRenderer;
+use crate::widgets::text::TextBox;
+use crate::widgets::Widget;
+
+const DATE_FORMAT: &str = "%a %-d %b %Y";
+
+/// When the local time next reaches a whole second, or a whole minute if `seconds` is false.
+fn next_boundary(seconds: bool) -> Instant {
+    let now = Local::now();
+    let period = if seconds { 1 } else { 60 };
+
+    // Leap seconds are reported as nanoseconds past 1s, so those are clamped
+    let into_period = Duration::new(
+        (now.second() % period) as u64,
+        now.nanosecond().min(999_999_999),
+    );
+    Instant::now() + Duration::from_secs(period as u64) - into_period
+}
+
+/// The local time as text, using a strftime-like format such as `%H:%M` (see
+/// [`chrono::format::strftime`]). Also used for dates, which just have a different format.
+pub struct DigitalClock {
+    text: TextBox,
+    format: String,
+    shows_seconds: bool,
+}
+
+impl DigitalClock {
+    pub fn new(text: TextBox, format: &str) -> Result<Self> {
+        let mut shows_seconds = false;
+        for item in StrftimeItems::new(format) {
+            match item {
+                Item::Error => return Err(ChipsError::InvalidTimeFormat(format.to_string())),
+                Item::Numeric(Numeric::Second | Numeric::Timestamp | Numeric::Nanosecond, _)
+                | Item::Fixed(Fixed::RFC2822 | Fixed::RFC3339) => shows_seconds = true,
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            text,
+            format: format.to_string(),
+            shows_seconds,
+        })
+    }
+
+    /// A clock showing the date, e.g. "Sun 18 Oct 2026".
+    pub fn date(text: TextBox) -> Self {
+        // The format is known to be valid
+        Self::new(text, DATE_FORMAT).unwrap()
+    }
+
+    fn update_text(&mut self) {
+        self.text
+            .set_text(Local::now().format(&self.format).to_string());
+    }
+}
+
+impl Widget for DigitalClock {
+    /// Only draws anything once the formatted time differs from what's shown.
+    fn render(&mut self, renderer: &mut WidgetRenderer) -> Result<()> {
+        self.update_text();
+        self.text.render(renderer)
+    }
+
+    fn invalidate(&mut self) {
+        self.text.invalidate();
+    }
+
+    fn set_foreground(&mut self, color: Color) {
+        self.text.set_foreground(color);
+    }
+
+    fn set_background(&mut self, color: Color) {
+        self.text.set_background(color);
+    }
+
+    fn next_update(&self) -> Option<Instant> {
+        Some(next_boundary(self.shows_seconds))
+    }
+}
+
+/// How long the system has been running, e.g. "3d 4h 12m", counted from a known uptime.
+pub struct Uptime {
+    text: TextBox,
+    booted: Instant,
+}
+
+impl Uptime {
+    pub fn new(text: TextBox, uptime: Duration) -> Self {
+        let now = Instant::now();
+        Self {
+            text,
+            booted: now.checked_sub(uptime).unwrap_or(now),
+        }
+    }
+
+    fn uptime(&self) -> Duration {
+        self.booted.elapsed()
+    }
+
+    fn update_text(&mut self) {
+        self.text.set_text(uptime_text(self.uptime()));
+    }
+}
+
+/// Formats an uptime in whole minutes, leaving out days and hours while they're zero.
+fn uptime_text(uptime: Duration) -> String {
+    let minutes = uptime.as_secs() / 60;
+    let (days, hours, minutes) = (minutes / 1440, minutes / 60 % 24, minutes % 60);
+    if days > 0 {
+        format!("{}d {}h {}m", days, hours, minutes)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+impl Widget for Uptime {
+    fn render(&mut self, renderer: &mut WidgetRenderer) -> Result<()> {
+        self.update_text();
+        self.text.render(renderer)
+    }
+
+    fn invalidate(&mut self) {
+        self.text.invalidate();
+    }
+
+    fn set_foreground(&mut self, color: Color) {
+        self.text.set_foreground(color);
+    }
+
+    fn set_background(&mut self, color: Color) {
+        self.text.set_background(color);
+    }
+
+    fn next_update(&self) -> Option<Instant> {
+        let minutes = self.uptime().as_secs() / 60;
+        Some(self.booted + Duration::from_secs((minutes + 1) * 60))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnalogClockStyle {
+    pub radius: i32,
+    pub face: Color,
+    pub rim: Color,
+    pub ticks: Color,
+    pub hour_hand: Color,
+    pub minute_hand: Color,
+    /// The clock only updates once a minute without a second hand.
+    pub second_hand: Option<Color>,
+}
+
+impl Default for AnalogClockStyle {
+    fn default() -> Self {
+        Self {
+            radius: 60,
+            face: Color::new(63, 67, 81),
+            rim: Color::new(228, 207, 154),
+            ticks: Color::new(228, 207, 154),
+            hour_hand: Color::new(228, 207, 154),
+            minute_hand: Color::new(228, 207, 154),
+            second_hand: Some(Color::new(220, 70, 60)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Hand {
+    /// Degrees clockwise from 12 o'clock.
+    angle: f32,
+    /// Fraction of the radius.
+    length: f32,
+    thickness: i32,
+}
+
+/// A clock face drawn with primitives. The face and ticks are drawn once, and after that only
+/// the hands are erased and redrawn.
+pub struct AnalogClock {
+    center: Point,
+    style: AnalogClockStyle,
+    drawn_hands: Option<[Hand; 3]>,
+    drawn_style: Option<AnalogClockStyle>,
+}
+
+impl AnalogClock {
+    pub fn new(center: Point, style: AnalogClockStyle) -> Self {
+        Self {
+            center,
+            style,
+            drawn_hands: None,
+            drawn_style: None,
+        }
+    }
+
+    /// The hour, minute and second hands for the current time.
+    fn hands(&self) -> [Hand; 3] {
+        let now = Local::now();
+        let (hour, minute) = (now.hour() % 12, now.minute());
+        let second = match self.style.second_hand {
+            Some(_) => now.second().min(59),
+            None => 0,
+        };
+
+        [
+            Hand {
+                angle: hour as f32 * 30.0 + minute as f32 * 0.5,
+                length: 0.5,
+                thickness: 5,
+            },
+            Hand {
+                angle: minute as f32 * 6.0,
+                length: 0.72,
+                thickness: 3,
+            },
+            Hand {
+                angle: second as f32 * 6.0,
+                length: 0.8,
+                thickness: 1,
+            },
+        ]
+    }
+
+    fn point_at(&self, angle: f32, distance: f32) -> Point {
+        let angle = angle.to_radians();
+        Point::new(
+            self.center.x() + (angle.sin() * distance).round() as i32,
+            self.center.y() - (angle.cos() * distance).round() as i32,
+        )
+    }
+
+    fn draw_hand(&self, renderer: &mut WidgetRenderer, hand: Hand, color: Color) -> Result<()> {
+        let end = self.point_at(hand.angle, self.style.radius as f32 * hand.length);
+        renderer
+            .canvas()
+            .thick_line(self.center, end, hand.thickness, color)
+    }
+
+    fn render_face(&self, renderer: &mut WidgetRenderer) -> Result<()> {
+        let style = self.style;
+        let mut canvas = renderer.canvas();
+        canvas.fill_circle(self.center, style.radius, style.face)?;
+        canvas.circle(self.center, style.radius, style.rim)?;
+        canvas.circle(self.center, style.radius - 1, style.rim)?;
+
+        // Ticks stay outside the reach of the hands, so erasing a hand never touches them
+        let radius = style.radius as f32;
+        for hour in 0..12 {
+            let angle = hour as f32 * 30.0;
+            let (inner, thickness) = if hour % 3 == 0 { (0.82, 3) } else { (0.88, 1) };
+            canvas.thick_line(
+                self.point_at(angle, radius * inner),
+                self.point_at(angle, radius - 4.0),
+                thickness,
+                style.ticks,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Widget for AnalogClock {
+    fn render(&mut self, renderer: &mut WidgetRenderer) -> Result<()> {
+        if self.drawn_style != Some(self.style) {
+            self.drawn_hands = None;
+        }
+
+        let hands = self.hands();
+        match self.drawn_hands {
+            Some(drawn_hands) if drawn_hands == hands => return Ok(()),
+            Some(drawn_hands) => {
+                for (drawn, hand) in drawn_hands.iter().zip(&hands) {
+                    if drawn != hand {
+                        self.draw_hand(renderer, *drawn, self.style.face)?;
+                    }
+                }
+            }
+            None => self.render_face(renderer)?,
+        }
+
+        // Erasing one hand can clip the others, so all of them are drawn again
+        let [hour, minute, second] = hands;
+        self.draw_hand(renderer, hour, self.style.hour_hand)?;
+        self.draw_hand(renderer, minute, self.style.minute_hand)?;
+        if let Some(color) = self.style.second_hand {
+            self.draw_hand(renderer, second, color)?;
+        }
+
+        let cap_color = self.style.second_hand.unwrap_or(self.style.minute_hand);
+        renderer.canvas().fill_circle(self.center, 3, cap_color)?;
+
+        self.drawn_hands = Some(hands);
+        self.drawn_style = Some(self.style);
+
+        Ok(())
+    }
+
+    fn invalidate(&mut self) {
+        self.drawn_hands = None;
+        self.drawn_style = None;
+    }
+
+    fn set_foreground(&mut self, color: Color) {
+        self.style.hour_hand = color;
+        self.style.minute_hand = color;
+    }
+
+    fn set_background(&mut self, color: Color) {
+        self.style.face = color;
+    }
+
+    fn next_update(&self) -> Option<Instant> {
+        Some(next_boundary(self.style.second_hand.is_some()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fontdue::Font;
+
+    use serialport::{SerialPortInfo, SerialPortType};
+
+    use super::*;
+    use crate::device::{ChipsDevice, Rect};
+    use crate::widgets::text::{TextBackground, TextBoxStyle};
+
+    fn text_box() -> TextBox {
+        let font = include_bytes!("../../resources/roboto/Roboto-Regular.ttf") as &[u8];
+        TextBox::new(
+            Rect::new(0, 0, 200, 40),
+            TextBoxStyle::default(),
+            Font::from_bytes(font, fontdue::FontSettings::default()).unwrap(),
+            TextBackground::Color(Color::new(0, 0, 0)),
+        )
+    }
+
+    #[test]
+    fn detects_seconds_in_formats() {
+        let shows_seconds =
+            |format: &str| DigitalClock::new(text_box(), format).unwrap().shows_seconds;
+        assert!(shows_seconds("%H:%M:%S"));
+        assert!(shows_seconds("%T"));
+        assert!(shows_seconds("%s"));
+        assert!(!shows_seconds("%H:%M"));
+        assert!(!shows_seconds(DATE_FORMAT));
+
+        assert!(DigitalClock::new(text_box(), "%H:%Q").is_err());
+        assert!(DigitalClock::new(text_box(), "%").is_err());
+    }
+
+    /// A device that was never connected, which takes commands without sending them anywhere.
+    fn device() -> ChipsDevice {
+        ChipsDevice::new(SerialPortInfo {
+            port_name: String::from("test"),
+            port_type: SerialPortType::Unknown,
+        })
+    }
+
+    #[test]
+    fn redraws_only_when_text_changes() {
+        let mut device = device();
+        let mut renderer = WidgetRenderer::new(&mut device);
+
+        let mut date = DigitalClock::date(text_box());
+        assert!(!date.text.is_current());
+        date.render(&mut renderer).unwrap();
+        date.update_text();
+        assert!(date.text.is_current());
+        date.set_foreground(Color::new(220, 70, 60));
+        assert!(!date.text.is_current());
+
+        let mut uptime = Uptime::new(text_box(), Duration::from_secs(90));
+        uptime.render(&mut renderer).unwrap();
+        uptime.update_text();
+        assert!(uptime.text.is_current());
+    }
+
+    #[test]
+    fn updates_on_boundaries() {
+        let now = Instant::now();
+        let second = next_boundary(true);
+        let minute = next_boundary(false);
+        assert!(second > now && second - now <= Duration::from_secs(1));
+        assert!(minute > now && minute - now <= Duration::from_secs(60));
+    }
+
+    #[test]
+    fn formats_uptime() {
+        assert_eq!(uptime_text(Duration::from_secs(59)), "0m");
+        assert_eq!(uptime_text(Duration::from_secs(61 * 60)), "1h 1m");
+        assert_eq!(
+            uptime_text(Duration::from_secs(3 * 86400 + 4 * 3600 + 12 * 60 + 30)),
+            "3d 4h 12m"
+        );
+        assert_eq!(uptime_text(Duration::from_secs(86400 + 5 * 60)), "1d 0h 5m");
+
+        // The text only changes once a minute, so that's when the next update is due
+        let uptime = Uptime::new(text_box(), Duration::from_secs(90));
+        assert_eq!(
+            uptime.next_update().unwrap() - uptime.booted,
+            Duration::from_secs(120)
+        );
+    }
+}
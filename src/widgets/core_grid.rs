@@ -0,0 +1,201 @@
+use crate::color::Color;
+use crate::device::Rect;
+use crate::errors::Result;
+use crate::widget_renderer::WidgetRenderer;
+use crate::widgets::progress_bar::{Orientation, ProgressBar, ProgressBarStyle};
+use crate::widgets::Widget;
+
+#[derive(Debug, Clone)]
+pub struct CoreGridStyle {
+    /// Bars per row, or enough to make the grid roughly square when `None`.
+    pub columns: Option<u32>,
+    pub gap: i32,
+    /// Used for every bar. The gaps between bars are filled with its background color.
+    pub bar: ProgressBarStyle,
+}
+
+impl Default for CoreGridStyle {
+    fn default() -> Self {
+        Self {
+            columns: None,
+            gap: 2,
+            bar: ProgressBarStyle {
+                orientation: Orientation::Vertical,
+                ..ProgressBarStyle::default()
+            },
+        }
+    }
+}
+
+/// A compact grid with one bar per logical processor. The layout is worked out from the number
+/// of values, so the whole grid is redrawn if that changes.
+pub struct CoreGrid {
+    rect: Rect,
+    style: CoreGridStyle,
+    bars: Vec<ProgressBar>,
+    values: Vec<f64>,
+    drawn_background: Option<Color>,
+}
+
+impl CoreGrid {
+    pub fn new(rect: Rect, style: CoreGridStyle) -> Self {
+        Self {
+            rect,
+            style,
+            bars: vec![],
+            values: vec![],
+            drawn_background: None,
+        }
+    }
+
+    /// Sets the displayed ratios, one per core.
+    pub fn set_values(&mut self, values: &[f64]) {
+        self.values = values.to_vec();
+    }
+
+    fn layout(&self, count: usize) -> Vec<Rect> {
+        if count == 0 {
+            return vec![];
+        }
+
+        let columns = match self.style.columns {
+            Some(columns) => columns.max(1) as i32,
+            None => (count as f64).sqrt().ceil() as i32,
+        }
+        .min(count as i32);
+        let rows = (count as i32 + columns - 1) / columns;
+
+        // Spread any leftover pixels evenly, like progress bar segments
+        let gap = self.style.gap;
+        let span = |length: i32, cells: i32, idx: i32| {
+            let total = length + gap;
+            let start = idx * total / cells;
+            let end = (idx + 1) * total / cells - gap;
+            (start, end - start)
+        };
+
+        (0..count as i32)
+            .map(|idx| {
+                let (x, width) = span(self.rect.width, columns, idx % columns);
+                let (y, height) = span(self.rect.height, rows, idx / columns);
+                Rect::new(self.rect.x + x, self.rect.y + y, width, height)
+            })
+            .collect()
+    }
+}
+
+impl Widget for CoreGrid {
+    fn render(&mut self, renderer: &mut WidgetRenderer) -> Result<()> {
+        if self.bars.len() != self.values.len()
+            || self.drawn_background != Some(self.style.bar.background)
+        {
+            renderer
+                .canvas()
+                .fill_rectangle(self.rect, self.style.bar.background)?;
+            self.bars = self
+                .layout(self.values.len())
+                .into_iter()
+                .map(|rect| ProgressBar::new(rect, self.style.bar.clone()))
+                .collect();
+            self.drawn_background = Some(self.style.bar.background);
+        }
+
+        for (bar, value) in self.bars.iter_mut().zip(&self.values) {
+            bar.set_value(*value);
+            bar.render(renderer)?;
+        }
+
+        Ok(())
+    }
+
+    fn invalidate(&mut self) {
+        // Lays out the bars again along with the background
+        self.drawn_background = None;
+    }
+
+    fn set_foreground(&mut self, color: Color) {
+        self.style.bar.fill = color;
+        for bar in &mut self.bars {
+            bar.set_foreground(color);
+        }
+    }
+
+    fn set_background(&mut self, color: Color) {
+        self.style.bar.background = color;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serialport::{SerialPortInfo, SerialPortType};
+
+    use super::*;
+    use crate::device::ChipsDevice;
+
+    fn grid(rect: Rect, columns: Option<u32>) -> CoreGrid {
+        CoreGrid::new(
+            rect,
+            CoreGridStyle {
+                columns,
+                ..CoreGridStyle::default()
+            },
+        )
+    }
+
+    #[test]
+    fn lays_out_rows_and_columns() {
+        // Six cores make a grid of three columns by two rows, and five leave a gap in the last row
+        let rect = Rect::new(10, 20, 100, 50);
+        let six = grid(rect, None).layout(6);
+        assert_eq!(
+            six,
+            vec![
+                Rect::new(10, 20, 32, 24),
+                Rect::new(44, 20, 32, 24),
+                Rect::new(78, 20, 32, 24),
+                Rect::new(10, 46, 32, 24),
+                Rect::new(44, 46, 32, 24),
+                Rect::new(78, 46, 32, 24),
+            ]
+        );
+        assert_eq!(grid(rect, None).layout(5), six[..5].to_vec());
+        assert_eq!(grid(rect, None).layout(0), vec![]);
+
+        // More columns than cores puts them all in one full-height row
+        assert_eq!(
+            grid(rect, Some(16)).layout(2),
+            vec![Rect::new(10, 20, 49, 50), Rect::new(61, 20, 49, 50)]
+        );
+    }
+
+    #[test]
+    fn spreads_leftover_pixels() {
+        let widths: Vec<i32> = grid(Rect::new(0, 0, 101, 10), Some(4))
+            .layout(4)
+            .iter()
+            .map(|rect| rect.width)
+            .collect();
+        assert_eq!(widths, vec![23, 24, 24, 24]);
+
+        let last = grid(Rect::new(0, 0, 101, 10), Some(4)).layout(4)[3];
+        assert_eq!(last.right(), 101);
+    }
+
+    #[test]
+    fn lays_out_again_when_core_count_changes() {
+        let mut device = ChipsDevice::new(SerialPortInfo {
+            port_name: String::from("test"),
+            port_type: SerialPortType::Unknown,
+        });
+        let mut renderer = WidgetRenderer::new(&mut device);
+        let mut grid = grid(Rect::new(0, 0, 100, 50), None);
+
+        grid.set_values(&[0.5; 4]);
+        grid.render(&mut renderer).unwrap();
+        assert_eq!(grid.bars.len(), 4);
+
+        grid.set_values(&[0.5; 6]);
+        grid.render(&mut renderer).unwrap();
+        assert_eq!(grid.bars.len(), 6);
+    }
+}
@@ -0,0 +1,264 @@
+use fontdue::layout::{
+    CoordinateSystem, HorizontalAlign, Layout, LayoutSettings, TextStyle, VerticalAlign,
+};
+use fontdue::Font;
+
+use crate::color::Color;
+use crate::device::{Point, Rect};
+use crate::drawing::Canvas;
+use crate::errors::Result;
+use crate::widget_renderer::WidgetRenderer;
+use crate::widgets::Widget;
+
+#[derive(Debug, Clone, Copy)]
+pub struct GaugeTicks {
+    pub count: u32,
+    pub length: i32,
+    pub color: Color,
+}
+
+/// Appearance of a [`Gauge`]. Angles are in degrees, clockwise from 12 o'clock, and the arc is
+/// filled from `start_angle` towards `end_angle` as the value goes from 0 to 1. An `end_angle`
+/// less than `start_angle` fills counter-clockwise.
+#[derive(Debug, Clone, Copy)]
+pub struct GaugeStyle {
+    pub radius: i32,
+    pub thickness: i32,
+    pub start_angle: f32,
+    pub end_angle: f32,
+    pub foreground: Color,
+    pub track: Color,
+    pub background: Color,
+    pub ticks: Option<GaugeTicks>,
+    pub font_size: f32,
+}
+
+impl Default for GaugeStyle {
+    fn default() -> Self {
+        Self {
+            radius: 60,
+            thickness: 10,
+            start_angle: -135.0,
+            end_angle: 135.0,
+            foreground: Color::new(228, 207, 154),
+            track: Color::new(90, 94, 110),
+            background: Color::new(63, 67, 81),
+            ticks: None,
+            font_size: 28.0,
+        }
+    }
+}
+
+/// A radial gauge for ratios such as CPU or GPU load, with the value printed in the middle.
+pub struct Gauge {
+    center: Point,
+    style: GaugeStyle,
+    font: Font,
+    value: f64,
+    drawn_value: Option<f64>,
+    drawn_label: Option<String>,
+    drawn_foreground: Option<Color>,
+    drawn_background: Option<Color>,
+}
+
+impl Gauge {
+    pub fn new(center: Point, style: GaugeStyle, font: Font) -> Self {
+        Self {
+            center,
+            style,
+            font,
+            value: 0.0,
+            drawn_value: None,
+            drawn_label: None,
+            drawn_foreground: None,
+            drawn_background: None,
+        }
+    }
+
+    /// Sets the displayed ratio, clamped to `0.0..=1.0`.
+    pub fn set_value(&mut self, value: f64) {
+        self.value = value.clamp(0.0, 1.0);
+    }
+
+    fn angle_of(&self, value: f64) -> f32 {
+        let sweep = self.style.end_angle - self.style.start_angle;
+        self.style.start_angle + sweep * value as f32
+    }
+
+    /// The clockwise arc covering the ring between two values, whichever way the gauge fills.
+    fn segment_angles(&self, from: f64, to: f64) -> (f32, f32) {
+        let (from, to) = (self.angle_of(from), self.angle_of(to));
+        (from.min(to), from.max(to))
+    }
+
+    fn draw_segment(&self, canvas: &mut Canvas, from: f64, to: f64, color: Color) -> Result<()> {
+        let (start_angle, end_angle) = self.segment_angles(from, to);
+        canvas.arc(
+            self.center,
+            self.style.radius,
+            self.style.thickness,
+            start_angle,
+            end_angle,
+            color,
+        )
+    }
+
+    fn label_text(&self) -> String {
+        format!("{:.0}%", (self.value * 100.0).ceil())
+    }
+
+    /// The largest square that fits inside the ring, which is where the label goes.
+    fn label_rect(&self) -> Rect {
+        let inner_radius = self.style.radius - self.style.thickness - 1;
+        let half_side = (inner_radius as f32 / std::f32::consts::SQRT_2) as i32;
+        Rect::new(
+            self.center.x() - half_side,
+            self.center.y() - half_side,
+            half_side * 2,
+            half_side * 2,
+        )
+    }
+
+    fn render_full(&mut self, renderer: &mut WidgetRenderer) -> Result<()> {
+        let style = self.style;
+        let mut canvas = renderer.canvas();
+
+        canvas.fill_circle(self.center, style.radius, style.background)?;
+
+        if let Some(ticks) = style.ticks {
+            // Ticks sit just outside the ring, evenly spaced from start to end
+            let divisions = ticks.count.max(2) - 1;
+            for i in 0..ticks.count {
+                let angle = self.angle_of(i as f64 / divisions as f64).to_radians();
+                let point_at = |distance: i32| {
+                    Point::new(
+                        self.center.x() + (angle.sin() * distance as f32).round() as i32,
+                        self.center.y() - (angle.cos() * distance as f32).round() as i32,
+                    )
+                };
+                canvas.line(
+                    point_at(style.radius + 2),
+                    point_at(style.radius + 2 + ticks.length),
+                    ticks.color,
+                )?;
+            }
+        }
+
+        self.draw_segment(&mut canvas, 0.0, self.value, style.foreground)?;
+        self.draw_segment(&mut canvas, self.value, 1.0, style.track)?;
+
+        Ok(())
+    }
+
+    fn render_label(&mut self, renderer: &mut WidgetRenderer, label: &str) -> Result<()> {
+        let rect = self.label_rect();
+        renderer
+            .canvas()
+            .fill_rectangle(rect, self.style.background)?;
+
+        let mut layout = Layout::new(CoordinateSystem::PositiveYDown);
+        layout.reset(&LayoutSettings {
+            x: rect.x as f32,
+            y: rect.y as f32,
+            max_width: Some(rect.width as f32),
+            max_height: Some(rect.height as f32),
+            horizontal_align: HorizontalAlign::Center,
+            vertical_align: VerticalAlign::Middle,
+            ..LayoutSettings::default()
+        });
+
+        let fonts = std::slice::from_ref(&self.font);
+        layout.append(fonts, &TextStyle::new(label, self.style.font_size, 0));
+        renderer.render_text(&layout, fonts, 0, 0, self.style.foreground)
+    }
+}
+
+impl Widget for Gauge {
+    fn render(&mut self, renderer: &mut WidgetRenderer) -> Result<()> {
+        if self.drawn_background != Some(self.style.background) {
+            self.drawn_value = None;
+            self.drawn_label = None;
+        }
+        if self.drawn_foreground != Some(self.style.foreground) {
+            self.drawn_label = None;
+        }
+
+        match self.drawn_value {
+            None => self.render_full(renderer)?,
+            Some(drawn_value) if self.drawn_foreground != Some(self.style.foreground) => {
+                // The filled part changes color, and the track only needs the part that emptied
+                let mut canvas = renderer.canvas();
+                self.draw_segment(&mut canvas, 0.0, self.value, self.style.foreground)?;
+                if self.value < drawn_value {
+                    self.draw_segment(&mut canvas, self.value, drawn_value, self.style.track)?;
+                }
+            }
+            Some(drawn_value) if drawn_value != self.value => {
+                // Only the segment between the old and new value changes color
+                let (from, to, color) = if self.value > drawn_value {
+                    (drawn_value, self.value, self.style.foreground)
+                } else {
+                    (self.value, drawn_value, self.style.track)
+                };
+
+                self.draw_segment(&mut renderer.canvas(), from, to, color)?;
+            }
+            _ => {}
+        }
+        self.drawn_value = Some(self.value);
+        self.drawn_foreground = Some(self.style.foreground);
+        self.drawn_background = Some(self.style.background);
+
+        let label = self.label_text();
+        if self.drawn_label.as_deref() != Some(label.as_str()) {
+            self.render_label(renderer, &label)?;
+            self.drawn_label = Some(label);
+        }
+
+        Ok(())
+    }
+
+    fn invalidate(&mut self) {
+        self.drawn_value = None;
+        self.drawn_label = None;
+    }
+
+    fn set_foreground(&mut self, color: Color) {
+        self.style.foreground = color;
+    }
+
+    fn set_background(&mut self, color: Color) {
+        self.style.background = color;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gauge(start_angle: f32, end_angle: f32) -> Gauge {
+        let font = include_bytes!("../../resources/roboto/Roboto-Regular.ttf") as &[u8];
+        Gauge::new(
+            Point::new(0, 0),
+            GaugeStyle {
+                start_angle,
+                end_angle,
+                ..GaugeStyle::default()
+            },
+            Font::from_bytes(font, fontdue::FontSettings::default()).unwrap(),
+        )
+    }
+
+    #[test]
+    fn segments_run_clockwise_either_way() {
+        let clockwise = gauge(-135.0, 135.0);
+        assert_eq!(clockwise.segment_angles(0.0, 0.5), (-135.0, 0.0));
+        assert_eq!(clockwise.segment_angles(1.0, 0.5), (0.0, 135.0));
+
+        // Filling counter-clockwise, the filled part ends at the start angle
+        let counter_clockwise = gauge(135.0, -135.0);
+        assert_eq!(counter_clockwise.segment_angles(0.0, 0.5), (0.0, 135.0));
+        assert_eq!(counter_clockwise.segment_angles(0.5, 1.0), (-135.0, 0.0));
+        assert_eq!(counter_clockwise.segment_angles(0.25, 0.75), (-67.5, 67.5));
+    }
+}
@@ -0,0 +1,36 @@
+use std::time::Instant;
+
+use crate::color::Color;
+use crate::errors::Result;
+use crate::widget_renderer::WidgetRenderer;
+
+pub mod animation;
+pub mod clock;
+pub mod core_grid;
+pub mod gauge;
+pub mod progress_bar;
+pub mod ruled;
+pub mod text;
+
+/// A stateful element of a dashboard. Widgets remember what they last drew so that each call to
+/// `render` only sends what changed since the previous one.
+pub trait Widget {
+    fn render(&mut self, renderer: &mut WidgetRenderer) -> Result<()>;
+
+    /// Forgets what was drawn, so that the next render draws the whole widget. Needed after
+    /// something else drew over it, like the background.
+    fn invalidate(&mut self);
+
+    /// Changes the widget's main color, such as a gauge's arc or a text box's text. Whatever it
+    /// affects is redrawn on the next render. Widgets without such a color ignore this.
+    fn set_foreground(&mut self, _color: Color) {}
+
+    /// Changes the color behind the widget's content, redrawing the widget on the next render.
+    fn set_background(&mut self, _color: Color) {}
+
+    /// When the widget's content next changes on its own, like a clock ticking over or an
+    /// animation frame coming due. Widgets that only change with new data return `None`.
+    fn next_update(&self) -> Option<Instant> {
+        None
+    }
+}
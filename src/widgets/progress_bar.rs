@@ -0,0 +1,333 @@
+use crate::color::Color;
+use crate::device::Rect;
+use crate::errors::Result;
+use crate::widget_renderer::WidgetRenderer;
+use crate::widgets::Widget;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    /// Fills from left to right.
+    Horizontal,
+    /// Fills from bottom to top.
+    Vertical,
+}
+
+/// Splits the bar into `count` LED-style blocks separated by `gap` pixels. A block is lit once
+/// the value reaches its share of the bar, rounding to the nearest block.
+#[derive(Debug, Clone, Copy)]
+pub struct Segments {
+    pub count: u32,
+    pub gap: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProgressBarStyle {
+    pub orientation: Orientation,
+    pub track: Color,
+    pub fill: Color,
+    /// Shown in the gaps between segments.
+    pub background: Color,
+    /// Fill color overrides as `(threshold, color)` pairs in ascending order. The last stop
+    /// whose threshold the value has reached is used, falling back to `fill` below the first.
+    pub color_stops: Vec<(f64, Color)>,
+    /// Fades between `fill` at 0 and each of the color stops, instead of switching at each
+    /// threshold.
+    pub blend_stops: bool,
+    pub segments: Option<Segments>,
+}
+
+impl Default for ProgressBarStyle {
+    fn default() -> Self {
+        Self {
+            orientation: Orientation::Horizontal,
+            track: Color::new(90, 94, 110),
+            fill: Color::new(228, 207, 154),
+            background: Color::new(63, 67, 81),
+            color_stops: vec![],
+            blend_stops: false,
+            segments: None,
+        }
+    }
+}
+
+/// A bar showing a ratio from 0 to 1. After the first draw, only the part between the old and
+/// new fill level is redrawn, unless the fill color changed with the value.
+pub struct ProgressBar {
+    rect: Rect,
+    style: ProgressBarStyle,
+    value: f64,
+    drawn_extent: Option<i32>,
+    drawn_color: Option<Color>,
+    drawn_background: Option<Color>,
+}
+
+impl ProgressBar {
+    pub fn new(rect: Rect, style: ProgressBarStyle) -> Self {
+        Self {
+            rect,
+            style,
+            value: 0.0,
+            drawn_extent: None,
+            drawn_color: None,
+            drawn_background: None,
+        }
+    }
+
+    /// Sets the displayed ratio, clamped to `0.0..=1.0`.
+    pub fn set_value(&mut self, value: f64) {
+        self.value = value.clamp(0.0, 1.0);
+    }
+
+    fn fill_color(&self) -> Color {
+        let reached = self
+            .style
+            .color_stops
+            .iter()
+            .take_while(|(threshold, _)| self.value >= *threshold)
+            .count();
+        let (from_threshold, from) = match reached {
+            0 => (0.0, self.style.fill),
+            reached => self.style.color_stops[reached - 1],
+        };
+
+        match self.style.color_stops.get(reached) {
+            Some(&(to_threshold, to)) if self.style.blend_stops => {
+                let t = (self.value - from_threshold) / (to_threshold - from_threshold);
+                from.lerp(to, t as f32)
+            }
+            _ => from,
+        }
+    }
+
+    fn length(&self) -> i32 {
+        match self.style.orientation {
+            Orientation::Horizontal => self.rect.width,
+            Orientation::Vertical => self.rect.height,
+        }
+    }
+
+    /// The number of units (pixels, or segments when segmented) in the whole bar.
+    fn units(&self) -> i32 {
+        match self.style.segments {
+            Some(segments) => segments.count as i32,
+            None => self.length(),
+        }
+    }
+
+    fn extent(&self) -> i32 {
+        (self.value * self.units() as f64).round() as i32
+    }
+
+    /// The areas covered by units `from..to`.
+    fn unit_rects(&self, from: i32, to: i32) -> Vec<Rect> {
+        let spans: Vec<(i32, i32)> = match self.style.segments {
+            Some(segments) => {
+                // Spread any leftover pixels evenly instead of piling them onto the last segment
+                let count = segments.count as i32;
+                let total = self.length() + segments.gap;
+                (from..to)
+                    .map(|i| {
+                        let start = i * total / count;
+                        let end = (i + 1) * total / count - segments.gap;
+                        (start, end)
+                    })
+                    .collect()
+            }
+            None if from < to => vec![(from, to)],
+            None => vec![],
+        };
+
+        let rect = self.rect;
+        spans
+            .into_iter()
+            .filter(|(start, end)| start < end)
+            .map(|(start, end)| match self.style.orientation {
+                Orientation::Horizontal => {
+                    Rect::new(rect.x + start, rect.y, end - start, rect.height)
+                }
+                Orientation::Vertical => {
+                    Rect::new(rect.x, rect.bottom() - end, rect.width, end - start)
+                }
+            })
+            .collect()
+    }
+
+    /// The units to fill, and what color, to go from what was last drawn to `extent` filled in
+    /// `color`. Only the units between the old and new level change, unless the color did.
+    fn unit_updates(&self, extent: i32, color: Color) -> Vec<(i32, i32, Color)> {
+        let track = self.style.track;
+        match (self.drawn_extent, self.drawn_color) {
+            (Some(drawn_extent), Some(drawn_color)) if drawn_color == color => {
+                if extent > drawn_extent {
+                    vec![(drawn_extent, extent, color)]
+                } else if extent < drawn_extent {
+                    vec![(extent, drawn_extent, track)]
+                } else {
+                    vec![]
+                }
+            }
+            (Some(drawn_extent), Some(_)) => {
+                // The whole fill changes color, but the track beyond both levels is untouched
+                let mut updates = vec![(0, extent, color)];
+                if extent < drawn_extent {
+                    updates.push((extent, drawn_extent, track));
+                }
+                updates
+            }
+            _ => vec![(0, extent, color), (extent, self.units(), track)],
+        }
+    }
+
+    fn fill_units(
+        &self,
+        renderer: &mut WidgetRenderer,
+        from: i32,
+        to: i32,
+        color: Color,
+    ) -> Result<()> {
+        let mut canvas = renderer.canvas();
+        for rect in self.unit_rects(from, to) {
+            canvas.fill_rectangle(rect, color)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Widget for ProgressBar {
+    fn render(&mut self, renderer: &mut WidgetRenderer) -> Result<()> {
+        let extent = self.extent();
+        let color = self.fill_color();
+        if self.drawn_background != Some(self.style.background) {
+            self.drawn_extent = None;
+        }
+
+        let drawn = self.drawn_extent.is_some() && self.drawn_color.is_some();
+        if !drawn && self.style.segments.is_some() {
+            renderer
+                .canvas()
+                .fill_rectangle(self.rect, self.style.background)?;
+        }
+        for (from, to, color) in self.unit_updates(extent, color) {
+            self.fill_units(renderer, from, to, color)?;
+        }
+
+        self.drawn_extent = Some(extent);
+        self.drawn_color = Some(color);
+        self.drawn_background = Some(self.style.background);
+
+        Ok(())
+    }
+
+    fn invalidate(&mut self) {
+        self.drawn_extent = None;
+        self.drawn_color = None;
+    }
+
+    /// Sets the fill color, which color stops still override above their thresholds.
+    fn set_foreground(&mut self, color: Color) {
+        self.style.fill = color;
+    }
+
+    fn set_background(&mut self, color: Color) {
+        self.style.background = color;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Color = Color::new(220, 70, 60);
+    const ORANGE: Color = Color::new(230, 150, 60);
+
+    fn bar(rect: Rect, style: ProgressBarStyle, value: f64) -> ProgressBar {
+        let mut bar = ProgressBar::new(rect, style);
+        bar.set_value(value);
+        bar
+    }
+
+    #[test]
+    fn updates_between_levels() {
+        let style = ProgressBarStyle::default();
+        let (fill, track) = (style.fill, style.track);
+        let mut bar = bar(Rect::new(0, 0, 100, 10), style, 0.3);
+        assert_eq!(bar.extent(), 30);
+        assert_eq!(
+            bar.unit_updates(30, fill),
+            vec![(0, 30, fill), (30, 100, track)]
+        );
+
+        bar.drawn_extent = Some(30);
+        bar.drawn_color = Some(fill);
+        assert_eq!(bar.unit_updates(50, fill), vec![(30, 50, fill)]);
+        assert_eq!(bar.unit_updates(10, fill), vec![(10, 30, track)]);
+        assert_eq!(bar.unit_updates(30, fill), vec![]);
+
+        // A new color repaints the fill, and clears any of the old fill beyond the new level
+        assert_eq!(bar.unit_updates(50, RED), vec![(0, 50, RED)]);
+        assert_eq!(
+            bar.unit_updates(10, RED),
+            vec![(0, 10, RED), (10, 30, track)]
+        );
+    }
+
+    #[test]
+    fn lays_out_segments() {
+        let style = ProgressBarStyle {
+            segments: Some(Segments { count: 4, gap: 2 }),
+            ..ProgressBarStyle::default()
+        };
+        let horizontal = bar(Rect::new(10, 20, 100, 8), style.clone(), 0.6);
+        assert_eq!(horizontal.extent(), 2);
+
+        // Leftover pixels are spread between the segments, and the last one ends at the edge
+        assert_eq!(
+            horizontal.unit_rects(0, 4),
+            vec![
+                Rect::new(10, 20, 23, 8),
+                Rect::new(35, 20, 24, 8),
+                Rect::new(61, 20, 23, 8),
+                Rect::new(86, 20, 24, 8),
+            ]
+        );
+        assert_eq!(horizontal.unit_rects(1, 2), vec![Rect::new(35, 20, 24, 8)]);
+
+        // Vertical bars fill from the bottom
+        let vertical = bar(
+            Rect::new(0, 0, 8, 100),
+            ProgressBarStyle {
+                orientation: Orientation::Vertical,
+                ..style
+            },
+            0.0,
+        );
+        assert_eq!(vertical.unit_rects(0, 1), vec![Rect::new(0, 77, 8, 23)]);
+        assert_eq!(vertical.unit_rects(2, 2), vec![]);
+    }
+
+    #[test]
+    fn picks_color_stops() {
+        let style = ProgressBarStyle {
+            color_stops: vec![(0.5, ORANGE), (0.8, RED)],
+            ..ProgressBarStyle::default()
+        };
+        let fill = style.fill;
+        let color = |style: &ProgressBarStyle, value: f64| {
+            bar(Rect::new(0, 0, 100, 10), style.clone(), value).fill_color()
+        };
+        assert_eq!(color(&style, 0.3), fill);
+        assert_eq!(color(&style, 0.5), ORANGE);
+        assert_eq!(color(&style, 0.79), ORANGE);
+        assert_eq!(color(&style, 1.0), RED);
+
+        // Blending fades towards the next stop, and holds at the last one
+        let blended = ProgressBarStyle {
+            blend_stops: true,
+            ..style
+        };
+        assert_eq!(color(&blended, 0.25), fill.lerp(ORANGE, 0.5));
+        assert_eq!(color(&blended, 0.65), ORANGE.lerp(RED, 0.5));
+        assert_eq!(color(&blended, 0.9), RED);
+    }
+}
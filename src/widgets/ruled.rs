@@ -0,0 +1,75 @@
+use std::time::Instant;
+
+use crate::color_rules::ColorRule;
+use crate::errors::Result;
+use crate::metrics::MetricSnapshot;
+use crate::widget_renderer::WidgetRenderer;
+use crate::widgets::Widget;
+
+/// Wraps a widget so that its colors follow [`ColorRule`]s on its bound metrics.
+pub struct Ruled<W: Widget> {
+    widget: W,
+    foreground: Option<ColorRule>,
+    background: Option<ColorRule>,
+    /// When a blinking rule next switches color, as of the last update.
+    next_toggle: Option<Instant>,
+}
+
+impl<W: Widget> Ruled<W> {
+    pub fn new(widget: W) -> Self {
+        Self {
+            widget,
+            foreground: None,
+            background: None,
+            next_toggle: None,
+        }
+    }
+
+    pub fn with_foreground(mut self, rule: ColorRule) -> Self {
+        self.foreground = Some(rule);
+        self
+    }
+
+    pub fn with_background(mut self, rule: ColorRule) -> Self {
+        self.background = Some(rule);
+        self
+    }
+
+    pub fn widget_mut(&mut self) -> &mut W {
+        &mut self.widget
+    }
+
+    /// Evaluates the rules against the snapshot, for the next render to pick up. Blinking rules
+    /// need this again at [`Widget::next_update`] to switch color.
+    pub fn update(&mut self, snapshot: &MetricSnapshot, now: Instant) {
+        if let Some(rule) = &mut self.foreground {
+            self.widget.set_foreground(rule.evaluate(snapshot, now));
+        }
+        if let Some(rule) = &mut self.background {
+            self.widget.set_background(rule.evaluate(snapshot, now));
+        }
+        self.next_toggle = [&self.foreground, &self.background]
+            .into_iter()
+            .flatten()
+            .filter_map(|rule| rule.next_toggle(now))
+            .min();
+    }
+}
+
+impl<W: Widget> Widget for Ruled<W> {
+    fn render(&mut self, renderer: &mut WidgetRenderer) -> Result<()> {
+        self.widget.render(renderer)
+    }
+
+    fn invalidate(&mut self) {
+        self.widget.invalidate();
+    }
+
+    fn next_update(&self) -> Option<Instant> {
+        self.widget
+            .next_update()
+            .into_iter()
+            .chain(self.next_toggle)
+            .min()
+    }
+}
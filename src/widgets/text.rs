@@ -0,0 +1,406 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use fontdue::layout::{
+    CoordinateSystem, HorizontalAlign, Layout, LayoutSettings, TextStyle, VerticalAlign,
+};
+use fontdue::Font;
+
+use crate::color::Color;
+use crate::device::{Point, Rect};
+use crate::dither::Rgb565Image;
+use crate::errors::Result;
+use crate::widget_renderer::WidgetRenderer;
+use crate::widgets::Widget;
+
+const ELLIPSIS: &str = "\u{2026}";
+
+/// What's behind a [`TextBox`], used to erase text that is no longer displayed.
+#[derive(Clone)]
+pub enum TextBackground {
+    Color(Color),
+    /// An image in screen coordinates, such as the wallpaper drawn underneath the text. Old text
+    /// is erased with the same pixels the image was drawn with.
+    Image(Arc<Rgb565Image>),
+}
+
+#[derive(Clone, Copy)]
+pub struct TextBoxStyle {
+    pub font_size: f32,
+    pub color: Color,
+    pub horizontal_align: HorizontalAlign,
+    pub vertical_align: VerticalAlign,
+    /// Wraps at word boundaries to fit the width of the box.
+    pub wrap: bool,
+    /// Truncates text that doesn't fit with an ellipsis, instead of clipping it.
+    pub ellipsis: bool,
+}
+
+impl Default for TextBoxStyle {
+    fn default() -> Self {
+        Self {
+            font_size: 24.0,
+            color: Color::new(228, 207, 154),
+            horizontal_align: HorizontalAlign::Left,
+            vertical_align: VerticalAlign::Top,
+            wrap: false,
+            ellipsis: true,
+        }
+    }
+}
+
+/// Text laid out inside a fixed box. When the text changes, pixels from the previous text that
+/// aren't part of the new one are erased, and nothing is drawn outside of the box. A solid
+/// background fills the whole box, while an image background is only restored behind old text.
+pub struct TextBox {
+    rect: Rect,
+    style: TextBoxStyle,
+    font: Font,
+    background: TextBackground,
+    text: String,
+    drawn_text: Option<String>,
+    drawn_color: Option<Color>,
+    drawn_background: Option<Color>,
+    drawn_pixels: HashSet<Point>,
+}
+
+impl TextBox {
+    pub fn new(rect: Rect, style: TextBoxStyle, font: Font, background: TextBackground) -> Self {
+        Self {
+            rect,
+            style,
+            font,
+            background,
+            text: String::new(),
+            drawn_text: None,
+            drawn_color: None,
+            drawn_background: None,
+            drawn_pixels: HashSet::new(),
+        }
+    }
+
+    pub fn set_text(&mut self, text: impl Into<String>) {
+        self.text = text.into();
+    }
+
+    fn layout(&self, text: &str) -> Layout {
+        // Alignment needs a maximum width, but fontdue also wraps to it. Unwrapped text gets a
+        // region wide enough to hold it, so that only hard breaks start new lines.
+        let max_width = if self.style.wrap {
+            self.rect.width as f32
+        } else {
+            (self.rect.width as f32).max(self.measure_width(text) + 1.0)
+        };
+
+        let mut layout = Layout::new(CoordinateSystem::PositiveYDown);
+        layout.reset(&LayoutSettings {
+            x: self.rect.x as f32,
+            y: self.rect.y as f32,
+            max_width: Some(max_width),
+            max_height: Some(self.rect.height as f32),
+            horizontal_align: self.style.horizontal_align,
+            vertical_align: self.style.vertical_align,
+            ..LayoutSettings::default()
+        });
+        layout.append(
+            std::slice::from_ref(&self.font),
+            &TextStyle::new(text, self.style.font_size, 0),
+        );
+
+        layout
+    }
+
+    /// The width of the longest line, measured the same way fontdue decides where to wrap.
+    fn measure_width(&self, text: &str) -> f32 {
+        text.lines()
+            .map(|line| {
+                line.chars()
+                    .map(|c| {
+                        let metrics = self.font.metrics(c, self.style.font_size);
+                        metrics.advance_width.ceil()
+                    })
+                    .sum::<f32>()
+            })
+            .fold(0.0, f32::max)
+    }
+
+    fn fits(&self, text: &str) -> bool {
+        let fits_width = self.style.wrap || self.measure_width(text) <= self.rect.width as f32;
+        fits_width && self.layout(text).height() <= self.rect.height as f32
+    }
+
+    /// Shortens the text with an ellipsis until it fits, if that's enabled.
+    fn fitted_text(&self) -> String {
+        if !self.style.ellipsis || self.fits(&self.text) {
+            return self.text.clone();
+        }
+
+        let boundaries: Vec<usize> = self.text.char_indices().map(|(idx, _)| idx).collect();
+        let truncated = |count: usize| {
+            let end = boundaries.get(count).copied().unwrap_or(self.text.len());
+            format!("{}{}", self.text[..end].trim_end(), ELLIPSIS)
+        };
+
+        // Binary search for the longest prefix that still fits with the ellipsis attached
+        let (mut low, mut high) = (0, boundaries.len());
+        while low < high {
+            let mid = (low + high).div_ceil(2);
+            if self.fits(&truncated(mid)) {
+                low = mid;
+            } else {
+                high = mid - 1;
+            }
+        }
+
+        truncated(low)
+    }
+
+    fn background_color(&self) -> Option<Color> {
+        match &self.background {
+            TextBackground::Color(color) => Some(*color),
+            TextBackground::Image(_) => None,
+        }
+    }
+
+    /// Whether the screen already shows the text in its current colors, so that rendering it
+    /// wouldn't draw anything.
+    pub fn is_current(&self) -> bool {
+        self.drawn_text.as_deref() == Some(self.text.as_str())
+            && self.drawn_color == Some(self.style.color)
+            && (self.background_color().is_none()
+                || self.drawn_background == self.background_color())
+    }
+
+    /// Pixels of the previous text that aren't part of the new text, and need erasing.
+    fn stale_pixels(&self, pixels: &HashSet<Point>) -> Vec<Point> {
+        self.drawn_pixels.difference(pixels).copied().collect()
+    }
+
+    fn rasterize(&self, layout: &Layout) -> HashSet<Point> {
+        let mut pixels = HashSet::new();
+        for glyph in layout.glyphs() {
+            let (metrics, bitmap) = self.font.rasterize(glyph.parent, glyph.key.px);
+            for char_y in 0..metrics.height {
+                for char_x in 0..metrics.width {
+                    if bitmap[char_x + metrics.width * char_y] == 0 {
+                        continue;
+                    }
+
+                    let x = glyph.x as i32 + char_x as i32;
+                    let y = glyph.y as i32 + char_y as i32;
+                    if x >= self.rect.x
+                        && x < self.rect.right()
+                        && y >= self.rect.y
+                        && y < self.rect.bottom()
+                    {
+                        pixels.insert(Point::new(x, y));
+                    }
+                }
+            }
+        }
+
+        pixels
+    }
+}
+
+impl Widget for TextBox {
+    fn render(&mut self, renderer: &mut WidgetRenderer) -> Result<()> {
+        if self.is_current() {
+            return Ok(());
+        }
+
+        let background_color = self.background_color();
+        let repaint = background_color.is_some() && self.drawn_background != background_color;
+        let recolored = self.drawn_color != Some(self.style.color) || repaint;
+
+        let layout = self.layout(&self.fitted_text());
+        let pixels = self.rasterize(&layout);
+        let stale = self.stale_pixels(&pixels);
+
+        let fresh: Vec<Point> = match &self.background {
+            TextBackground::Color(color) => {
+                if repaint {
+                    renderer.canvas().fill_rectangle(self.rect, *color)?;
+                } else {
+                    renderer.canvas().pixels(&stale, *color)?;
+                }
+
+                // Pixels shared with the previous text are already the right color, unless
+                // either color changed
+                if recolored {
+                    pixels.iter().copied().collect()
+                } else {
+                    pixels.difference(&self.drawn_pixels).copied().collect()
+                }
+            }
+            TextBackground::Image(image) => {
+                if let Some((bounds, restored)) =
+                    bounding_box(&stale).and_then(|bounds| image.crop(bounds))
+                {
+                    renderer.render_rgb565(bounds, &restored)?;
+                }
+
+                // Restoring a whole area may have covered pixels that are still part of the text
+                pixels.iter().copied().collect()
+            }
+        };
+
+        renderer.canvas().pixels(&fresh, self.style.color)?;
+
+        self.drawn_pixels = pixels;
+        self.drawn_text = Some(self.text.clone());
+        self.drawn_color = Some(self.style.color);
+        self.drawn_background = background_color;
+
+        Ok(())
+    }
+
+    fn invalidate(&mut self) {
+        self.drawn_text = None;
+        self.drawn_color = None;
+        self.drawn_background = None;
+        self.drawn_pixels.clear();
+    }
+
+    fn set_foreground(&mut self, color: Color) {
+        self.style.color = color;
+    }
+
+    fn set_background(&mut self, color: Color) {
+        self.background = TextBackground::Color(color);
+    }
+}
+
+fn bounding_box(points: &[Point]) -> Option<Rect> {
+    let left = points.iter().map(|point| point.x()).min()?;
+    let top = points.iter().map(|point| point.y()).min()?;
+    let right = points.iter().map(|point| point.x()).max()?;
+    let bottom = points.iter().map(|point| point.y()).max()?;
+    Some(Rect::new(left, top, right - left + 1, bottom - top + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_box(rect: Rect, style: TextBoxStyle, text: &str) -> TextBox {
+        let font = include_bytes!("../../resources/roboto/Roboto-Regular.ttf") as &[u8];
+        let mut text_box = TextBox::new(
+            rect,
+            style,
+            Font::from_bytes(font, fontdue::FontSettings::default()).unwrap(),
+            TextBackground::Color(Color::new(0, 0, 0)),
+        );
+        text_box.set_text(text);
+        text_box
+    }
+
+    fn bounds(text_box: &TextBox, text: &str) -> Rect {
+        let pixels: Vec<Point> = text_box
+            .rasterize(&text_box.layout(text))
+            .into_iter()
+            .collect();
+        bounding_box(&pixels).unwrap()
+    }
+
+    #[test]
+    fn truncates_with_ellipsis() {
+        let text = "The quick brown fox jumps over the lazy dog";
+        let narrow = text_box(Rect::new(0, 0, 120, 40), TextBoxStyle::default(), text);
+        let fitted = narrow.fitted_text();
+        assert!(!narrow.fits(text));
+        assert!(narrow.fits(&fitted));
+
+        // The longest prefix that fits is kept, so one more character would be too much
+        let prefix = fitted.strip_suffix(ELLIPSIS).unwrap();
+        assert!(text.starts_with(prefix));
+        let longer = (prefix.len() + 1..=text.len())
+            .map(|end| text[..end].trim_end())
+            .find(|longer| longer.len() > prefix.len())
+            .unwrap();
+        assert!(!narrow.fits(&format!("{}{}", longer, ELLIPSIS)));
+
+        let short = text_box(Rect::new(0, 0, 120, 40), TextBoxStyle::default(), "fox");
+        assert_eq!(short.fitted_text(), "fox");
+        let clipped = text_box(
+            Rect::new(0, 0, 120, 40),
+            TextBoxStyle {
+                ellipsis: false,
+                ..TextBoxStyle::default()
+            },
+            text,
+        );
+        assert_eq!(clipped.fitted_text(), text);
+    }
+
+    #[test]
+    fn wraps_to_fit() {
+        let style = TextBoxStyle {
+            wrap: true,
+            ..TextBoxStyle::default()
+        };
+        let text = "The quick brown fox";
+        let wrapped = text_box(Rect::new(0, 0, 120, 80), style, text);
+        assert_eq!(wrapped.fitted_text(), text);
+        let lines = wrapped.layout(text).lines().map_or(0, |lines| lines.len());
+        assert!(lines > 1, "{} lines", lines);
+
+        // Wrapped lines that run out of room are truncated too
+        let short = text_box(Rect::new(0, 0, 120, 30), style, text);
+        let fitted = short.fitted_text();
+        assert!(fitted.ends_with(ELLIPSIS));
+        assert_eq!(
+            short.layout(&fitted).lines().map_or(0, |lines| lines.len()),
+            1
+        );
+    }
+
+    #[test]
+    fn aligns_within_box() {
+        let rect = Rect::new(100, 50, 200, 100);
+        let aligned = |horizontal_align, vertical_align| {
+            let style = TextBoxStyle {
+                horizontal_align,
+                vertical_align,
+                ..TextBoxStyle::default()
+            };
+            bounds(&text_box(rect, style, "5%"), "5%")
+        };
+
+        let left = aligned(HorizontalAlign::Left, VerticalAlign::Top);
+        let center = aligned(HorizontalAlign::Center, VerticalAlign::Top);
+        let right = aligned(HorizontalAlign::Right, VerticalAlign::Top);
+        assert!((left.x - rect.x).abs() <= 2, "{:?}", left);
+        assert!((rect.right() - right.right()).abs() <= 2, "{:?}", right);
+        let margins = (center.x - rect.x, rect.right() - center.right());
+        assert!((margins.0 - margins.1).abs() <= 2, "{:?}", margins);
+
+        // Each step down moves the text by the same amount, half the room left in the box
+        let middle = aligned(HorizontalAlign::Left, VerticalAlign::Middle);
+        let bottom = aligned(HorizontalAlign::Left, VerticalAlign::Bottom);
+        assert!(middle.y > left.y);
+        assert!(((middle.y - left.y) - (bottom.y - middle.y)).abs() <= 1);
+        assert!(bottom.bottom() <= rect.bottom());
+    }
+
+    #[test]
+    fn erases_previous_extent() {
+        let style = TextBoxStyle {
+            horizontal_align: HorizontalAlign::Right,
+            ..TextBoxStyle::default()
+        };
+        let mut text_box = text_box(Rect::new(0, 0, 200, 40), style, "5%");
+        let old = text_box.rasterize(&text_box.layout("99%"));
+        let new = text_box.rasterize(&text_box.layout("5%"));
+        text_box.drawn_pixels = old.clone();
+
+        // The erased area reaches back to where the wider text started, and no further
+        let stale = text_box.stale_pixels(&new);
+        assert!(stale.iter().all(|point| !new.contains(point)));
+        let erased = bounding_box(&stale).unwrap();
+        let old_bounds = bounds(&text_box, "99%");
+        assert_eq!(erased.x, old_bounds.x);
+        assert!(erased.right() <= old_bounds.right());
+        assert!(erased.x < bounds(&text_box, "5%").x);
+    }
+}